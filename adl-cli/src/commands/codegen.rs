@@ -1,22 +1,62 @@
-use codegen::{bytecode::serializer::write_bytecode_to_file, gen_bytecode};
+use crate::commands::print_located;
+use codegen::{
+    bytecode::serializer::write_program_to_file, error::CompileError, gen_bytecode_with_line_table,
+    ir, opt::optimize,
+};
 use colored::*;
-use common::util::read_file;
+use std::fs;
 
-pub fn run(input: String, output: Option<String>) {
+/// Prints every error followed by the source line it points at, in the
+/// file of `files` the line is in.
+pub fn print_compile_errors(files: &[String], errors: &[CompileError]) {
+    for e in errors {
+        let (start, _) = e.location();
+        let message = format!("compilation error: {}", e).red();
+        let source = files.get(start.file()).map(String::as_str);
+        print_located(source, Some(start), message);
+    }
+}
+
+pub fn run(input: String, output: Option<String>, optimized: bool, cfg: Option<String>) {
     println!(
         "{}",
         format!("Generating bytecode from: {}", input)
             .green()
             .bold()
     );
-    let source_text = read_file(&input);
-    match parser::parse(&source_text) {
-        Ok(ast) => {
+    match parser::parse_program_with_files(&input) {
+        Ok((ast, files)) => {
             println!("{}", "Code parsed successfully.".green());
-            let bytecode = gen_bytecode(ast);
+            if let Some(cfg) = cfg {
+                match ir::build(&ast) {
+                    Ok(graph) => {
+                        fs::write(&cfg, graph.to_dot()).expect("Failed to write the graph");
+                        println!(
+                            "{}",
+                            format!("Control-flow graph has been saved to: {}", cfg).green()
+                        );
+                    }
+                    Err(errors) => {
+                        print_compile_errors(&files, &errors);
+                        return;
+                    }
+                }
+            }
+            let (mut bytecode, mut line_table) = match gen_bytecode_with_line_table(ast) {
+                Ok(program) => program,
+                Err(errors) => {
+                    print_compile_errors(&files, &errors);
+                    return;
+                }
+            };
+            line_table.set_sources(files);
             println!("{}", "Bytecode generated successfully.".green());
+            if optimized {
+                (bytecode, line_table) = optimize(bytecode, &line_table);
+            }
             if let Some(output) = output {
-                write_bytecode_to_file(&bytecode, &output).expect("Serialization failed");
+                write_program_to_file(&bytecode, Some(&line_table), &output)
+                    .expect("Serialization failed");
                 println!(
                     "{}",
                     format!("Bytecode has been saved to: {}", output).green()
@@ -25,6 +65,6 @@ pub fn run(input: String, output: Option<String>) {
                 println!("{}", format!("{:?}", bytecode).green());
             }
         }
-        Err(e) => eprintln!("{}", format!("Failed to parse code: {:?}", e).red()),
+        Err(e) => eprintln!("{}", format!("Failed to parse code: {}", e).red()),
     }
 }
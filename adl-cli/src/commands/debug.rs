@@ -0,0 +1,387 @@
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+use crate::commands::{codegen::print_compile_errors, print_located};
+use codegen::bytecode::{line_table::LineTable, Bytecode};
+use codegen::gen_bytecode_with_line_table;
+use colored::*;
+use value::Value;
+use vm::{config::VmConfig, new_vm, vm::VM};
+
+const HELP: &str = "\
+step, s                      execute one instruction
+next, n                      execute until the source line changes
+continue, c                  run until a breakpoint or the end of the program
+break, b <label|line>        set a breakpoint
+delete, d <number>           remove a breakpoint
+breakpoints                  list breakpoints
+print, p <name>              print a variable: name -> address -> value
+follow, f <name|address> [n] follow a pointer chain for at most n steps
+stack                        dump the operand stack
+scopes                       dump the scopes, innermost last
+calls                        dump the call stack
+where, w                     show the current instruction and source line
+quit, q                      leave the debugger";
+
+const DEFAULT_FOLLOW_DEPTH: usize = 16;
+
+enum Breakpoint {
+    /// Stops when execution enters the line from another one.
+    Line(usize),
+    /// Stops before any of the instructions; a label stops both where it
+    /// is defined and where calls of its subprogram enter the body.
+    Label(String, Vec<usize>),
+}
+
+struct Debugger {
+    vm: VM,
+    line_table: LineTable,
+    source_lines: Vec<String>,
+    breakpoints: Vec<Breakpoint>,
+    failed: bool,
+}
+
+pub fn run(input: String) {
+    println!("{}", format!("Debugging: {}", input).green().bold());
+    let (ast, files) = match parser::parse_program_with_files(&input) {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("{}", format!("Failed to parse code: {}", e).red());
+            return;
+        }
+    };
+    let (bytecode, mut line_table) = match gen_bytecode_with_line_table(ast) {
+        Ok(program) => program,
+        Err(errors) => {
+            print_compile_errors(&files, &errors);
+            return;
+        }
+    };
+    line_table.set_sources(files);
+    let vm = new_vm(bytecode, &VmConfig::default());
+    if let Err(errors) = vm.verify() {
+        for e in errors {
+            let location = line_table.get(e.offset()).map(|(start, _)| start);
+            print_located(
+                location.and_then(|location| line_table.source_of(location)),
+                location,
+                format!("invalid bytecode: {}", e).red(),
+            );
+        }
+        return;
+    }
+    let source_lines = std::fs::read_to_string(&input)
+        .map(|source| source.lines().map(str::to_string).collect())
+        .unwrap_or_default();
+
+    let mut debugger = Debugger {
+        vm,
+        line_table,
+        source_lines,
+        breakpoints: vec![],
+        failed: false,
+    };
+    println!("{}", "Type 'help' for a list of commands.".green());
+    debugger.print_location();
+    debugger.repl();
+}
+
+impl Debugger {
+    fn repl(&mut self) {
+        loop {
+            print!("{}", "(adl-debug) ".blue());
+            io::stdout().flush().unwrap();
+            let mut line = String::new();
+            match io::stdin().read_line(&mut line) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(_) => {
+                    eprintln!("{}", "Error reading input. Please try again.".red());
+                    continue;
+                }
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let Some((command, args)) = words.split_first() else {
+                continue;
+            };
+            match (*command, args) {
+                ("step" | "s", []) => {
+                    if self.step() {
+                        self.print_location();
+                    }
+                }
+                ("next" | "n", []) => self.next_line(),
+                ("continue" | "c", []) => self.resume(),
+                ("break" | "b", [target]) => self.add_breakpoint(target),
+                ("delete" | "d", [number]) => self.delete_breakpoint(number),
+                ("breakpoints", []) => self.print_breakpoints(),
+                ("print" | "p", [name]) => self.print_variable(name),
+                ("follow" | "f", [start]) => self.follow(start, DEFAULT_FOLLOW_DEPTH),
+                ("follow" | "f", [start, depth]) => match depth.parse() {
+                    Ok(depth) => self.follow(start, depth),
+                    Err(_) => eprintln!("{}", format!("Invalid depth '{}'", depth).red()),
+                },
+                ("stack", []) => self.print_stack(),
+                ("scopes", []) => self.print_scopes(),
+                ("calls", []) => self.print_calls(),
+                ("where" | "w", []) => self.print_location(),
+                ("help" | "h", []) => println!("{}", HELP),
+                ("quit" | "q" | "exit", []) => break,
+                _ => eprintln!(
+                    "{}",
+                    format!("Unknown command '{}', type 'help'", line.trim()).red()
+                ),
+            }
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        if self.failed || self.vm.is_finished() {
+            println!("{}", "The program is not running.".yellow());
+            return true;
+        }
+        false
+    }
+
+    /// Executes one instruction, returns whether the program can go on.
+    fn step(&mut self) -> bool {
+        if self.is_done() {
+            return false;
+        }
+        if let Err(e) = self.vm.step() {
+            self.failed = true;
+            let location = self
+                .line_table
+                .get(self.vm.current_pc())
+                .map(|(start, _)| start);
+            print_located(
+                location.and_then(|location| self.line_table.source_of(location)),
+                location,
+                format!("runtime error: {}", e).red(),
+            );
+            return false;
+        }
+        if self.vm.is_finished() {
+            println!("{}", "Program finished.".green());
+            return false;
+        }
+        true
+    }
+
+    fn row(&self, offset: usize) -> Option<usize> {
+        self.line_table.get(offset).map(|(start, _)| start.row())
+    }
+
+    fn next_line(&mut self) {
+        let row = self.row(self.vm.pc());
+        while self.step() {
+            if self.row(self.vm.pc()) != row {
+                self.print_location();
+                return;
+            }
+        }
+    }
+
+    fn resume(&mut self) {
+        let mut row = self.row(self.vm.pc());
+        while self.step() {
+            let pc = self.vm.pc();
+            let next_row = self.row(pc);
+            let hit = self
+                .breakpoints
+                .iter()
+                .position(|breakpoint| match breakpoint {
+                    Breakpoint::Line(line) => next_row == Some(*line) && row != next_row,
+                    Breakpoint::Label(_, offsets) => offsets.contains(&pc),
+                });
+            if let Some(index) = hit {
+                println!("{}", format!("Breakpoint {} hit", index + 1).yellow());
+                self.print_location();
+                return;
+            }
+            row = next_row;
+        }
+    }
+
+    fn add_breakpoint(&mut self, target: &str) {
+        let breakpoint = if let Ok(line) = target.parse::<usize>() {
+            if !self.line_table.spans().iter().any(|(s, _)| s.row() == line) {
+                eprintln!("{}", format!("No code on line {}", line).red());
+                return;
+            }
+            Breakpoint::Line(line)
+        } else {
+            let bytecode = self.vm.bytecode();
+            let Some(label) = bytecode
+                .iter()
+                .position(|i| matches!(i, Bytecode::Label(name) if name == target))
+            else {
+                eprintln!("{}", format!("No label '{}'", target).red());
+                return;
+            };
+            let mut offsets = vec![label];
+            for instruction in bytecode {
+                if let Bytecode::CallSubProgram(entry, _) = instruction {
+                    let enclosing_label = bytecode[..=*entry]
+                        .iter()
+                        .rposition(|i| matches!(i, Bytecode::Label(_)));
+                    if enclosing_label == Some(label) && !offsets.contains(entry) {
+                        offsets.push(*entry);
+                    }
+                }
+            }
+            Breakpoint::Label(target.to_string(), offsets)
+        };
+        self.breakpoints.push(breakpoint);
+        println!(
+            "{}",
+            format!("Breakpoint {} set", self.breakpoints.len()).green()
+        );
+    }
+
+    fn delete_breakpoint(&mut self, number: &str) {
+        match number.parse::<usize>() {
+            Ok(n) if (1..=self.breakpoints.len()).contains(&n) => {
+                self.breakpoints.remove(n - 1);
+            }
+            _ => eprintln!("{}", format!("No breakpoint {}", number).red()),
+        }
+    }
+
+    fn print_breakpoints(&self) {
+        if self.breakpoints.is_empty() {
+            println!("No breakpoints.");
+        }
+        for (i, breakpoint) in self.breakpoints.iter().enumerate() {
+            match breakpoint {
+                Breakpoint::Line(line) => println!("{:<3} line {}", i + 1, line),
+                Breakpoint::Label(name, offsets) => {
+                    println!("{:<3} label {} (instructions {:?})", i + 1, name, offsets)
+                }
+            }
+        }
+    }
+
+    fn print_location(&self) {
+        if self.vm.is_finished() {
+            println!("{}", "The program is not running.".yellow());
+            return;
+        }
+        let pc = self.vm.pc();
+        let instruction = &self.vm.bytecode()[pc];
+        match instruction {
+            Bytecode::LoadSlot(slot) | Bytecode::StoreSlot(slot) | Bytecode::BindSlot(slot) => {
+                let name = self.vm.variable_names().get(*slot);
+                println!("{:<5} {:?} ({})", pc, instruction, name.map_or("?", |n| n))
+            }
+            _ => println!("{:<5} {:?}", pc, instruction),
+        }
+        if let Some(row) = self.row(pc) {
+            let line = self.source_lines.get(row.wrapping_sub(1));
+            println!("{:>5} | {}", row, line.map_or("", |line| line.as_str()));
+        }
+    }
+
+    fn lookup(&self, address: i64) -> String {
+        match self.vm.heap().lookup_address(address) {
+            Ok(value) => describe(&value),
+            Err(_) => "<unallocated>".to_string(),
+        }
+    }
+
+    /// Variables are looked up in the current scope, like the VM does.
+    fn address_of(&self, name: &str) -> Option<i64> {
+        let scope = self.vm.scopes().last()?;
+        scope.get_var(name).ok()
+    }
+
+    fn print_variable(&self, name: &str) {
+        match self.address_of(name) {
+            Some(address) => println!("{} -> {} -> {}", name, address, self.lookup(address)),
+            None => eprintln!(
+                "{}",
+                format!("'{}' is not bound in the current scope", name).red()
+            ),
+        }
+    }
+
+    fn follow(&self, start: &str, depth: usize) {
+        let (mut chain, mut address) = match start.parse::<i64>() {
+            Ok(address) => (vec![], address),
+            Err(_) => match self.address_of(start) {
+                Some(address) => (vec![start.to_string()], address),
+                None => {
+                    eprintln!(
+                        "{}",
+                        format!("'{}' is not bound in the current scope", start).red()
+                    );
+                    return;
+                }
+            },
+        };
+        let mut seen = HashSet::new();
+        chain.push(address.to_string());
+        for _ in 0..depth {
+            if !seen.insert(address) {
+                chain.push("(cycle)".to_string());
+                break;
+            }
+            match self.vm.heap().lookup_address(address) {
+                Ok(Value::Int(next)) => {
+                    chain.push(next.to_string());
+                    address = next;
+                }
+                Ok(value) => {
+                    chain.push(describe(&value));
+                    break;
+                }
+                Err(_) => {
+                    chain.push("<unallocated>".to_string());
+                    break;
+                }
+            }
+        }
+        println!("{}", chain.join(" -> "));
+    }
+
+    fn print_stack(&self) {
+        if self.vm.stack().is_empty() {
+            println!("The stack is empty.");
+        }
+        for (i, value) in self.vm.stack().iter().enumerate().rev() {
+            println!("{:<5} {}", i, describe(value));
+        }
+    }
+
+    fn print_scopes(&self) {
+        for (depth, scope) in self.vm.scopes().iter().enumerate() {
+            println!("scope {}:", depth);
+            for (name, address) in scope.variables() {
+                println!("  {} -> {} -> {}", name, address, self.lookup(address));
+            }
+        }
+    }
+
+    fn print_calls(&self) {
+        if self.vm.call_stack().is_empty() {
+            println!("No active subprogram calls.");
+        }
+        for (depth, return_address) in self.vm.call_stack().iter().enumerate().rev() {
+            match self.row(*return_address) {
+                Some(row) => println!(
+                    "#{} returns to instruction {} (line {})",
+                    depth, return_address, row
+                ),
+                None => println!("#{} returns to instruction {}", depth, return_address),
+            }
+        }
+    }
+}
+
+/// Shows strings quoted so they can be told apart from other values.
+fn describe(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("{:?}", s),
+        value => value.to_string(),
+    }
+}
@@ -0,0 +1,27 @@
+use colored::*;
+use lexer::notation::Notation;
+use parser::{ast::printer::print_algorithm, parse_by_path};
+
+pub fn run(input: String, notation: Notation, output: Option<String>) {
+    println!(
+        "{}",
+        format!("Formatting {} in {} notation", input, notation)
+            .green()
+            .bold()
+    );
+    match parse_by_path(&input) {
+        Ok(ast) => {
+            let source = print_algorithm(&ast, notation);
+            if let Some(output) = output {
+                std::fs::write(&output, source + "\n").expect("Failed to write output file");
+                println!(
+                    "{}",
+                    format!("Formatted source has been saved to: {}", output).green()
+                );
+            } else {
+                println!("{}", source);
+            }
+        }
+        Err(e) => eprintln!("{}", format!("Failed to parse code: {:?}", e).red()),
+    }
+}
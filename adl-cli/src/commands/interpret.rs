@@ -1,11 +1,9 @@
 use colored::*;
-use common::util::read_file;
-use interpreter::interpret;
+use interpreter::interpret_file;
 
-pub fn run(input: String) {
+pub fn run(input: String, max_call_depth: usize) {
     println!("{}", format!("Interpreting code from: {}", input).green());
-    let source_text = read_file(&input);
-    let result = interpret(source_text);
+    let result = interpret_file(&input, max_call_depth);
     println!(
         "{}",
         format!("Interpretation result: {:?}", result)
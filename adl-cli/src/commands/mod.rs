@@ -1,4 +1,23 @@
+use common::location::Location;
+use std::fmt::Display;
+
 pub mod codegen;
+pub mod debug;
+pub mod fmt;
 pub mod interpret;
 pub mod parse;
 pub mod run;
+
+/// Prints `message` followed by the line of `source_path` that `location`
+/// points at, when both are known.
+pub fn print_located(source_path: Option<&str>, location: Option<Location>, message: impl Display) {
+    let line = source_path.zip(location).and_then(|(path, location)| {
+        let source_text = std::fs::read_to_string(path).ok()?;
+        let line = source_text.lines().nth(location.row().checked_sub(1)?)?;
+        Some((format!("{}\n", line), location))
+    });
+    match line {
+        Some((line, location)) => eprintln!("{}", location.visualize(&line, message)),
+        None => eprintln!("{}", message),
+    }
+}
@@ -1,47 +1,128 @@
-use codegen::{bytecode::serializer::parse_bytecode_instructions, gen_bytecode};
+use crate::commands::{codegen::print_compile_errors, print_located};
+use codegen::bytecode::{line_table::LineTable, Bytecode};
+use codegen::{
+    bytecode::serializer::read_program_from_file, gen_bytecode_with_line_table, opt::optimize,
+};
 use colored::*;
-use common::util::read_file;
-use vm::execute_bytecode;
+use common::{location::Location, snapshot::HeapSnapshot};
+use std::fmt::Display;
+use std::fs;
+use vm::{
+    config::VmConfig,
+    heap::HeapError,
+    new_vm, run_with_line_table,
+    vm::error::{LocatedVMError, VMError},
+};
 
-pub fn run_bytecode(bytecode: String) {
+/// Prints `message` followed by the line `location` points at, in the
+/// source file of the program that line is in.
+fn print_in_source(line_table: &LineTable, location: Option<Location>, message: impl Display) {
+    let source = location.and_then(|location| line_table.source_of(location));
+    print_located(source, location, message);
+}
+
+fn print_runtime_error(line_table: &LineTable, error: &LocatedVMError) {
+    if let VMError::InvalidBytecode(errors) = &error.error {
+        for e in errors {
+            let message = format!("invalid bytecode: {}", e).red();
+            let location = line_table.get(e.offset()).map(|(start, _)| start);
+            print_in_source(line_table, location, message);
+        }
+        return;
+    }
+    let message = format!("runtime error: {}", error).red();
+    print_in_source(line_table, error.span.map(|(start, _)| start), message);
+    if let VMError::HeapEror(HeapError::PartitionLimitExceeded(_)) = error.error {
+        eprintln!(
+            "{}",
+            "hint: raise --heap-size, let the heap grow with --max-heap-size \
+             or free unreachable cells with --gc"
+                .yellow()
+        );
+    }
+}
+
+/// Writes `snapshot` to `path`, as JSON if it ends in `.json` and as
+/// Graphviz DOT otherwise.
+fn dump_heap(snapshot: &HeapSnapshot, path: &str) {
+    let contents = if path.ends_with(".json") {
+        snapshot.to_json()
+    } else {
+        snapshot.to_dot()
+    };
+    match fs::write(path, contents) {
+        Ok(()) => println!("{}", format!("Heap has been saved to: {}", path).green()),
+        Err(e) => eprintln!("{}", format!("Failed to save the heap: {}", e).red()),
+    }
+}
+
+/// Runs `bytecode`, reports what the sanitizer found, if enabled, and,
+/// whether the program succeeds or not, dumps the heap it leaves behind
+/// when asked to.
+fn execute(
+    bytecode: Vec<Bytecode>,
+    line_table: &LineTable,
+    config: &VmConfig,
+    heap_dump: Option<&str>,
+) {
+    let mut vm = new_vm(bytecode, config);
+    match run_with_line_table(&mut vm, line_table) {
+        Ok(_) => println!("{}", format!("Compilation result: ()").green()),
+        Err(e) => print_runtime_error(line_table, &e),
+    }
+    for violation in vm.memory_violations() {
+        let message = format!("sanitizer: {}", violation).yellow();
+        let location = line_table.get(violation.offset()).map(|(start, _)| start);
+        print_in_source(line_table, location, message);
+    }
+    if let Some(path) = heap_dump {
+        dump_heap(&vm.heap_snapshot(), path);
+    }
+}
+
+pub fn run_bytecode(bytecode: String, optimized: bool, config: &VmConfig, heap_dump: Option<&str>) {
     println!(
         "{}",
         format!("Initiating the virtual machine with bytecode: {}", bytecode)
             .green()
             .bold()
     );
-    let source_text = read_file(&bytecode);
-    let bytecode = parse_bytecode_instructions(&source_text);
-    match bytecode {
-        Ok(bytecode) => {
+    match read_program_from_file(&bytecode) {
+        Ok((mut bytecode, mut line_table)) => {
             println!("{}", "Bytecode parsed successfully.".green());
-            match execute_bytecode(bytecode) {
-                Ok(_) => println!("{}", format!("Compilation result: ()").green()),
-                Err(e) => eprintln!("{}", format!("{:?}", e).red()),
+            if optimized {
+                (bytecode, line_table) = optimize(bytecode, &line_table);
             }
+            execute(bytecode, &line_table, config, heap_dump);
         }
-        Err(e) => eprintln!("{}", format!("Failed to parse bytecode: {:?}", e).red()),
+        Err(e) => eprintln!("{}", format!("Failed to parse bytecode: {}", e).red()),
     }
 }
 
-pub fn compile_and_run(input: String) {
+pub fn compile_and_run(input: String, optimized: bool, config: &VmConfig, heap_dump: Option<&str>) {
     println!(
         "{}",
         format!("Compiling and executing code from: {}", input)
             .green()
             .bold()
     );
-    let source_text = read_file(&input);
-    match parser::parse(&source_text) {
-        Ok(ast) => {
+    match parser::parse_program_with_files(&input) {
+        Ok((ast, files)) => {
             println!("{}", "Code parsed successfully.".green());
-            let bytecode = gen_bytecode(ast);
+            let (mut bytecode, mut line_table) = match gen_bytecode_with_line_table(ast) {
+                Ok(program) => program,
+                Err(errors) => {
+                    print_compile_errors(&files, &errors);
+                    return;
+                }
+            };
             println!("{}", "Bytecode generated successfully.".green());
-            match execute_bytecode(bytecode) {
-                Ok(_) => println!("{}", format!("Compilation result: ()").green()),
-                Err(e) => eprintln!("{}", format!("{:?}", e).red()),
+            line_table.set_sources(files);
+            if optimized {
+                (bytecode, line_table) = optimize(bytecode, &line_table);
             }
+            execute(bytecode, &line_table, config, heap_dump);
         }
-        Err(e) => eprintln!("{}", format!("Failed to parse code: {:?}", e).red()),
+        Err(e) => eprintln!("{}", format!("Failed to parse code: {}", e).red()),
     }
 }
@@ -2,8 +2,14 @@ mod commands;
 
 use clap::{Parser, Subcommand};
 use colored::*;
-use commands::{codegen, interpret, parse, run};
+use commands::{codegen, debug, fmt, interpret, parse, run};
+use interpreter::evaluation::DEFAULT_MAX_CALL_DEPTH;
+use lexer::notation::Notation;
 use std::io::{self, Write};
+use vm::{
+    config::{Backend, VmConfig},
+    heap::HeapGrowth,
+};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -23,6 +29,14 @@ enum Commands {
         input: String,
         #[arg(short, long)]
         output: Option<String>,
+
+        /// Optimize the generated bytecode
+        #[arg(short = 'O', long)]
+        optimize: bool,
+
+        /// Save the control-flow graph as a Graphviz file
+        #[arg(long)]
+        cfg: Option<String>,
     },
     Run {
         #[arg(short, long)]
@@ -30,9 +44,62 @@ enum Commands {
 
         #[arg(short, long)]
         file: Option<String>,
+
+        /// Optimize the bytecode before running it
+        #[arg(short = 'O', long)]
+        optimize: bool,
+
+        /// Collect garbage when the heap fills up
+        #[arg(long)]
+        gc: bool,
+
+        /// Number of heap cells
+        #[arg(long, default_value_t = VmConfig::default().heap_size)]
+        heap_size: i64,
+
+        /// Share of the heap cells reserved for lists built by the VM
+        #[arg(long, default_value_t = VmConfig::default().reserved_ratio)]
+        reserved_ratio: f64,
+
+        /// Let the heap double its size when it fills up, up to this many cells
+        #[arg(long)]
+        max_heap_size: Option<i64>,
+
+        /// Report reads and writes of freed or unallocated cells, addresses
+        /// computed past their block, double frees and leaks
+        #[arg(long)]
+        sanitize: bool,
+
+        /// Save the heap the program leaves behind, as JSON if the path ends
+        /// in .json and as Graphviz DOT otherwise
+        #[arg(long)]
+        dump_heap: Option<String>,
+
+        /// Interpreter loop to run the program with: the operand stack or
+        /// registers the bytecode is lowered to
+        #[arg(long, default_value_t = Backend::Stack)]
+        backend: Backend,
+
+        /// Subprogram calls that may be active at once; tail calls do not count
+        #[arg(long, default_value_t = VmConfig::default().max_call_depth)]
+        max_call_depth: usize,
     },
     Interpret {
         input: String,
+
+        /// Subprogram calls that may be active at once
+        #[arg(long, default_value_t = DEFAULT_MAX_CALL_DEPTH)]
+        max_call_depth: usize,
+    },
+    /// Step through a program in the virtual machine
+    Debug { input: String },
+    /// Print a program in the ascii or the original notation
+    Fmt {
+        input: String,
+        #[arg(short, long, default_value = "ascii")]
+        notation: Notation,
+        #[arg(short, long)]
+        output: Option<String>,
     },
 }
 
@@ -71,15 +138,52 @@ fn main() {
 
         match args.cmd {
             Commands::Parse { input, output } => parse::run(input, output),
-            Commands::Codegen { input, output } => codegen::run(input, output),
-            Commands::Run { bytecode, file } => {
-                if let Some(bytecode) = bytecode {
-                    run::run_bytecode(bytecode);
+            Commands::Codegen {
+                input,
+                output,
+                optimize,
+                cfg,
+            } => codegen::run(input, output, optimize, cfg),
+            Commands::Run {
+                bytecode,
+                file,
+                optimize,
+                gc,
+                heap_size,
+                reserved_ratio,
+                max_heap_size,
+                sanitize,
+                dump_heap,
+                backend,
+                max_call_depth,
+            } => {
+                let config = VmConfig {
+                    heap_size,
+                    reserved_ratio,
+                    growth: max_heap_size.map_or(HeapGrowth::Fixed, HeapGrowth::Doubling),
+                    gc,
+                    sanitize,
+                    backend,
+                    max_call_depth,
+                };
+                if let Err(e) = config.validate() {
+                    eprintln!("{}", format!("Invalid settings: {}", e).red());
+                } else if let Some(bytecode) = bytecode {
+                    run::run_bytecode(bytecode, optimize, &config, dump_heap.as_deref());
                 } else if let Some(input) = file {
-                    run::compile_and_run(input);
+                    run::compile_and_run(input, optimize, &config, dump_heap.as_deref());
                 }
             }
-            Commands::Interpret { input } => interpret::run(input),
+            Commands::Interpret {
+                input,
+                max_call_depth,
+            } => interpret::run(input, max_call_depth),
+            Commands::Debug { input } => debug::run(input),
+            Commands::Fmt {
+                input,
+                notation,
+                output,
+            } => fmt::run(input, notation, output),
         }
     }
 }
@@ -107,6 +211,8 @@ fn print_welcome_message() {
                                    codegen    
                                    run        
                                    interpret  
+                                   debug      Step through a program in the virtual machine
+                                   fmt        Print a program in the ascii or the original notation
                                    help       Print this message or the help of the given subcommand(s)
                                  
                                  Options:
@@ -0,0 +1,6 @@
+use std::io;
+
+/// Speaks the Debug Adapter Protocol over stdin and stdout.
+fn main() -> io::Result<()> {
+    adl_dap::server::serve(io::stdin().lock(), io::stdout().lock())
+}
@@ -0,0 +1,692 @@
+use std::cell::RefCell;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use codegen::bytecode::{line_table::LineTable, Bytecode};
+use codegen::gen_bytecode_with_line_table;
+use serde_json::{json, Value as Json};
+use value::Value;
+use vm::{config::VmConfig, new_vm, vm::VM};
+
+use common::protocol::{read_message, write_message};
+
+const THREAD_ID: i64 = 1;
+
+/// Collects what the program prints so it can be sent as `output` events
+/// instead of corrupting the protocol stream on stdout.
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut self.0.borrow_mut())
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Continue,
+    StepIn,
+    StepOver,
+    StepOut,
+}
+
+/// What a `variablesReference` handed to the client points at. Handles are
+/// only valid until the program resumes.
+enum Handle {
+    Scope(usize),
+    Stack,
+    Pointer(i64),
+}
+
+struct Session {
+    vm: VM,
+    line_table: LineTable,
+    program: String,
+}
+
+pub struct Server<W: Write> {
+    writer: W,
+    seq: i64,
+    session: Option<Session>,
+    output: SharedBuffer,
+    breakpoints: Vec<usize>,
+    stop_on_entry: bool,
+    configured: bool,
+    handles: Vec<Handle>,
+    running: bool,
+    disconnected: bool,
+}
+
+/// Serves requests from `reader` until the client disconnects. A request
+/// that is not valid JSON, or comes without a `Content-Length`, is answered
+/// with an error and the next one is read.
+pub fn serve(mut reader: impl BufRead, writer: impl Write) -> io::Result<()> {
+    let mut server = Server::new(writer);
+    while !server.disconnected {
+        match read_message(&mut reader) {
+            Ok(Some(request)) => server.handle(&request)?,
+            Ok(None) => break,
+            Err(error) if error.kind() == io::ErrorKind::InvalidData => {
+                let message = format!("malformed request: {}", error);
+                server.respond(&json!({ "seq": 0, "command": "" }), Err(message))?;
+            }
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(())
+}
+
+impl<W: Write> Server<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            seq: 0,
+            session: None,
+            output: SharedBuffer::default(),
+            breakpoints: vec![],
+            stop_on_entry: false,
+            configured: false,
+            handles: vec![],
+            running: false,
+            disconnected: false,
+        }
+    }
+
+    fn send(&mut self, mut message: Json) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        write_message(&mut self.writer, &message)
+    }
+
+    fn respond(&mut self, request: &Json, result: Result<Json, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)
+    }
+
+    fn event(&mut self, event: &str, body: Json) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+
+    pub fn handle(&mut self, request: &Json) -> io::Result<()> {
+        let arguments = &request["arguments"];
+        match request["command"].as_str().unwrap_or_default() {
+            "initialize" => {
+                let capabilities = json!({ "supportsConfigurationDoneRequest": true });
+                self.respond(request, Ok(capabilities))?;
+                self.event("initialized", json!({}))
+            }
+            "launch" => {
+                let result = self.launch(arguments);
+                let launched = result.is_ok();
+                self.respond(request, result)?;
+                if launched && self.configured {
+                    self.start()?;
+                }
+                Ok(())
+            }
+            "setBreakpoints" => {
+                let result = self.set_breakpoints(arguments);
+                self.respond(request, Ok(result))
+            }
+            "configurationDone" => {
+                self.respond(request, Ok(json!({})))?;
+                self.configured = true;
+                if self.session.is_some() {
+                    self.start()?;
+                }
+                Ok(())
+            }
+            "threads" => self.respond(
+                request,
+                Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })),
+            ),
+            "stackTrace" => {
+                let result = self.stack_trace();
+                self.respond(request, result)
+            }
+            "scopes" => {
+                let result = self.scopes(arguments["frameId"].as_u64().unwrap_or(0) as usize);
+                self.respond(request, result)
+            }
+            "variables" => {
+                let reference = arguments["variablesReference"].as_u64().unwrap_or(0) as usize;
+                let result = self.variables(reference);
+                self.respond(request, result)
+            }
+            command @ ("continue" | "next" | "stepIn" | "stepOut") => {
+                let (mode, body) = match command {
+                    "continue" => (Mode::Continue, json!({ "allThreadsContinued": true })),
+                    "next" => (Mode::StepOver, json!({})),
+                    "stepIn" => (Mode::StepIn, json!({})),
+                    _ => (Mode::StepOut, json!({})),
+                };
+                if !self.running {
+                    return self.respond(request, Err("the program is not running".to_string()));
+                }
+                self.respond(request, Ok(body))?;
+                self.resume(mode)
+            }
+            "disconnect" | "terminate" => {
+                self.respond(request, Ok(json!({})))?;
+                self.disconnected = true;
+                Ok(())
+            }
+            command => {
+                let message = format!("unsupported request '{}'", command);
+                self.respond(request, Err(message))
+            }
+        }
+    }
+
+    fn launch(&mut self, arguments: &Json) -> Result<Json, String> {
+        let program = arguments["program"]
+            .as_str()
+            .ok_or("missing 'program' in launch arguments")?
+            .to_string();
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+        let ast = parser::parse_program(&program).map_err(|e| e.to_string())?;
+        let (bytecode, line_table) = gen_bytecode_with_line_table(ast).map_err(|errors| {
+            let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            messages.join("\n")
+        })?;
+        let mut vm = new_vm(bytecode, &VmConfig::default());
+        if let Err(errors) = vm.verify() {
+            let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            return Err(messages.join("\n"));
+        }
+        vm.set_output(self.output.clone());
+        self.session = Some(Session {
+            vm,
+            line_table,
+            program,
+        });
+        Ok(json!({}))
+    }
+
+    fn set_breakpoints(&mut self, arguments: &Json) -> Json {
+        let lines: Vec<usize> = arguments["breakpoints"]
+            .as_array()
+            .map(|breakpoints| {
+                breakpoints
+                    .iter()
+                    .filter_map(|b| b["line"].as_u64())
+                    .map(|line| line as usize)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let breakpoints: Vec<Json> = lines
+            .iter()
+            .map(|line| {
+                // before launch there is no line table to check against
+                let verified = self.session.as_ref().is_none_or(|session| {
+                    session
+                        .line_table
+                        .spans()
+                        .iter()
+                        .any(|(start, _)| start.row() == *line)
+                });
+                json!({ "verified": verified, "line": line })
+            })
+            .collect();
+        self.breakpoints = lines;
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn start(&mut self) -> io::Result<()> {
+        self.running = true;
+        if self.stop_on_entry {
+            self.stopped("entry")
+        } else {
+            self.resume(Mode::Continue)
+        }
+    }
+
+    fn stopped(&mut self, reason: &str) -> io::Result<()> {
+        self.handles.clear();
+        self.event(
+            "stopped",
+            json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }),
+        )
+    }
+
+    fn flush_output(&mut self) -> io::Result<()> {
+        let output = self.output.take();
+        if output.is_empty() {
+            return Ok(());
+        }
+        let output = String::from_utf8_lossy(&output).into_owned();
+        self.event("output", json!({ "category": "stdout", "output": output }))
+    }
+
+    fn terminate(&mut self, exit_code: i64) -> io::Result<()> {
+        self.running = false;
+        self.event("exited", json!({ "exitCode": exit_code }))?;
+        self.event("terminated", json!({}))
+    }
+
+    /// Runs until `mode` tells to stop, a breakpoint line is entered or the
+    /// program ends.
+    fn resume(&mut self, mode: Mode) -> io::Result<()> {
+        let Some(session) = self.session.as_mut() else {
+            return Ok(());
+        };
+        let depth = session.vm.call_stack().len();
+        let mut row = row(&session.line_table, session.vm.pc());
+        let start_row = row;
+        loop {
+            let Some(session) = self.session.as_mut() else {
+                return Ok(());
+            };
+            if session.vm.is_finished() {
+                self.flush_output()?;
+                return self.terminate(0);
+            }
+            if let Err(e) = session.vm.step() {
+                let pc = session.vm.current_pc();
+                let location = match session.line_table.get(pc) {
+                    Some((start, _)) => format!(" at {}", start),
+                    None => String::new(),
+                };
+                let message = format!("runtime error: {} (instruction {}){}\n", e, pc, location);
+                self.flush_output()?;
+                self.event("output", json!({ "category": "stderr", "output": message }))?;
+                return self.terminate(1);
+            }
+            if session.vm.is_finished() {
+                continue;
+            }
+            let next_row = self::row(&session.line_table, session.vm.pc());
+            let next_depth = session.vm.call_stack().len();
+            let entered_line = next_row.is_some() && next_row != row;
+            let reason = if entered_line && next_row.is_some_and(|r| self.breakpoints.contains(&r))
+            {
+                Some("breakpoint")
+            } else {
+                let done = match mode {
+                    Mode::Continue => false,
+                    Mode::StepIn => entered_line && next_row != start_row || next_depth > depth,
+                    Mode::StepOver => {
+                        next_depth < depth
+                            || next_depth == depth && entered_line && next_row != start_row
+                    }
+                    Mode::StepOut => next_depth < depth,
+                };
+                done.then_some("step")
+            };
+            if next_row.is_some() {
+                row = next_row;
+            }
+            if let Some(reason) = reason {
+                self.flush_output()?;
+                return self.stopped(reason);
+            }
+        }
+    }
+
+    fn session(&self) -> Result<&Session, String> {
+        self.session
+            .as_ref()
+            .filter(|_| self.running)
+            .ok_or_else(|| "the program is not running".to_string())
+    }
+
+    /// Frames innermost first; a caller's frame points at its call.
+    fn frames(session: &Session) -> Vec<(String, usize)> {
+        let vm = &session.vm;
+        let bytecode = vm.bytecode();
+        let mut frames = vec![];
+        let mut pc = vm.pc();
+        for return_address in vm.call_stack().iter().rev() {
+            let call = return_address.saturating_sub(2);
+            let name = match bytecode.get(call) {
+                Some(Bytecode::CallSubProgram(entry, _)) => subprogram_name(bytecode, *entry),
+                _ => None,
+            };
+            frames.push((name.unwrap_or_else(|| "subprogram".to_string()), pc));
+            pc = call;
+        }
+        frames.push(("main".to_string(), pc));
+        frames
+    }
+
+    fn stack_trace(&self) -> Result<Json, String> {
+        let session = self.session()?;
+        let path = Path::new(&session.program);
+        let source = json!({
+            "name": path.file_name().map(|name| name.to_string_lossy()),
+            "path": path.canonicalize().unwrap_or_else(|_| path.to_path_buf()),
+        });
+        let frames: Vec<Json> = Self::frames(session)
+            .into_iter()
+            .enumerate()
+            .map(|(id, (name, pc))| {
+                let (line, column) = match session.line_table.get(pc) {
+                    Some((start, _)) => (start.row(), start.column().max(1)),
+                    None => (0, 0),
+                };
+                json!({
+                    "id": id,
+                    "name": name,
+                    "source": source,
+                    "line": line,
+                    "column": column,
+                })
+            })
+            .collect();
+        Ok(json!({ "stackFrames": frames, "totalFrames": frames.len() }))
+    }
+
+    fn handle_for(&mut self, handle: Handle) -> usize {
+        self.handles.push(handle);
+        self.handles.len()
+    }
+
+    fn scopes(&mut self, frame: usize) -> Result<Json, String> {
+        let scopes = self.session()?.vm.scopes().len();
+        // every call runs in a scope of its own, the innermost frame in the last one
+        let scope = scopes.saturating_sub(1 + frame);
+        let variables = self.handle_for(Handle::Scope(scope));
+        let stack = self.handle_for(Handle::Stack);
+        Ok(json!({ "scopes": [
+            { "name": "Variables", "variablesReference": variables, "expensive": false },
+            { "name": "Operand stack", "variablesReference": stack, "expensive": false },
+        ]}))
+    }
+
+    fn variables(&mut self, reference: usize) -> Result<Json, String> {
+        let session = self.session.as_ref().ok_or("the program is not running")?;
+        let vm = &session.vm;
+        let cells: Vec<(String, Option<i64>, Value)> =
+            match reference.checked_sub(1).and_then(|i| self.handles.get(i)) {
+                Some(Handle::Scope(scope)) => vm
+                    .scopes()
+                    .get(*scope)
+                    .map(|scope| scope.variables())
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(name, address)| (name.to_string(), Some(address), lookup(vm, address)))
+                    .collect(),
+                Some(Handle::Stack) => vm
+                    .stack()
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .map(|(i, value)| (i.to_string(), None, value.clone()))
+                    .collect(),
+                Some(Handle::Pointer(address)) => {
+                    vec![(
+                        format!("'{}", address),
+                        Some(*address),
+                        lookup(vm, *address),
+                    )]
+                }
+                None => return Err(format!("unknown variables reference {}", reference)),
+            };
+        // addresses can be followed further, and so can an integer that is
+        // an allocated address, as the collector assumes (see `ToInt`)
+        let cells: Vec<_> = cells
+            .into_iter()
+            .map(|(name, address, value)| {
+                let pointer = match value {
+                    Value::Address(target) => Some(target),
+                    Value::Int(target) if vm.heap().lookup_address(target).is_ok() => Some(target),
+                    _ => None,
+                };
+                (name, address, value, pointer)
+            })
+            .collect();
+        let mut variables = vec![];
+        for (name, address, value, pointer) in cells {
+            let child = pointer.map_or(0, |target| self.handle_for(Handle::Pointer(target)));
+            let mut variable = json!({
+                "name": name,
+                "value": describe(&value),
+                "variablesReference": child,
+            });
+            if let Some(address) = address {
+                variable["value"] = json!(format!("{} -> {}", address, describe(&value)));
+                variable["memoryReference"] = json!(address.to_string());
+            }
+            variables.push(variable);
+        }
+        Ok(json!({ "variables": variables }))
+    }
+}
+
+fn row(line_table: &LineTable, offset: usize) -> Option<usize> {
+    line_table
+        .get(offset)
+        .map(|(start, _)| start.row())
+        .filter(|row| *row > 0)
+}
+
+/// The label a subprogram entered at `entry` is declared with.
+fn subprogram_name(bytecode: &[Bytecode], entry: usize) -> Option<String> {
+    bytecode[..=entry.min(bytecode.len().checked_sub(1)?)]
+        .iter()
+        .rev()
+        .find_map(|instruction| match instruction {
+            Bytecode::Label(name) => Some(name.clone()),
+            _ => None,
+        })
+}
+
+fn lookup(vm: &VM, address: i64) -> Value {
+    vm.heap().lookup_address(address).unwrap_or(Value::Null)
+}
+
+fn describe(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("{:?}", s),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Client {
+        server: Server<Vec<u8>>,
+        seq: i64,
+    }
+
+    impl Client {
+        fn new() -> Self {
+            Self {
+                server: Server::new(vec![]),
+                seq: 0,
+            }
+        }
+
+        /// Sends a request and returns every message the server wrote.
+        fn request(&mut self, command: &str, arguments: Json) -> Vec<Json> {
+            self.seq += 1;
+            let request = json!({
+                "seq": self.seq,
+                "type": "request",
+                "command": command,
+                "arguments": arguments,
+            });
+            self.server.handle(&request).unwrap();
+            let written = std::mem::take(&mut self.server.writer);
+            let mut reader = written.as_slice();
+            let mut messages = vec![];
+            while let Some(message) = read_message(&mut reader).unwrap() {
+                messages.push(message);
+            }
+            assert_eq!(messages[0]["request_seq"], json!(self.seq));
+            messages
+        }
+
+        fn stopped_reason(messages: &[Json]) -> Option<&str> {
+            messages
+                .iter()
+                .find(|m| m["event"] == "stopped")
+                .and_then(|m| m["body"]["reason"].as_str())
+        }
+
+        fn frames(&mut self) -> Vec<(String, u64)> {
+            let response = &self.request("stackTrace", json!({ "threadId": 1 }))[0];
+            response["body"]["stackFrames"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|f| {
+                    (
+                        f["name"].as_str().unwrap().to_string(),
+                        f["line"].as_u64().unwrap(),
+                    )
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_session() {
+        let program = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../examples/subprogram/square.adl"
+        );
+        let mut client = Client::new();
+
+        let messages = client.request("initialize", json!({ "adapterID": "adl" }));
+        assert_eq!(messages[1]["event"], "initialized");
+        client.request("launch", json!({ "program": program, "stopOnEntry": true }));
+        let messages = client.request(
+            "setBreakpoints",
+            json!({ "source": { "path": program }, "breakpoints": [{ "line": 8 }, { "line": 4 }] }),
+        );
+        let verified: Vec<&Json> = messages[0]["body"]["breakpoints"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|b| &b["verified"])
+            .collect();
+        assert_eq!(verified, vec![&json!(true), &json!(false)]);
+
+        let messages = client.request("configurationDone", json!({}));
+        assert_eq!(Client::stopped_reason(&messages), Some("entry"));
+        assert_eq!(client.frames(), vec![("main".to_string(), 1)]);
+
+        let messages = client.request("continue", json!({ "threadId": 1 }));
+        assert_eq!(Client::stopped_reason(&messages), Some("breakpoint"));
+        assert_eq!(
+            client.frames(),
+            vec![("square".to_string(), 8), ("main".to_string(), 2)]
+        );
+
+        let scopes = &client.request("scopes", json!({ "frameId": 0 }))[0];
+        let reference = scopes["body"]["scopes"][0]["variablesReference"].clone();
+        let variables = &client.request("variables", json!({ "variablesReference": reference }))[0];
+        assert_eq!(variables["body"]["variables"][0]["name"], "n");
+        assert_eq!(variables["body"]["variables"][0]["value"], "0 -> 5");
+
+        let messages = client.request("stepOut", json!({ "threadId": 1 }));
+        assert_eq!(Client::stopped_reason(&messages), Some("step"));
+        assert_eq!(client.frames(), vec![("main".to_string(), 2)]);
+
+        let messages = client.request("next", json!({ "threadId": 1 }));
+        assert_eq!(Client::stopped_reason(&messages), Some("step"));
+        assert_eq!(client.frames(), vec![("main".to_string(), 3)]);
+
+        let messages = client.request("continue", json!({ "threadId": 1 }));
+        let output: Vec<&Json> = messages
+            .iter()
+            .filter(|m| m["event"] == "output")
+            .map(|m| &m["body"]["output"])
+            .collect();
+        assert_eq!(output, vec![&json!("k * k = 25\n")]);
+        assert_eq!(messages.last().unwrap()["event"], "terminated");
+    }
+
+    #[test]
+    fn test_serve_answers_malformed_requests() {
+        let input = concat!(
+            "Content-Length: 8\r\n\r\nnot json",
+            "Content-Length: 32\r\n\r\n{\"seq\":1,\"command\":\"disconnect\"}",
+        );
+        let mut output = vec![];
+        serve(input.as_bytes(), &mut output).unwrap();
+
+        let mut reader = output.as_slice();
+        let rejected = read_message(&mut reader).unwrap().unwrap();
+        assert_eq!(rejected["success"], false);
+        assert!(rejected["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("malformed request: "));
+        let disconnected = read_message(&mut reader).unwrap().unwrap();
+        assert_eq!(disconnected["command"], "disconnect");
+        assert_eq!(disconnected["success"], true);
+    }
+
+    #[test]
+    fn test_variables_follow_addresses() {
+        let program = concat!(env!("CARGO_MANIFEST_DIR"), "/../examples/list/lib.adl");
+        let mut client = Client::new();
+        client.request("initialize", json!({}));
+        client.request("launch", json!({ "program": program, "stopOnEntry": true }));
+        client.request("configurationDone", json!({}));
+        client.request("next", json!({ "threadId": 1 }));
+
+        let scopes = &client.request("scopes", json!({ "frameId": 0 }))[0];
+        let reference = scopes["body"]["scopes"][0]["variablesReference"].clone();
+        let variables = &client.request("variables", json!({ "variablesReference": reference }))[0];
+        // `l` is bound to the first node of its list, which links to the second
+        let l = &variables["body"]["variables"][0];
+        assert_eq!(l["name"], "l");
+        assert_eq!(l["value"], "0 -> 2");
+        let reference = l["variablesReference"].clone();
+        let next = &client.request("variables", json!({ "variablesReference": reference }))[0];
+        assert_eq!(next["body"]["variables"][0]["name"], "'2");
+        assert_eq!(next["body"]["variables"][0]["value"], "2 -> 4");
+    }
+
+    #[test]
+    fn test_step_in_and_over_calls() {
+        let program = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../examples/subprogram/square.adl"
+        );
+        let mut client = Client::new();
+        client.request("initialize", json!({}));
+        client.request("launch", json!({ "program": program, "stopOnEntry": true }));
+        client.request("configurationDone", json!({}));
+
+        client.request("next", json!({ "threadId": 1 }));
+        assert_eq!(client.frames(), vec![("main".to_string(), 2)]);
+        client.request("stepIn", json!({ "threadId": 1 }));
+        assert_eq!(client.frames()[0].0, "square");
+
+        let mut client = Client::new();
+        client.request("initialize", json!({}));
+        client.request("launch", json!({ "program": program, "stopOnEntry": true }));
+        client.request("configurationDone", json!({}));
+        client.request("next", json!({ "threadId": 1 }));
+        client.request("next", json!({ "threadId": 1 }));
+        assert_eq!(client.frames(), vec![("main".to_string(), 3)]);
+    }
+}
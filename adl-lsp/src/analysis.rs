@@ -0,0 +1,405 @@
+use std::collections::{BTreeSet, HashMap};
+
+use common::location::Location;
+use lalrpop_util::ParseError;
+use lexer::errors::LexError;
+use lexer::lexer::{Lexer, Span};
+use lexer::notation::Notation;
+use lexer::token::TokenKind;
+
+/// A label name at the place it is written.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Occurrence {
+    pub name: String,
+    pub start: Location,
+    pub end: Location,
+}
+
+impl Occurrence {
+    fn new(span: &Span) -> Self {
+        let (start, token, _) = span;
+        let name = token.to_string();
+        // the lexer ends a token before a line break on the next line
+        let end = Location::new(start.row(), start.column() + name.chars().count());
+        Occurrence {
+            name,
+            start: *start,
+            end,
+        }
+    }
+
+    /// Whether `location` is inside the name or right after it, where the
+    /// cursor is left after typing it.
+    pub fn contains(&self, location: Location) -> bool {
+        let position = (location.row(), location.column());
+        (self.start.row(), self.start.column()) <= position
+            && position <= (self.end.row(), self.end.column())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub start: Location,
+    pub end: Location,
+    pub message: String,
+}
+
+/// What the language server knows about one source file.
+///
+/// Label definitions and usages are found on the token stream rather than
+/// on the AST, which keeps no locations for label names and is not there
+/// at all while the file does not parse.
+#[derive(Default)]
+pub struct Analysis {
+    pub definitions: Vec<Occurrence>,
+    pub references: Vec<Occurrence>,
+    pub variables: BTreeSet<String>,
+    /// Parameter lists of labels that start a subprogram, either because
+    /// their line binds parameters (`name... null => a; null => b`) or
+    /// because they are called with `SP name {...}`.
+    pub subprograms: HashMap<String, Vec<String>>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Analysis {
+    pub fn new(source: &str) -> Self {
+        let mut analysis = Analysis::default();
+        let mut tokens = vec![];
+        for token in Lexer::with_notation(source, Notation::detect(source)) {
+            match token {
+                Ok(span) => tokens.push(span),
+                Err(e) => {
+                    analysis.diagnostics.push(lex_diagnostic(&e));
+                    break;
+                }
+            }
+        }
+        for line in tokens.split(|(_, token, _)| is_line_end(token)) {
+            analysis.scan_line(line);
+        }
+        analysis.check_references();
+        if analysis.diagnostics.is_empty() {
+            if let Err(e) = parser::parse(source) {
+                analysis.diagnostics.push(parse_diagnostic(source, e));
+            }
+        }
+        analysis
+    }
+
+    fn scan_line(&mut self, line: &[Span]) {
+        let mut statements = line;
+        if let Some(end) = label_declaration(line) {
+            let labels = line[..end].iter().step_by(2);
+            self.definitions.extend(labels.clone().map(Occurrence::new));
+            statements = &line[end + 1..];
+            if let Some(parameters) = parameters(statements) {
+                for label in labels {
+                    self.subprograms
+                        .insert(label.1.to_string(), parameters.clone());
+                }
+            }
+        }
+        if let Some((_, TokenKind::Import, _)) = statements.first() {
+            // `import {a, b} from path as alias`: the labels are defined here
+            let imported = statements
+                .iter()
+                .skip(2)
+                .take_while(|(_, token, _)| *token != TokenKind::RightCurlyBrace);
+            self.definitions.extend(
+                imported
+                    .filter(|(_, token, _)| matches!(token, TokenKind::Identifier(_)))
+                    .map(Occurrence::new),
+            );
+            return;
+        }
+
+        // labels expected after the closing brace of each open brace
+        let mut braces: Vec<usize> = vec![];
+        let mut trailing_labels = 0;
+        let mut pending_labels = 0;
+        let mut i = 0;
+        while i < statements.len() {
+            let span = &statements[i];
+            let next = statements.get(i + 1).map(|(_, token, _)| token);
+            match (&span.1, next) {
+                (TokenKind::At, Some(TokenKind::Identifier(_))) => {
+                    self.references.push(Occurrence::new(&statements[i + 1]));
+                    i += 1;
+                }
+                (TokenKind::SubProgram, Some(TokenKind::Identifier(_))) => {
+                    if let Some(TokenKind::ColonColon) = statements.get(i + 2).map(|s| &s.1) {
+                        // labels of aliased modules are not known here
+                        i += 3;
+                    } else {
+                        let name = Occurrence::new(&statements[i + 1]);
+                        self.subprograms.entry(name.name.clone()).or_default();
+                        self.references.push(name);
+                        i += 1;
+                    }
+                    pending_labels = 1;
+                }
+                (TokenKind::Loop, _) => pending_labels = 2,
+                (TokenKind::LeftCurlyBrace, _) => {
+                    braces.push(pending_labels);
+                    pending_labels = 0;
+                }
+                (TokenKind::RightCurlyBrace, _) => {
+                    trailing_labels = braces.pop().unwrap_or(0);
+                    i += 1;
+                    continue;
+                }
+                (TokenKind::Identifier(_), _) if trailing_labels > 0 => {
+                    self.references.push(Occurrence::new(span));
+                    trailing_labels -= 1;
+                    i += 1;
+                    continue;
+                }
+                // a call of a builtin function
+                (TokenKind::Identifier(_), Some(TokenKind::LeftCurlyBrace)) => {}
+                (TokenKind::Identifier(name), _) => {
+                    self.variables.insert(name.clone());
+                }
+                _ => {}
+            }
+            trailing_labels = 0;
+            i += 1;
+        }
+    }
+
+    fn check_references(&mut self) {
+        for reference in &self.references {
+            if self.definition(&reference.name).is_none() {
+                self.diagnostics.push(Diagnostic {
+                    start: reference.start,
+                    end: reference.end,
+                    message: format!("undefined label '{}'", reference.name),
+                });
+            }
+        }
+    }
+
+    pub fn definition(&self, name: &str) -> Option<&Occurrence> {
+        self.definitions.iter().find(|d| d.name == name)
+    }
+
+    /// The label definition or usage at `location`.
+    pub fn label_at(&self, location: Location) -> Option<&Occurrence> {
+        self.definitions
+            .iter()
+            .chain(&self.references)
+            .find(|occurrence| occurrence.contains(location))
+    }
+
+    /// Every usage of the label `name`, in source order.
+    pub fn references(&self, name: &str, include_definition: bool) -> Vec<&Occurrence> {
+        let definitions = self.definitions.iter().filter(|_| include_definition);
+        let mut references: Vec<&Occurrence> = definitions
+            .chain(&self.references)
+            .filter(|occurrence| occurrence.name == name)
+            .collect();
+        references.sort_by_key(|o| (o.start.row(), o.start.column()));
+        references
+    }
+
+    /// A short description of the label `name`: the call syntax of a
+    /// subprogram, or where a plain label is defined.
+    pub fn describe(&self, name: &str) -> Option<String> {
+        let definition = self.definition(name)?;
+        Some(match self.subprograms.get(name) {
+            Some(parameters) => format!("SP {} {{{}}}", name, parameters.join(", ")),
+            None => format!("label {} (line {})", name, definition.start.row()),
+        })
+    }
+}
+
+fn is_line_end(token: &TokenKind) -> bool {
+    matches!(token, TokenKind::NewLine | TokenKind::EndOfFile)
+}
+
+/// The position of `...` if the line starts with `a, b...`.
+fn label_declaration(line: &[Span]) -> Option<usize> {
+    let end = line
+        .iter()
+        .position(|(_, t, _)| *t == TokenKind::Ellipsis)?;
+    let well_formed = line[..end].iter().enumerate().all(|(i, (_, token, _))| {
+        if i % 2 == 0 {
+            matches!(token, TokenKind::Identifier(_))
+        } else {
+            *token == TokenKind::Comma
+        }
+    });
+    well_formed.then_some(end)
+}
+
+/// The parameters bound by a subprogram header: `null => a; null => b`.
+fn parameters(statements: &[Span]) -> Option<Vec<String>> {
+    if statements.is_empty() {
+        return None;
+    }
+    statements
+        .split(|(_, token, _)| *token == TokenKind::Semicolon)
+        .map(|statement| match statement {
+            [(_, TokenKind::Null, _), (_, TokenKind::Send, _), (_, TokenKind::Identifier(name), _)] => {
+                Some(name.clone())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn lex_diagnostic(error: &LexError) -> Diagnostic {
+    let (location, message) = match error {
+        LexError::Unexpected(location, c) => (location, format!("unexpected character '{}'", c)),
+        LexError::UnterminatedStringLiteral(location) => {
+            (location, "unterminated string literal".to_string())
+        }
+        LexError::FloatFormatError(location, s) => (location, format!("invalid float '{}'", s)),
+        LexError::IntegerFormatError(location, s) => (location, format!("invalid integer '{}'", s)),
+    };
+    let mut end = *location;
+    end.go_right();
+    Diagnostic {
+        start: *location,
+        end,
+        message,
+    }
+}
+
+fn parse_diagnostic(source: &str, error: ParseError<Location, TokenKind, LexError>) -> Diagnostic {
+    let expected = |expected: Vec<String>| match expected.len() {
+        0 => String::new(),
+        _ => format!(", expected one of {}", expected.join(", ")),
+    };
+    let (start, end, message) = match error {
+        ParseError::InvalidToken { location } => (location, location, "invalid token".to_string()),
+        ParseError::UnrecognizedEof {
+            location,
+            expected: e,
+        } => (
+            location,
+            location,
+            format!("unexpected end of file{}", expected(e)),
+        ),
+        ParseError::UnrecognizedToken {
+            token: (start, token, end),
+            expected: e,
+        } => {
+            let (start, end) = token_range(source, start, &token, end);
+            (start, end, format!("unexpected '{}'{}", token, expected(e)))
+        }
+        ParseError::ExtraToken {
+            token: (start, token, end),
+        } => {
+            let (start, end) = token_range(source, start, &token, end);
+            (start, end, format!("extra token '{}'", token))
+        }
+        ParseError::User { error } => return lex_diagnostic(&error),
+    };
+    Diagnostic {
+        start,
+        end,
+        message,
+    }
+}
+
+/// The lexer locates a line break at the start of the next line; it is
+/// moved back to the end of the line it terminates.
+fn token_range(
+    source: &str,
+    start: Location,
+    token: &TokenKind,
+    end: Location,
+) -> (Location, Location) {
+    if *token != TokenKind::NewLine || start.row() < 2 {
+        return (start, end);
+    }
+    let row = start.row() - 1;
+    let length = source.lines().nth(row - 1).map_or(0, |l| l.chars().count());
+    let start = Location::new(row, length + 1);
+    (start, Location::new(row, length + 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "\
+L {1, 1, 'i < 6 => i} a b
+P {'i == 3} @c | SP square {i}
+a ...
+c ... Print {\"Should be here\"}
+b ... !
+square... null => n; null => m
+'n * 'n => n
+return";
+
+    fn names(occurrences: &[Occurrence]) -> Vec<(&str, usize, usize)> {
+        occurrences
+            .iter()
+            .map(|o| (o.name.as_str(), o.start.row(), o.start.column()))
+            .collect()
+    }
+
+    #[test]
+    fn test_labels() {
+        let analysis = Analysis::new(SOURCE);
+        assert_eq!(
+            names(&analysis.definitions),
+            [("a", 3, 1), ("c", 4, 1), ("b", 5, 1), ("square", 6, 1)]
+        );
+        assert_eq!(
+            names(&analysis.references),
+            [("a", 1, 23), ("b", 1, 25), ("c", 2, 14), ("square", 2, 21)]
+        );
+        assert_eq!(
+            analysis.variables,
+            BTreeSet::from(["i".to_string(), "m".to_string(), "n".to_string()])
+        );
+        assert_eq!(analysis.diagnostics, []);
+
+        let label = analysis.label_at(Location::new(2, 23)).unwrap();
+        assert_eq!(label.name, "square");
+        assert_eq!(analysis.definition(&label.name).unwrap().start.row(), 6);
+        assert_eq!(
+            analysis.describe("square").as_deref(),
+            Some("SP square {n, m}")
+        );
+        assert_eq!(analysis.describe("c").as_deref(), Some("label c (line 4)"));
+        assert_eq!(analysis.references("c", true).len(), 2);
+        assert_eq!(analysis.references("c", false).len(), 1);
+    }
+
+    #[test]
+    fn test_imported_labels() {
+        let analysis = Analysis::new("import {len} from lib as l\nSP len {x}\nSP l::get {x, y}");
+        assert_eq!(names(&analysis.definitions), [("len", 1, 9)]);
+        assert_eq!(names(&analysis.references), [("len", 2, 4)]);
+        assert_eq!(analysis.diagnostics, []);
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let analysis = Analysis::new("@nowhere\nx = 1");
+        assert_eq!(
+            analysis.diagnostics,
+            [Diagnostic {
+                start: Location::new(1, 2),
+                end: Location::new(1, 9),
+                message: "undefined label 'nowhere'".to_string(),
+            }]
+        );
+
+        let analysis = Analysis::new("x = (1\n");
+        assert_eq!(analysis.diagnostics.len(), 1);
+        assert_eq!(analysis.diagnostics[0].start, Location::new(1, 7));
+        assert!(analysis.diagnostics[0]
+            .message
+            .starts_with("unexpected '\\n'"));
+
+        let analysis = Analysis::new("x = \"open");
+        assert_eq!(
+            analysis.diagnostics[0].message,
+            "unterminated string literal"
+        );
+    }
+}
@@ -0,0 +1,6 @@
+use std::io;
+
+/// Speaks the Language Server Protocol over stdin and stdout.
+fn main() -> io::Result<()> {
+    adl_lsp::server::serve(io::stdin().lock(), io::stdout().lock())
+}
@@ -0,0 +1,432 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use common::location::Location;
+use common::protocol::{read_message, write_message};
+use serde_json::{json, Value as Json};
+use vm::builtins::STANDARD_BUILTINS;
+
+use crate::analysis::{Analysis, Occurrence};
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+// LSP enumerations
+const TEXT_DOCUMENT_SYNC_FULL: i64 = 1;
+const SEVERITY_ERROR: i64 = 1;
+const COMPLETION_FUNCTION: i64 = 3;
+const COMPLETION_VARIABLE: i64 = 6;
+const COMPLETION_REFERENCE: i64 = 18;
+
+struct Document {
+    text: String,
+    analysis: Analysis,
+}
+
+pub struct Server<W: Write> {
+    writer: W,
+    documents: HashMap<String, Document>,
+    exited: bool,
+}
+
+/// Serves requests from `reader` until the client sends `exit`.
+pub fn serve(mut reader: impl BufRead, writer: impl Write) -> io::Result<()> {
+    let mut server = Server::new(writer);
+    while !server.exited {
+        match read_message(&mut reader)? {
+            Some(message) => server.handle(&message)?,
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+impl<W: Write> Server<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            documents: HashMap::new(),
+            exited: false,
+        }
+    }
+
+    fn respond(&mut self, id: &Json, result: Result<Json, (i64, String)>) -> io::Result<()> {
+        let mut response = json!({ "jsonrpc": "2.0", "id": id });
+        match result {
+            Ok(result) => response["result"] = result,
+            Err((code, message)) => {
+                response["error"] = json!({ "code": code, "message": message });
+            }
+        }
+        write_message(&mut self.writer, &response)
+    }
+
+    fn notify(&mut self, method: &str, params: Json) -> io::Result<()> {
+        let notification = json!({ "jsonrpc": "2.0", "method": method, "params": params });
+        write_message(&mut self.writer, &notification)
+    }
+
+    /// Handles a request (a message with an `id`) or a notification.
+    pub fn handle(&mut self, message: &Json) -> io::Result<()> {
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let result = match message["method"].as_str().unwrap_or_default() {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": TEXT_DOCUMENT_SYNC_FULL,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "hoverProvider": true,
+                    "completionProvider": { "triggerCharacters": ["@"] },
+                },
+                "serverInfo": { "name": "adl-lsp" },
+            })),
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                return self.update(uri, text.to_string());
+            }
+            "textDocument/didChange" => {
+                // with full synchronization the last change is the whole text
+                let changes = params["contentChanges"].as_array();
+                let Some(text) = changes.and_then(|c| c.last()?["text"].as_str()) else {
+                    return Ok(());
+                };
+                return self.update(uri, text.to_string());
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                return self.notify(
+                    "textDocument/publishDiagnostics",
+                    json!({ "uri": uri, "diagnostics": [] }),
+                );
+            }
+            "textDocument/definition" => self.definition(uri, &params["position"]),
+            "textDocument/references" => {
+                let include_declaration = params["context"]["includeDeclaration"]
+                    .as_bool()
+                    .unwrap_or(true);
+                self.references(uri, &params["position"], include_declaration)
+            }
+            "textDocument/hover" => self.hover(uri, &params["position"]),
+            "textDocument/completion" => self.completion(uri, &params["position"]),
+            "shutdown" => Ok(Json::Null),
+            "exit" => {
+                self.exited = true;
+                return Ok(());
+            }
+            method => Err((METHOD_NOT_FOUND, format!("unknown method '{}'", method))),
+        };
+        // notifications get no response, not even an error
+        match message.get("id") {
+            Some(id) => self.respond(id, result),
+            None => Ok(()),
+        }
+    }
+
+    fn update(&mut self, uri: &str, text: String) -> io::Result<()> {
+        let analysis = Analysis::new(&text);
+        let diagnostics: Vec<Json> = analysis
+            .diagnostics
+            .iter()
+            .map(|d| {
+                json!({
+                    "range": range(d.start, d.end),
+                    "severity": SEVERITY_ERROR,
+                    "source": "adl",
+                    "message": d.message,
+                })
+            })
+            .collect();
+        self.documents
+            .insert(uri.to_string(), Document { text, analysis });
+        self.notify(
+            "textDocument/publishDiagnostics",
+            json!({ "uri": uri, "diagnostics": diagnostics }),
+        )
+    }
+
+    fn document(&self, uri: &str) -> Result<&Document, (i64, String)> {
+        self.documents
+            .get(uri)
+            .ok_or_else(|| (INVALID_PARAMS, format!("document '{}' is not open", uri)))
+    }
+
+    fn label_at(&self, uri: &str, position: &Json) -> Result<Option<&Occurrence>, (i64, String)> {
+        let document = self.document(uri)?;
+        Ok(location(position).and_then(|l| document.analysis.label_at(l)))
+    }
+
+    fn definition(&self, uri: &str, position: &Json) -> Result<Json, (i64, String)> {
+        let analysis = &self.document(uri)?.analysis;
+        let definition = self
+            .label_at(uri, position)?
+            .and_then(|label| analysis.definition(&label.name));
+        Ok(match definition {
+            Some(d) => json!({ "uri": uri, "range": range(d.start, d.end) }),
+            None => Json::Null,
+        })
+    }
+
+    fn references(
+        &self,
+        uri: &str,
+        position: &Json,
+        include_declaration: bool,
+    ) -> Result<Json, (i64, String)> {
+        let analysis = &self.document(uri)?.analysis;
+        let Some(label) = self.label_at(uri, position)? else {
+            return Ok(Json::Null);
+        };
+        let references = analysis.references(&label.name, include_declaration);
+        Ok(references
+            .iter()
+            .map(|r| json!({ "uri": uri, "range": range(r.start, r.end) }))
+            .collect())
+    }
+
+    fn hover(&self, uri: &str, position: &Json) -> Result<Json, (i64, String)> {
+        let analysis = &self.document(uri)?.analysis;
+        let Some(label) = self.label_at(uri, position)? else {
+            return Ok(Json::Null);
+        };
+        Ok(match analysis.describe(&label.name) {
+            Some(description) => json!({
+                "contents": { "kind": "markdown", "value": format!("```adl\n{}\n```", description) },
+                "range": range(label.start, label.end),
+            }),
+            None => Json::Null,
+        })
+    }
+
+    fn completion(&self, uri: &str, position: &Json) -> Result<Json, (i64, String)> {
+        let document = self.document(uri)?;
+        let analysis = &document.analysis;
+        let mut items = vec![];
+        let mut labels: Vec<&str> = analysis
+            .definitions
+            .iter()
+            .map(|d| d.name.as_str())
+            .collect();
+        labels.sort_unstable();
+        labels.dedup();
+        for label in labels {
+            let (kind, detail) = match analysis.describe(label) {
+                Some(signature) if analysis.subprograms.contains_key(label) => {
+                    (COMPLETION_FUNCTION, signature)
+                }
+                _ => (COMPLETION_REFERENCE, "label".to_string()),
+            };
+            items.push(json!({ "label": label, "kind": kind, "detail": detail }));
+        }
+        // only a label can follow `@` and `SP`
+        if !expects_label(&document.text, position) {
+            for variable in &analysis.variables {
+                let item =
+                    json!({ "label": variable, "kind": COMPLETION_VARIABLE, "detail": "variable" });
+                items.push(item);
+            }
+            for (name, _) in STANDARD_BUILTINS {
+                let item =
+                    json!({ "label": name, "kind": COMPLETION_FUNCTION, "detail": "builtin" });
+                items.push(item);
+            }
+        }
+        Ok(json!(items))
+    }
+}
+
+/// LSP positions count lines and characters from zero, locations from one.
+fn location(position: &Json) -> Option<Location> {
+    let line = position["line"].as_u64()? as usize;
+    let character = position["character"].as_u64()? as usize;
+    Some(Location::new(line + 1, character + 1))
+}
+
+fn range(start: Location, end: Location) -> Json {
+    let position = |l: Location| {
+        json!({
+            "line": l.row().saturating_sub(1),
+            "character": l.column().saturating_sub(1),
+        })
+    };
+    json!({ "start": position(start), "end": position(end) })
+}
+
+/// Whether the word being typed at `position` follows `@` or `SP`.
+fn expects_label(text: &str, position: &Json) -> bool {
+    let Some(location) = location(position) else {
+        return false;
+    };
+    let Some(line) = text.lines().nth(location.row() - 1) else {
+        return false;
+    };
+    let before: String = line.chars().take(location.column() - 1).collect();
+    let before = before
+        .trim_end_matches(|c: char| c.is_alphanumeric() || c == '_')
+        .trim_end();
+    before.ends_with('@') || before.ends_with("SP") || before.ends_with('П')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URI: &str = "file:///square.adl";
+
+    struct Client {
+        server: Server<Vec<u8>>,
+        id: i64,
+    }
+
+    impl Client {
+        fn new() -> Self {
+            Client {
+                server: Server::new(vec![]),
+                id: 0,
+            }
+        }
+
+        fn messages(&mut self) -> Vec<Json> {
+            let output = std::mem::take(&mut self.server.writer);
+            let mut reader = output.as_slice();
+            let mut messages = vec![];
+            while let Some(message) = read_message(&mut reader).unwrap() {
+                messages.push(message);
+            }
+            messages
+        }
+
+        fn notify(&mut self, method: &str, params: Json) -> Vec<Json> {
+            let message = json!({ "jsonrpc": "2.0", "method": method, "params": params });
+            self.server.handle(&message).unwrap();
+            self.messages()
+        }
+
+        fn request(&mut self, method: &str, params: Json) -> Json {
+            self.id += 1;
+            let message =
+                json!({ "jsonrpc": "2.0", "id": self.id, "method": method, "params": params });
+            self.server.handle(&message).unwrap();
+            let messages = self.messages();
+            assert_eq!(messages.len(), 1);
+            assert_eq!(messages[0]["id"], json!(self.id));
+            messages[0]["result"].clone()
+        }
+
+        fn at(&mut self, method: &str, line: usize, character: usize) -> Json {
+            let params = json!({
+                "textDocument": { "uri": URI },
+                "position": { "line": line, "character": character },
+                "context": { "includeDeclaration": true },
+            });
+            self.request(method, params)
+        }
+    }
+
+    fn diagnostics(messages: &[Json]) -> Vec<(String, Json)> {
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["method"], "textDocument/publishDiagnostics");
+        let diagnostics = messages[0]["params"]["diagnostics"].as_array().unwrap();
+        diagnostics
+            .iter()
+            .map(|d| {
+                (
+                    d["message"].as_str().unwrap().to_string(),
+                    d["range"].clone(),
+                )
+            })
+            .collect()
+    }
+
+    fn line_range(line: usize, start: usize, end: usize) -> Json {
+        json!({
+            "start": { "line": line, "character": start },
+            "end": { "line": line, "character": end },
+        })
+    }
+
+    #[test]
+    fn test_session() {
+        let mut client = Client::new();
+        let result = client.request("initialize", json!({ "capabilities": {} }));
+        assert_eq!(result["capabilities"]["definitionProvider"], true);
+
+        let text = std::fs::read_to_string("../examples/subprogram/square.adl").unwrap();
+        let messages = client.notify(
+            "textDocument/didOpen",
+            json!({ "textDocument": { "uri": URI, "languageId": "adl", "version": 1, "text": text } }),
+        );
+        assert_eq!(diagnostics(&messages), []);
+
+        // `SP square { k}` on the second line
+        let definition = client.at("textDocument/definition", 1, 5);
+        assert_eq!(definition["range"], line_range(6, 0, 6));
+        let references = client.at("textDocument/references", 6, 2);
+        let ranges: Vec<&Json> = references
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| &r["range"])
+            .collect();
+        assert_eq!(ranges, [&line_range(1, 3, 9), &line_range(6, 0, 6)]);
+        let hover = client.at("textDocument/hover", 1, 5);
+        assert_eq!(hover["contents"]["value"], "```adl\nSP square {n}\n```");
+        assert_eq!(client.at("textDocument/hover", 0, 0), Json::Null);
+
+        let completion = client.at("textDocument/completion", 2, 0);
+        let labels: Vec<&str> = completion
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["label"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                "square",
+                "k",
+                "n",
+                "Print",
+                "CharAt",
+                "Concat",
+                "Replace",
+                "SubString",
+                "Gc",
+                "ToAddress",
+                "ToInt"
+            ]
+        );
+
+        let messages = client.notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": URI, "version": 2 },
+                "contentChanges": [{ "text": "@done\n" }],
+            }),
+        );
+        assert_eq!(
+            diagnostics(&messages),
+            [("undefined label 'done'".to_string(), line_range(0, 1, 5))]
+        );
+        let completion = client.at("textDocument/completion", 0, 1);
+        assert_eq!(completion, json!([]));
+
+        assert_eq!(client.request("shutdown", Json::Null), Json::Null);
+        client.notify("exit", Json::Null);
+        assert!(client.server.exited);
+    }
+
+    #[test]
+    fn test_unknown_method() {
+        let mut client = Client::new();
+        client.id += 1;
+        let message = json!({ "jsonrpc": "2.0", "id": 1, "method": "workspace/symbol" });
+        client.server.handle(&message).unwrap();
+        let messages = client.messages();
+        assert_eq!(messages[0]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(
+            client.notify("$/cancelRequest", json!({ "id": 1 })),
+            Vec::<Json>::new()
+        );
+    }
+}
@@ -0,0 +1,612 @@
+//! Compact binary container for bytecode (`.adlc` files).
+//!
+//! Layout, all integers little-endian:
+//!
+//! ```text
+//! magic        b"ADLC"
+//! version      u16
+//! flags        u16           bit 0: line table present
+//! pool         u32 count, then `count` constants (tag u8 + payload)
+//! code         u32 count, then `count` instructions (opcode u8 + operands)
+//! line table   u32 count, then `count` source paths as pool indices,
+//!              u32 count, then `count` spans as five u32
+//!              (source, row, column, row, column)
+//! checksum     u32           CRC-32 of everything above
+//! ```
+//!
+//! Variable and label names as well as constants are interned in the pool,
+//! instructions refer to them by index.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Write};
+
+use common::location::Location;
+use value::Value;
+
+use crate::bytecode::{line_table::LineTable, Bytecode};
+
+pub const MAGIC: &[u8; 4] = b"ADLC";
+/// Bumped whenever the encoding changes; files of other versions are
+/// rejected. 2 added address constants and made integers plain numbers,
+/// 3 added `GreaterEqual` and `LessEqual`, 4 added the slot instructions,
+/// 5 the source files of imported modules.
+pub const FORMAT_VERSION: u16 = 5;
+
+const FLAG_LINE_TABLE: u16 = 1;
+
+const TAG_NULL: u8 = 0;
+const TAG_INT: u8 = 1;
+const TAG_FLOAT: u8 = 2;
+const TAG_BOOL: u8 = 3;
+const TAG_STRING: u8 = 4;
+const TAG_ADDRESS: u8 = 5;
+
+/// Tells whether `contents` starts like an `.adlc` file.
+pub fn is_binary(contents: &[u8]) -> bool {
+    contents.starts_with(MAGIC)
+}
+
+pub fn write_binary_to_file(
+    bytecode: &[Bytecode],
+    line_table: Option<&LineTable>,
+    file_path: &str,
+) -> Result<(), io::Error> {
+    let mut file = File::create(file_path)?;
+    file.write_all(&encode(bytecode, line_table)?)
+}
+
+pub fn read_binary_from_file(file_path: &str) -> Result<(Vec<Bytecode>, LineTable), io::Error> {
+    let mut file = File::open(file_path)?;
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents)?;
+    decode(&contents)
+}
+
+pub fn encode(bytecode: &[Bytecode], line_table: Option<&LineTable>) -> Result<Vec<u8>, io::Error> {
+    let mut pool = Pool::default();
+    let mut code = Vec::new();
+    for instruction in bytecode {
+        encode_instruction(instruction, &mut pool, &mut code)?;
+    }
+
+    let mut debug = Vec::new();
+    if let Some(line_table) = line_table {
+        put_len(&mut debug, line_table.sources().len())?;
+        for source in line_table.sources() {
+            let index = pool.intern(PoolKey::String(source.clone()));
+            put_u32(&mut debug, index);
+        }
+        put_len(&mut debug, line_table.len())?;
+        for (start, end) in line_table.spans() {
+            let file = start.file();
+            for n in [file, start.row(), start.column(), end.row(), end.column()] {
+                put_len(&mut debug, n)?;
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    let flags = if line_table.is_some() {
+        FLAG_LINE_TABLE
+    } else {
+        0
+    };
+    out.extend_from_slice(&flags.to_le_bytes());
+    pool.write(&mut out)?;
+    put_len(&mut out, bytecode.len())?;
+    out.extend_from_slice(&code);
+    out.extend_from_slice(&debug);
+    let checksum = crc32(&out);
+    put_u32(&mut out, checksum);
+    Ok(out)
+}
+
+pub fn decode(contents: &[u8]) -> Result<(Vec<Bytecode>, LineTable), io::Error> {
+    if !is_binary(contents) {
+        return Err(invalid("not an .adlc file: bad magic"));
+    }
+    let mut header = Reader::new(&contents[MAGIC.len()..]);
+    let version = header.u16()?;
+    if version < FORMAT_VERSION {
+        return Err(invalid(format!(
+            "outdated .adlc format version {} (supported version: {}), compile the program again",
+            version, FORMAT_VERSION
+        )));
+    }
+    if version != FORMAT_VERSION {
+        return Err(invalid(format!(
+            "incompatible .adlc format version {} (supported version: {})",
+            version, FORMAT_VERSION
+        )));
+    }
+    if contents.len() < MAGIC.len() + 8 {
+        return Err(invalid("unexpected end of .adlc file"));
+    }
+    let (body, checksum) = contents.split_at(contents.len() - 4);
+    let expected = u32::from_le_bytes(checksum.try_into().unwrap());
+    if crc32(body) != expected {
+        return Err(invalid("checksum mismatch: .adlc file is corrupted"));
+    }
+
+    let mut reader = Reader::new(&body[MAGIC.len() + 2..]);
+    let flags = reader.u16()?;
+
+    let pool_len = reader.u32()?;
+    let mut pool = Vec::new();
+    for _ in 0..pool_len {
+        pool.push(reader.constant()?);
+    }
+
+    let code_len = reader.u32()?;
+    let mut bytecode = Vec::new();
+    for _ in 0..code_len {
+        bytecode.push(reader.instruction(&pool)?);
+    }
+
+    let mut line_table = LineTable::default();
+    if flags & FLAG_LINE_TABLE != 0 {
+        let sources_len = reader.u32()?;
+        let mut sources = Vec::new();
+        for _ in 0..sources_len {
+            sources.push(pool_string(&pool, reader.u32()?)?);
+        }
+        let spans_len = reader.u32()?;
+        let mut spans = Vec::new();
+        for _ in 0..spans_len {
+            let file = reader.usize()?;
+            let start = Location::new(reader.usize()?, reader.usize()?).in_file(file);
+            let end = Location::new(reader.usize()?, reader.usize()?).in_file(file);
+            spans.push((start, end));
+        }
+        line_table = LineTable::new(spans);
+        line_table.set_sources(sources);
+    }
+
+    if !reader.is_empty() {
+        return Err(invalid("trailing data in .adlc file"));
+    }
+    Ok((bytecode, line_table))
+}
+
+fn encode_instruction(
+    instruction: &Bytecode,
+    pool: &mut Pool,
+    out: &mut Vec<u8>,
+) -> Result<(), io::Error> {
+    let mut name = |out: &mut Vec<u8>, name: &str| {
+        let index = pool.intern(PoolKey::String(name.to_string()));
+        put_u32(out, index);
+    };
+    match instruction {
+        Bytecode::Halt => out.push(0),
+        Bytecode::Return => out.push(1),
+        Bytecode::Constant(value) => {
+            out.push(2);
+            let index = pool.intern(PoolKey::from_value(value)?);
+            put_u32(out, index);
+        }
+        Bytecode::Not => out.push(3),
+        Bytecode::And => out.push(4),
+        Bytecode::Or => out.push(5),
+        Bytecode::Negate => out.push(6),
+        Bytecode::Add => out.push(7),
+        Bytecode::Sub => out.push(8),
+        Bytecode::Mul => out.push(9),
+        Bytecode::Div => out.push(10),
+        Bytecode::Mod => out.push(11),
+        Bytecode::Equal => out.push(12),
+        Bytecode::NotEqual => out.push(13),
+        Bytecode::Greater => out.push(14),
+        Bytecode::Less => out.push(15),
+        Bytecode::Pop => out.push(16),
+        Bytecode::Label(label) => {
+            out.push(17);
+            name(out, label);
+        }
+        Bytecode::Jump(addr) => {
+            out.push(18);
+            put_len(out, *addr)?;
+        }
+        Bytecode::JumpIfFalse(addr) => {
+            out.push(19);
+            put_len(out, *addr)?;
+        }
+        Bytecode::Deref => out.push(20),
+        Bytecode::MulDeref => out.push(21),
+        Bytecode::StoreVar(var) => {
+            out.push(22);
+            name(out, var);
+        }
+        Bytecode::LoadVar(var) => {
+            out.push(23);
+            name(out, var);
+        }
+        Bytecode::Store => out.push(24),
+        Bytecode::Alloc => out.push(25),
+        Bytecode::AllocMany(count) => {
+            out.push(26);
+            put_len(out, *count)?;
+        }
+        Bytecode::Dup => out.push(27),
+        Bytecode::StoreAddr => out.push(28),
+        Bytecode::BindAddr(var) => {
+            out.push(29);
+            name(out, var);
+        }
+        Bytecode::FreeAddr => out.push(30),
+        Bytecode::CallBuiltin(function, arity) => {
+            out.push(31);
+            name(out, function);
+            put_len(out, *arity)?;
+        }
+        Bytecode::CallSubProgram(addr, arity) => {
+            out.push(32);
+            put_len(out, *addr)?;
+            put_len(out, *arity)?;
+        }
+        Bytecode::PushScope => out.push(33),
+        Bytecode::PopScope => out.push(34),
+        Bytecode::Swap => out.push(35),
+        Bytecode::Exchange => out.push(36),
+        Bytecode::GreaterEqual => out.push(37),
+        Bytecode::LessEqual => out.push(38),
+        Bytecode::LoadSlot(slot) => {
+            out.push(39);
+            put_len(out, *slot)?;
+        }
+        Bytecode::StoreSlot(slot) => {
+            out.push(40);
+            put_len(out, *slot)?;
+        }
+        Bytecode::BindSlot(slot) => {
+            out.push(41);
+            put_len(out, *slot)?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(PartialEq, Eq, Hash, Clone)]
+enum PoolKey {
+    Null,
+    Int(i64),
+    Address(i64),
+    Float(u64),
+    Bool(bool),
+    String(String),
+}
+
+impl PoolKey {
+    fn from_value(value: &Value) -> Result<Self, io::Error> {
+        match value {
+            Value::Null => Ok(PoolKey::Null),
+            Value::Int(i) => Ok(PoolKey::Int(*i)),
+            Value::Address(address) => Ok(PoolKey::Address(*address)),
+            Value::Float(f) => Ok(PoolKey::Float(f.to_bits())),
+            Value::Bool(b) => Ok(PoolKey::Bool(*b)),
+            Value::String(s) => Ok(PoolKey::String(s.clone())),
+            Value::Function(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "function constants cannot be serialized",
+            )),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Pool {
+    entries: Vec<PoolKey>,
+    indices: HashMap<PoolKey, u32>,
+}
+
+impl Pool {
+    fn intern(&mut self, key: PoolKey) -> u32 {
+        if let Some(index) = self.indices.get(&key) {
+            return *index;
+        }
+        let index = self.entries.len() as u32;
+        self.entries.push(key.clone());
+        self.indices.insert(key, index);
+        index
+    }
+
+    fn write(&self, out: &mut Vec<u8>) -> Result<(), io::Error> {
+        put_len(out, self.entries.len())?;
+        for entry in &self.entries {
+            match entry {
+                PoolKey::Null => out.push(TAG_NULL),
+                PoolKey::Int(i) => {
+                    out.push(TAG_INT);
+                    out.extend_from_slice(&i.to_le_bytes());
+                }
+                PoolKey::Address(address) => {
+                    out.push(TAG_ADDRESS);
+                    out.extend_from_slice(&address.to_le_bytes());
+                }
+                PoolKey::Float(bits) => {
+                    out.push(TAG_FLOAT);
+                    out.extend_from_slice(&bits.to_le_bytes());
+                }
+                PoolKey::Bool(b) => {
+                    out.push(TAG_BOOL);
+                    out.push(*b as u8);
+                }
+                PoolKey::String(s) => {
+                    out.push(TAG_STRING);
+                    put_len(out, s.len())?;
+                    out.extend_from_slice(s.as_bytes());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn pool_string(pool: &[Value], index: u32) -> Result<String, io::Error> {
+    match pool.get(index as usize) {
+        Some(Value::String(s)) => Ok(s.clone()),
+        Some(_) => Err(invalid(format!("pool entry {} is not a name", index))),
+        None => Err(invalid(format!("pool index {} out of range", index))),
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], io::Error> {
+        if self.bytes.len() < n {
+            return Err(invalid("unexpected end of .adlc file"));
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, io::Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, io::Error> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, io::Error> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, io::Error> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn usize(&mut self) -> Result<usize, io::Error> {
+        Ok(self.u32()? as usize)
+    }
+
+    fn constant(&mut self) -> Result<Value, io::Error> {
+        match self.u8()? {
+            TAG_NULL => Ok(Value::Null),
+            TAG_INT => Ok(Value::new_int(self.u64()? as i64)),
+            TAG_ADDRESS => Ok(Value::new_address(self.u64()? as i64)),
+            TAG_FLOAT => Ok(Value::new_float(f64::from_bits(self.u64()?))),
+            TAG_BOOL => Ok(Value::new_bool(self.u8()? != 0)),
+            TAG_STRING => {
+                let len = self.usize()?;
+                let s = std::str::from_utf8(self.take(len)?)
+                    .map_err(|_| invalid("invalid UTF-8 in constant pool"))?;
+                Ok(Value::new_string(s.to_string()))
+            }
+            tag => Err(invalid(format!("unknown constant tag {}", tag))),
+        }
+    }
+
+    fn instruction(&mut self, pool: &[Value]) -> Result<Bytecode, io::Error> {
+        let opcode = self.u8()?;
+        let instruction = match opcode {
+            0 => Bytecode::Halt,
+            1 => Bytecode::Return,
+            2 => {
+                let index = self.u32()?;
+                let value = pool
+                    .get(index as usize)
+                    .ok_or_else(|| invalid(format!("pool index {} out of range", index)))?;
+                Bytecode::Constant(value.clone())
+            }
+            3 => Bytecode::Not,
+            4 => Bytecode::And,
+            5 => Bytecode::Or,
+            6 => Bytecode::Negate,
+            7 => Bytecode::Add,
+            8 => Bytecode::Sub,
+            9 => Bytecode::Mul,
+            10 => Bytecode::Div,
+            11 => Bytecode::Mod,
+            12 => Bytecode::Equal,
+            13 => Bytecode::NotEqual,
+            14 => Bytecode::Greater,
+            15 => Bytecode::Less,
+            16 => Bytecode::Pop,
+            17 => Bytecode::Label(pool_string(pool, self.u32()?)?),
+            18 => Bytecode::Jump(self.usize()?),
+            19 => Bytecode::JumpIfFalse(self.usize()?),
+            20 => Bytecode::Deref,
+            21 => Bytecode::MulDeref,
+            22 => Bytecode::StoreVar(pool_string(pool, self.u32()?)?),
+            23 => Bytecode::LoadVar(pool_string(pool, self.u32()?)?),
+            24 => Bytecode::Store,
+            25 => Bytecode::Alloc,
+            26 => Bytecode::AllocMany(self.usize()?),
+            27 => Bytecode::Dup,
+            28 => Bytecode::StoreAddr,
+            29 => Bytecode::BindAddr(pool_string(pool, self.u32()?)?),
+            30 => Bytecode::FreeAddr,
+            31 => {
+                let function = pool_string(pool, self.u32()?)?;
+                Bytecode::CallBuiltin(function, self.usize()?)
+            }
+            32 => {
+                let addr = self.usize()?;
+                Bytecode::CallSubProgram(addr, self.usize()?)
+            }
+            33 => Bytecode::PushScope,
+            34 => Bytecode::PopScope,
+            35 => Bytecode::Swap,
+            36 => Bytecode::Exchange,
+            37 => Bytecode::GreaterEqual,
+            38 => Bytecode::LessEqual,
+            39 => Bytecode::LoadSlot(self.usize()?),
+            40 => Bytecode::StoreSlot(self.usize()?),
+            41 => Bytecode::BindSlot(self.usize()?),
+            _ => return Err(invalid(format!("unknown opcode {}", opcode))),
+        };
+        Ok(instruction)
+    }
+}
+
+fn put_u32(out: &mut Vec<u8>, n: u32) {
+    out.extend_from_slice(&n.to_le_bytes());
+}
+
+fn put_len(out: &mut Vec<u8>, n: usize) -> Result<(), io::Error> {
+    let n = u32::try_from(n).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} does not fit in an .adlc operand", n),
+        )
+    })?;
+    put_u32(out, n);
+    Ok(())
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// CRC-32 (IEEE 802.3).
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program() -> Vec<Bytecode> {
+        vec![
+            Bytecode::Jump(3),
+            Bytecode::Constant(Value::new_string("hello".to_string())),
+            Bytecode::BindAddr("hello".to_string()),
+            Bytecode::Label("main".to_string()),
+            Bytecode::Constant(Value::new_int(-42)),
+            Bytecode::Constant(Value::new_address(7)),
+            Bytecode::Constant(Value::new_float(2.5)),
+            Bytecode::Constant(Value::new_bool(true)),
+            Bytecode::Constant(Value::Null),
+            Bytecode::LoadVar("hello".to_string()),
+            Bytecode::Deref,
+            Bytecode::CallBuiltin("Print".to_string(), 5),
+            Bytecode::CallSubProgram(3, 0),
+            Bytecode::AllocMany(2),
+            Bytecode::Exchange,
+            Bytecode::LoadSlot(0),
+            Bytecode::StoreSlot(1),
+            Bytecode::BindSlot(2),
+            Bytecode::Halt,
+        ]
+    }
+
+    #[test]
+    fn test_encode_and_decode() {
+        let bytecode = program();
+        let mut line_table = LineTable::new(
+            (0..bytecode.len())
+                .map(|i| {
+                    let file = i % 2;
+                    let start = Location::new(i + 1, 1).in_file(file);
+                    (start, Location::new(i + 1, 10).in_file(file))
+                })
+                .collect(),
+        );
+        line_table.set_sources(vec![
+            "examples/list/import.adl".to_string(),
+            "examples/list/lib.adl".to_string(),
+        ]);
+
+        let bytes = encode(&bytecode, Some(&line_table)).unwrap();
+        assert!(is_binary(&bytes));
+        assert_eq!(decode(&bytes).unwrap(), (bytecode.clone(), line_table));
+
+        let bytes = encode(&bytecode, None).unwrap();
+        assert_eq!(decode(&bytes).unwrap(), (bytecode, LineTable::default()));
+    }
+
+    #[test]
+    fn test_names_are_interned() {
+        let bytes = encode(&program(), None).unwrap();
+        let occurrences = bytes.windows(5).filter(|w| w == b"hello").count();
+        assert_eq!(occurrences, 1);
+    }
+
+    #[test]
+    fn test_reject_incompatible_version() {
+        let mut bytes = encode(&program(), None).unwrap();
+        bytes[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        let error = decode(&bytes).unwrap_err();
+        assert!(error.to_string().starts_with(&format!(
+            "incompatible .adlc format version {}",
+            FORMAT_VERSION + 1
+        )));
+    }
+
+    #[test]
+    fn test_reject_outdated_version() {
+        let mut bytes = encode(&program(), None).unwrap();
+        bytes[4..6].copy_from_slice(&1u16.to_le_bytes());
+        let error = decode(&bytes).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "outdated .adlc format version 1 (supported version: {}), compile the program again",
+                FORMAT_VERSION
+            )
+        );
+    }
+
+    #[test]
+    fn test_reject_corrupted_file() {
+        let mut bytes = encode(&program(), None).unwrap();
+        let middle = bytes.len() / 2;
+        bytes[middle] ^= 0xFF;
+        let error = decode(&bytes).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "checksum mismatch: .adlc file is corrupted"
+        );
+
+        let bytes = encode(&program(), None).unwrap();
+        assert!(decode(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,58 @@
+use common::location::Location;
+
+/// Maps every instruction index to the span of the statement it was
+/// generated from.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LineTable {
+    spans: Vec<(Location, Location)>,
+    sources: Vec<String>,
+}
+
+impl LineTable {
+    pub fn new(spans: Vec<(Location, Location)>) -> Self {
+        Self {
+            spans,
+            sources: vec![],
+        }
+    }
+
+    /// Path of the entry source file of the program.
+    pub fn source(&self) -> Option<&str> {
+        self.sources.first().map(String::as_str)
+    }
+
+    pub fn set_source(&mut self, source: impl Into<String>) {
+        self.sources = vec![source.into()];
+    }
+
+    /// Paths of the source files the spans refer to, indexed by
+    /// `Location::file`.
+    pub fn sources(&self) -> &[String] {
+        &self.sources
+    }
+
+    pub fn set_sources(&mut self, sources: Vec<String>) {
+        self.sources = sources;
+    }
+
+    /// Path of the source file `location` points into.
+    pub fn source_of(&self, location: Location) -> Option<&str> {
+        self.sources.get(location.file()).map(String::as_str)
+    }
+
+    pub fn get(&self, index: usize) -> Option<(Location, Location)> {
+        self.spans.get(index).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    pub fn spans(&self) -> &[(Location, Location)] {
+        &self.spans
+    }
+}
@@ -1,6 +1,9 @@
 use value::Value;
 
+pub mod binary;
+pub mod line_table;
 pub mod serializer;
+pub mod slots;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Bytecode {
@@ -20,6 +23,8 @@ pub enum Bytecode {
     NotEqual,
     Greater,
     Less,
+    GreaterEqual,
+    LessEqual,
     Pop,
     Label(String),
     Jump(usize),
@@ -39,5 +44,9 @@ pub enum Bytecode {
     CallSubProgram(usize, usize), // label, arity, label_to
     PushScope,                    // Push a new scope to the stack
     PopScope,
-    Swap, //
+    Swap,            //
+    Exchange,        // swap the values stored at the two addresses on top of the stack
+    LoadSlot(usize), // `LoadVar`, `StoreVar` and `BindAddr` with the name resolved to a frame slot
+    StoreSlot(usize),
+    BindSlot(usize),
 }
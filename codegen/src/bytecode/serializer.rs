@@ -1,38 +1,214 @@
-use crate::bytecode::Bytecode;
+use crate::bytecode::{binary, line_table::LineTable, Bytecode};
+use common::location::Location;
+use std::collections::{BTreeSet, HashMap};
 use std::fs::File;
 use std::io::{self, Read, Write};
 use value::Value;
 
-/// Serializes bytecode to a human-readable format and writes to a file.
-pub fn write_bytecode_to_file(bytecode: &[Bytecode], file_path: &str) -> Result<(), io::Error> {
-    let mut file = File::create(file_path)?;
-    for (i, instruction) in bytecode.iter().enumerate() {
-        let serialized_instruction = format_bytecode_instruction(i, instruction);
-        file.write_all(serialized_instruction.as_bytes())?;
+const SOURCE_SECTION: &str = ".source";
+const LINE_TABLE_SECTION: &str = ".line_table";
+const COMMENT: char = '#';
+
+/// Extension of files in the binary format, see `bytecode::binary`.
+pub const BINARY_EXTENSION: &str = "adlc";
+
+/// Writes the binary format if `file_path` ends with `.adlc`, the text
+/// listing otherwise.
+pub fn write_program_to_file(
+    bytecode: &[Bytecode],
+    line_table: Option<&LineTable>,
+    file_path: &str,
+) -> Result<(), io::Error> {
+    let is_binary = std::path::Path::new(file_path)
+        .extension()
+        .is_some_and(|e| e == BINARY_EXTENSION);
+    if is_binary {
+        binary::write_binary_to_file(bytecode, line_table, file_path)
+    } else {
+        write_bytecode_to_file(bytecode, line_table, file_path)
+    }
+}
+
+/// Reads a program in either format, telling them apart by the magic
+/// number of the binary one.
+pub fn read_program_from_file(file_path: &str) -> Result<(Vec<Bytecode>, LineTable), io::Error> {
+    let contents = std::fs::read(file_path)?;
+    if binary::is_binary(&contents) {
+        return binary::decode(&contents);
     }
-    Ok(())
+    let contents = String::from_utf8(contents)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Bytecode is not valid UTF-8"))?;
+    parse_bytecode_with_line_table(&contents)
+}
+
+/// Disassembles bytecode and writes the listing to a file.
+/// The line table, if any, is appended after the instructions.
+pub fn write_bytecode_to_file(
+    bytecode: &[Bytecode],
+    line_table: Option<&LineTable>,
+    file_path: &str,
+) -> Result<(), io::Error> {
+    let mut file = File::create(file_path)?;
+    file.write_all(disassemble(bytecode, line_table)?.as_bytes())
 }
 
-/// Deserializes bytecode from a human-readable format in a file.
+/// Assembles bytecode from a listing in a file.
 pub fn read_bytecode_from_file(file_path: &str) -> Result<Vec<Bytecode>, io::Error> {
+    read_bytecode_with_line_table_from_file(file_path).map(|(bytecode, _)| bytecode)
+}
+
+/// Assembles bytecode and its line table (empty if the file has none).
+pub fn read_bytecode_with_line_table_from_file(
+    file_path: &str,
+) -> Result<(Vec<Bytecode>, LineTable), io::Error> {
     let mut file = File::open(file_path)?;
     let mut contents = String::new();
     file.read_to_string(&mut contents)?;
-    let bytecode = parse_bytecode_instructions(&contents)?;
-    Ok(bytecode)
+    parse_bytecode_with_line_table(&contents)
+}
+
+pub fn parse_bytecode_instructions(contents: &str) -> Result<Vec<Bytecode>, io::Error> {
+    parse_bytecode_with_line_table(contents).map(|(bytecode, _)| bytecode)
 }
 
-fn format_bytecode_instruction(offset: usize, instruction: &Bytecode) -> String {
+/// Renders bytecode as a listing that `parse_bytecode_with_line_table`
+/// reads back unchanged.
+///
+/// Every instruction is prefixed with its offset. Jump and call targets are
+/// written as symbolic labels (`L12`) defined on a line of their own right
+/// before the instruction they point at, string constants and names that
+/// are not plain identifiers are quoted and escaped.
+pub fn disassemble(
+    bytecode: &[Bytecode],
+    line_table: Option<&LineTable>,
+) -> Result<String, io::Error> {
+    let targets: BTreeSet<usize> = bytecode
+        .iter()
+        .filter_map(jump_target)
+        .filter(|target| *target <= bytecode.len())
+        .collect();
+    let mut s = String::new();
+    for (i, instruction) in bytecode.iter().enumerate() {
+        if targets.contains(&i) {
+            s.push_str(&format!("{}:\n", label_for(i)));
+        }
+        s.push_str(&format_bytecode_instruction(i, instruction, &targets)?);
+    }
+    if targets.contains(&bytecode.len()) {
+        s.push_str(&format!("{}:\n", label_for(bytecode.len())));
+    }
+    if let Some(line_table) = line_table {
+        s.push_str(&format_line_table(line_table));
+    }
+    Ok(s)
+}
+
+fn jump_target(instruction: &Bytecode) -> Option<usize> {
     match instruction {
-        Bytecode::Constant(val) => match val {
-            Value::Int(i) => format!("{:<5} {:<23} {}\n", offset, "LOAD_CONST", i),
-            Value::Float(f) => format!("{:<5} {:<23} {}\n", offset, "LOAD_CONST", f),
-            Value::Bool(b) => format!("{:<5} {:<23} {}\n", offset, "LOAD_CONST", b),
-            Value::String(s) => format!("{:<5} {:<23} '{}'\n", offset, "LOAD_CONST", s),
-            _ => format!("{:<5} {:<23} {:?}\n", offset, "LOAD_CONST", val),
-        },
-        Bytecode::LoadVar(name) => format!("{:<5} {:<23} {}\n", offset, "LOAD_NAME", name),
-        Bytecode::StoreVar(name) => format!("{:<5} {:<23} {}\n", offset, "STORE_NAME", name),
+        Bytecode::Jump(addr) | Bytecode::JumpIfFalse(addr) | Bytecode::CallSubProgram(addr, _) => {
+            Some(*addr)
+        }
+        _ => None,
+    }
+}
+
+fn label_for(offset: usize) -> String {
+    format!("L{}", offset)
+}
+
+fn format_line_table(line_table: &LineTable) -> String {
+    let mut s = String::new();
+    for source in line_table.sources() {
+        s.push_str(&format!("{} {}\n", SOURCE_SECTION, source));
+    }
+    s.push_str(LINE_TABLE_SECTION);
+    s.push('\n');
+    for (i, (start, end)) in line_table.spans().iter().enumerate() {
+        s.push_str(&format!(
+            "{:<5} {}:{} {}:{}",
+            i,
+            start.row(),
+            start.column(),
+            end.row(),
+            end.column()
+        ));
+        // spans in the entry file leave out its index
+        if start.file() != 0 {
+            s.push_str(&format!(" {}", start.file()));
+        }
+        s.push('\n');
+    }
+    s
+}
+
+fn parse_location(s: &str) -> Result<Location, io::Error> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Invalid line table location");
+    let (row, column) = s.split_once(':').ok_or_else(invalid)?;
+    Ok(Location::new(
+        row.parse().map_err(|_| invalid())?,
+        column.parse().map_err(|_| invalid())?,
+    ))
+}
+
+fn parse_line_table<'a>(
+    lines: impl Iterator<Item = &'a str>,
+) -> Result<Vec<(Location, Location)>, io::Error> {
+    let mut spans = vec![];
+    for line in lines {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.is_empty() {
+            continue;
+        }
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Invalid line table entry");
+        let file = match parts.len() {
+            3 => 0,
+            4 => parts[3].parse().map_err(|_| invalid())?,
+            _ => return Err(invalid()),
+        };
+        spans.push((
+            parse_location(parts[1])?.in_file(file),
+            parse_location(parts[2])?.in_file(file),
+        ));
+    }
+    Ok(spans)
+}
+
+fn format_bytecode_instruction(
+    offset: usize,
+    instruction: &Bytecode,
+    targets: &BTreeSet<usize>,
+) -> Result<String, io::Error> {
+    let target = |addr: &usize| {
+        if targets.contains(addr) {
+            label_for(*addr)
+        } else {
+            addr.to_string()
+        }
+    };
+    let line = match instruction {
+        Bytecode::Constant(val) => {
+            let constant = match val {
+                Value::Null => "null".to_string(),
+                Value::Int(i) => i.to_string(),
+                Value::Address(address) => format!("&{}", address),
+                Value::Float(f) => format!("{:?}", f),
+                Value::Bool(b) => b.to_string(),
+                Value::String(s) => escape(s),
+                Value::Function(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "function constants cannot be serialized",
+                    ))
+                }
+            };
+            format!("{:<5} {:<23} {}\n", offset, "LOAD_CONST", constant)
+        }
+        Bytecode::LoadVar(name) => {
+            format!("{:<5} {:<23} {}\n", offset, "LOAD_NAME", format_name(name))
+        }
+        Bytecode::StoreVar(name) => {
+            format!("{:<5} {:<23} {}\n", offset, "STORE_NAME", format_name(name))
+        }
         Bytecode::Add => format!("{:<5} {}\n", offset, "BINARY_ADD"),
         Bytecode::Sub => format!("{:<5} {}\n", offset, "BINARY_SUBTRACT"),
         Bytecode::Mul => format!("{:<5} {}\n", offset, "BINARY_MULTIPLY"),
@@ -44,14 +220,23 @@ fn format_bytecode_instruction(offset: usize, instruction: &Bytecode) -> String
         Bytecode::NotEqual => format!("{:<5} {}\n", offset, "COMPARE_OP NE"),
         Bytecode::Greater => format!("{:<5} {}\n", offset, "COMPARE_OP GT"),
         Bytecode::Less => format!("{:<5} {}\n", offset, "COMPARE_OP LT"),
+        Bytecode::GreaterEqual => format!("{:<5} {}\n", offset, "COMPARE_OP GE"),
+        Bytecode::LessEqual => format!("{:<5} {}\n", offset, "COMPARE_OP LE"),
         Bytecode::CallBuiltin(name, arity) => format!(
             "{:<5} {:<23} {} ({})\n",
-            offset, "CALL_FUNCTION", name, arity
+            offset,
+            "CALL_FUNCTION",
+            format_name(name),
+            arity
         ),
         Bytecode::Return => format!("{:<5} {}\n", offset, "RETURN_VALUE"),
-        Bytecode::Jump(addr) => format!("{:<5} {:<23} {}\n", offset, "JUMP", addr),
-        Bytecode::JumpIfFalse(addr) => format!("{:<5} {:<23} {}\n", offset, "JUMP_IF_FALSE", addr),
-        Bytecode::Label(label) => format!("{:<5} {:<23} {}\n", offset, "LABEL", label),
+        Bytecode::Jump(addr) => format!("{:<5} {:<23} {}\n", offset, "JUMP", target(addr)),
+        Bytecode::JumpIfFalse(addr) => {
+            format!("{:<5} {:<23} {}\n", offset, "JUMP_IF_FALSE", target(addr))
+        }
+        Bytecode::Label(label) => {
+            format!("{:<5} {:<23} {}\n", offset, "LABEL", format_name(label))
+        }
         Bytecode::Halt => format!("{:<5} {}\n", offset, "HALT"),
         Bytecode::Not => format!("{:<5} {}\n", offset, "UNARY_NOT"),
         Bytecode::Negate => format!("{:<5} {}\n", offset, "UNARY_NEGATIVE"),
@@ -63,42 +248,255 @@ fn format_bytecode_instruction(offset: usize, instruction: &Bytecode) -> String
         Bytecode::AllocMany(n) => format!("{:<5} {:<23} {}\n", offset, "ALLOC_MANY", n),
         Bytecode::Dup => format!("{:<5} {}\n", offset, "DUP"),
         Bytecode::StoreAddr => format!("{:<5} {}\n", offset, "STORE_ADDR"),
-        Bytecode::BindAddr(name) => format!("{:<5} {:<23} {}\n", offset, "BIND_ADDR", name),
+        Bytecode::BindAddr(name) => {
+            format!("{:<5} {:<23} {}\n", offset, "BIND_ADDR", format_name(name))
+        }
         Bytecode::FreeAddr => format!("{:<5} {}\n", offset, "FREE_ADDR"),
         Bytecode::CallSubProgram(label, arity) => format!(
             "{:<5} {:<23} {} ({})\n",
-            offset, "CALL_SUBPROGRAM", label, arity
+            offset,
+            "CALL_SUBPROGRAM",
+            target(label),
+            arity
         ),
         Bytecode::PushScope => format!("{:<5} {}\n", offset, "PUSH_SCOPE"),
         Bytecode::PopScope => format!("{:<5} {}\n", offset, "POP_SCOPE"),
         Bytecode::Swap => format!("{:<5} {}\n", offset, "SWAP"),
+        Bytecode::Exchange => format!("{:<5} {}\n", offset, "EXCHANGE"),
+        Bytecode::LoadSlot(slot) => format!("{:<5} {:<23} {}\n", offset, "LOAD_SLOT", slot),
+        Bytecode::StoreSlot(slot) => format!("{:<5} {:<23} {}\n", offset, "STORE_SLOT", slot),
+        Bytecode::BindSlot(slot) => format!("{:<5} {:<23} {}\n", offset, "BIND_SLOT", slot),
+    };
+    Ok(line)
+}
+
+/// Names that are plain identifiers are written as is, anything else is
+/// quoted like a string constant.
+fn format_name(name: &str) -> String {
+    let plain = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '.');
+    if plain {
+        name.to_string()
+    } else {
+        escape(name)
     }
 }
 
-pub fn parse_bytecode_instructions(contents: &str) -> Result<Vec<Bytecode>, io::Error> {
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// A whitespace separated word of a listing line, quoted ones unescaped.
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+    Word(&'a str),
+    Quoted(String),
+}
+
+/// Splits a listing line into tokens, dropping a trailing `#` comment.
+/// Besides `"escaped"` strings, `'raw'` ones written by older versions are
+/// accepted.
+fn tokenize(line: &str) -> Result<Vec<Token<'_>>, String> {
+    let mut tokens = vec![];
+    let mut chars = line.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            COMMENT => break,
+            '"' => {
+                chars.next();
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => s.push(unescape(&mut chars)?),
+                        Some((_, c)) => s.push(c),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+                tokens.push(Token::Quoted(s));
+            }
+            '\'' => {
+                chars.next();
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '\'')) => break,
+                        Some((_, c)) => s.push(c),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+                tokens.push(Token::Quoted(s));
+            }
+            _ => {
+                let mut end = line.len();
+                while let Some(&(i, c)) = chars.peek() {
+                    if c.is_whitespace() || c == COMMENT {
+                        end = i;
+                        break;
+                    }
+                    chars.next();
+                }
+                tokens.push(Token::Word(&line[start..end]));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn unescape(chars: &mut impl Iterator<Item = (usize, char)>) -> Result<char, String> {
+    match chars.next().map(|(_, c)| c) {
+        Some('"') => Ok('"'),
+        Some('\\') => Ok('\\'),
+        Some('n') => Ok('\n'),
+        Some('r') => Ok('\r'),
+        Some('t') => Ok('\t'),
+        Some('u') => {
+            if chars.next().map(|(_, c)| c) != Some('{') {
+                return Err("expected '{' after \\u".to_string());
+            }
+            let mut hex = String::new();
+            for (_, c) in chars.by_ref() {
+                if c == '}' {
+                    return u32::from_str_radix(&hex, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .ok_or_else(|| format!("invalid escape \\u{{{}}}", hex));
+                }
+                hex.push(c);
+            }
+            Err("unterminated \\u escape".to_string())
+        }
+        Some(c) => Err(format!("unknown escape \\{}", c)),
+        None => Err("unterminated string".to_string()),
+    }
+}
+
+/// A jump or call target before labels are resolved.
+enum Target {
+    Offset(usize),
+    Label(String),
+}
+
+/// Assembles a listing produced by `disassemble`.
+///
+/// Offsets in front of instructions are optional and ignored, jump targets
+/// may be either labels or raw offsets, `#` starts a comment.
+pub fn parse_bytecode_with_line_table(
+    contents: &str,
+) -> Result<(Vec<Bytecode>, LineTable), io::Error> {
     let mut bytecode = Vec::new();
-    for line in contents.lines() {
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.is_empty() {
+    let mut unresolved: Vec<(usize, usize, Target)> = Vec::new();
+    let mut labels: HashMap<String, usize> = HashMap::new();
+    let mut spans = Vec::new();
+    let mut sources = Vec::new();
+    let mut lines = contents.lines().enumerate();
+    while let Some((line_number, line)) = lines.next() {
+        let error = |message: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {}", line_number + 1, message),
+            )
+        };
+        if let Some(path) = line.strip_prefix(SOURCE_SECTION) {
+            sources.push(path.trim().to_string());
             continue;
         }
-        let instruction = match parts[1] {
-            "LOAD_CONST" => {
-                let value = parts[2];
-                if let Ok(i) = value.parse::<i64>() {
-                    Bytecode::Constant(Value::new_int(i))
-                } else if let Ok(f) = value.parse::<f64>() {
-                    Bytecode::Constant(Value::new_float(f))
-                } else if let Ok(b) = value.parse::<bool>() {
-                    Bytecode::Constant(Value::new_bool(b))
-                } else {
-                    let s = value.trim_matches('\'').to_string();
-                    Bytecode::Constant(Value::new_string(s))
+        if line.trim() == LINE_TABLE_SECTION {
+            spans = parse_line_table(lines.by_ref().map(|(_, line)| line))?;
+            break;
+        }
+        let tokens = tokenize(line).map_err(error)?;
+        let mut tokens = tokens.as_slice();
+        if let [Token::Word(word)] = tokens {
+            if let Some(label) = word.strip_suffix(':') {
+                if labels.insert(label.to_string(), bytecode.len()).is_some() {
+                    return Err(error(format!("duplicate label '{}'", label)));
                 }
+                continue;
+            }
+        }
+        if let [Token::Word(word), rest @ ..] = tokens {
+            if word.parse::<usize>().is_ok() {
+                tokens = rest;
             }
-            "LOAD_NAME" => Bytecode::LoadVar(parts[2].to_string()),
-            "STORE_NAME" => Bytecode::StoreVar(parts[2].to_string()),
-            "BIND_ADDR" => Bytecode::BindAddr(parts[2].to_string()),
+        }
+        let (mnemonic, operands) = match tokens {
+            [] => continue,
+            [Token::Word(mnemonic), operands @ ..] => (*mnemonic, operands),
+            [Token::Quoted(_), ..] => return Err(error("expected an instruction".to_string())),
+        };
+        let operand = |i: usize| {
+            operands
+                .get(i)
+                .ok_or_else(|| error(format!("missing operand of {}", mnemonic)))
+        };
+        let number = |i: usize| match operand(i)? {
+            Token::Word(word) => word
+                .trim_matches('(')
+                .trim_matches(')')
+                .parse::<usize>()
+                .map_err(|e| error(format!("invalid operand of {}: {}", mnemonic, e))),
+            Token::Quoted(_) => Err(error(format!("invalid operand of {}", mnemonic))),
+        };
+        let name = |i: usize| {
+            operand(i).map(|token| match token {
+                Token::Word(word) => word.to_string(),
+                Token::Quoted(s) => s.clone(),
+            })
+        };
+        // the offset is patched in once all labels are known
+        let mut target = |i: usize| {
+            let target = match operand(i)? {
+                Token::Word(word) => match word.parse::<usize>() {
+                    Ok(offset) => Target::Offset(offset),
+                    Err(_) => Target::Label(word.to_string()),
+                },
+                Token::Quoted(s) => Target::Label(s.clone()),
+            };
+            unresolved.push((bytecode.len(), line_number, target));
+            Ok::<usize, io::Error>(0)
+        };
+        let instruction = match mnemonic {
+            "LOAD_CONST" => Bytecode::Constant(match operand(0)? {
+                Token::Quoted(s) => Value::new_string(s.clone()),
+                Token::Word("null" | "Null") => Value::Null,
+                Token::Word(word) => {
+                    if let Ok(b) = word.parse::<bool>() {
+                        Value::new_bool(b)
+                    } else if let Ok(i) = word.parse::<i64>() {
+                        Value::new_int(i)
+                    } else if let Some(Ok(address)) = word.strip_prefix('&').map(str::parse::<i64>)
+                    {
+                        Value::new_address(address)
+                    } else if let Ok(f) = word.parse::<f64>() {
+                        Value::new_float(f)
+                    } else {
+                        return Err(error(format!("invalid constant '{}'", word)));
+                    }
+                }
+            }),
+            "LOAD_NAME" => Bytecode::LoadVar(name(0)?),
+            "STORE_NAME" => Bytecode::StoreVar(name(0)?),
+            "BIND_ADDR" => Bytecode::BindAddr(name(0)?),
             "BINARY_ADD" => Bytecode::Add,
             "BINARY_SUBTRACT" => Bytecode::Sub,
             "BINARY_MULTIPLY" => Bytecode::Mul,
@@ -106,64 +504,21 @@ pub fn parse_bytecode_instructions(contents: &str) -> Result<Vec<Bytecode>, io::
             "BINARY_MODULO" => Bytecode::Mod,
             "BINARY_AND" => Bytecode::And,
             "BINARY_OR" => Bytecode::Or,
-            "COMPARE_OP" => match parts[2] {
-                "EQ" => Bytecode::Equal,
-                "NE" => Bytecode::NotEqual,
-                "GT" => Bytecode::Greater,
-                "LT" => Bytecode::Less,
-                _ => {
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        "Invalid compare operation",
-                    ))
-                }
+            "COMPARE_OP" => match operand(0)? {
+                Token::Word("EQ") => Bytecode::Equal,
+                Token::Word("NE") => Bytecode::NotEqual,
+                Token::Word("GT") => Bytecode::Greater,
+                Token::Word("LT") => Bytecode::Less,
+                Token::Word("GE") => Bytecode::GreaterEqual,
+                Token::Word("LE") => Bytecode::LessEqual,
+                _ => return Err(error("invalid compare operation".to_string())),
             },
-            "CALL_FUNCTION" => {
-                if parts.len() < 4 {
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        "Invalid CALL_FUNCTION format",
-                    ));
-                }
-                let name = parts[2].to_string();
-                let arity = parts[3]
-                    .trim_matches('(')
-                    .trim_matches(')')
-                    .parse()
-                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-                Bytecode::CallBuiltin(name, arity)
-            }
-            "CALL_SUBPROGRAM" => {
-                if parts.len() < 4 {
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        "Invalid CALL_SUBPROGRAM format",
-                    ));
-                }
-                let label = parts[2]
-                    .parse()
-                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-                let arity = parts[3]
-                    .trim_matches('(')
-                    .trim_matches(')')
-                    .parse()
-                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-                Bytecode::CallSubProgram(label, arity)
-            }
+            "CALL_FUNCTION" => Bytecode::CallBuiltin(name(0)?, number(1)?),
+            "CALL_SUBPROGRAM" => Bytecode::CallSubProgram(target(0)?, number(1)?),
             "RETURN_VALUE" => Bytecode::Return,
-            "JUMP" => {
-                let addr = parts[2]
-                    .parse()
-                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-                Bytecode::Jump(addr)
-            }
-            "JUMP_IF_FALSE" => {
-                let addr = parts[2]
-                    .parse()
-                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-                Bytecode::JumpIfFalse(addr)
-            }
-            "LABEL" => Bytecode::Label(parts[2].to_string()),
+            "JUMP" => Bytecode::Jump(target(0)?),
+            "JUMP_IF_FALSE" => Bytecode::JumpIfFalse(target(0)?),
+            "LABEL" => Bytecode::Label(name(0)?),
             "HALT" => Bytecode::Halt,
             "UNARY_NOT" => Bytecode::Not,
             "UNARY_NEGATIVE" => Bytecode::Negate,
@@ -172,28 +527,48 @@ pub fn parse_bytecode_instructions(contents: &str) -> Result<Vec<Bytecode>, io::
             "MULTIPLE_DEREFERENCE" => Bytecode::MulDeref,
             "STORE" => Bytecode::Store,
             "ALLOC" => Bytecode::Alloc,
-            "ALLOC_MANY" => {
-                let count = parts[2]
-                    .parse()
-                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-                Bytecode::AllocMany(count)
-            }
+            "ALLOC_MANY" => Bytecode::AllocMany(number(0)?),
             "DUP" => Bytecode::Dup,
             "FREE_ADDR" => Bytecode::FreeAddr,
             "STORE_ADDR" => Bytecode::StoreAddr,
             "PUSH_SCOPE" => Bytecode::PushScope,
             "POP_SCOPE" => Bytecode::PopScope,
             "SWAP" => Bytecode::Swap,
+            "EXCHANGE" => Bytecode::Exchange,
+            "LOAD_SLOT" => Bytecode::LoadSlot(number(0)?),
+            "STORE_SLOT" => Bytecode::StoreSlot(number(0)?),
+            "BIND_SLOT" => Bytecode::BindSlot(number(0)?),
             _ => {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "Unknown bytecode instruction",
-                ))
+                return Err(error(format!(
+                    "unknown bytecode instruction '{}'",
+                    mnemonic
+                )))
             }
         };
         bytecode.push(instruction);
     }
-    Ok(bytecode)
+
+    for (position, line_number, target) in unresolved {
+        let addr = match target {
+            Target::Offset(offset) => offset,
+            Target::Label(label) => *labels.get(&label).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: undefined label '{}'", line_number + 1, label),
+                )
+            })?,
+        };
+        match &mut bytecode[position] {
+            Bytecode::Jump(a) | Bytecode::JumpIfFalse(a) | Bytecode::CallSubProgram(a, _) => {
+                *a = addr
+            }
+            _ => unreachable!("only jumps and calls have targets"),
+        }
+    }
+
+    let mut line_table = LineTable::new(spans);
+    line_table.set_sources(sources);
+    Ok((bytecode, line_table))
 }
 
 #[cfg(test)]
@@ -231,11 +606,16 @@ mod tests {
             Bytecode::PushScope,
             Bytecode::PopScope,
             Bytecode::Swap,
+            Bytecode::Exchange,
+            Bytecode::LoadSlot(0),
+            Bytecode::StoreSlot(1),
+            Bytecode::BindSlot(2),
         ];
 
         let file_path = "test/bytecode/test_bytecode.txt";
 
-        write_bytecode_to_file(&bytecode, file_path).expect("Failed to write bytecode to file");
+        write_bytecode_to_file(&bytecode, None, file_path)
+            .expect("Failed to write bytecode to file");
 
         let read_bytecode =
             read_bytecode_from_file(file_path).expect("Failed to read bytecode from file");
@@ -244,4 +624,192 @@ mod tests {
 
         std::fs::remove_file(file_path).expect("Failed to delete test file");
     }
+
+    #[test]
+    fn test_write_and_read_line_table() {
+        let bytecode = vec![
+            Bytecode::Constant(Value::new_int(1)),
+            Bytecode::Label("main".to_string()),
+            Bytecode::Halt,
+        ];
+        let mut line_table = LineTable::new(vec![
+            (Location::new(1, 1), Location::new(1, 9)),
+            (Location::new(2, 1), Location::new(2, 5)),
+            (
+                Location::new(3, 1).in_file(1),
+                Location::new(3, 7).in_file(1),
+            ),
+        ]);
+        line_table.set_sources(vec![
+            "examples/list/import.adl".to_string(),
+            "examples/list/lib.adl".to_string(),
+        ]);
+
+        let file_path = "test/bytecode/test_line_table.txt";
+
+        write_bytecode_to_file(&bytecode, Some(&line_table), file_path)
+            .expect("Failed to write bytecode to file");
+
+        let (read_bytecode, read_line_table) = read_bytecode_with_line_table_from_file(file_path)
+            .expect("Failed to read bytecode from file");
+
+        assert_eq!(bytecode, read_bytecode);
+        assert_eq!(line_table, read_line_table);
+
+        std::fs::remove_file(file_path).expect("Failed to delete test file");
+    }
+
+    #[test]
+    fn test_parse_listing() {
+        let listing = r#"
+# labels may be referenced before they are defined
+        JUMP              main   # skip the subprogram
+f:
+        LABEL             f
+        RETURN_VALUE
+main:
+        LOAD_CONST        "say \"hi\"\n"
+        LOAD_CONST        'legacy string'
+        LOAD_CONST        Null
+        CALL_SUBPROGRAM   f (0)
+        JUMP_IF_FALSE     7
+        CALL_FUNCTION     "odd name" (2)
+"#;
+        let bytecode = parse_bytecode_instructions(listing).unwrap();
+        assert_eq!(
+            bytecode,
+            vec![
+                Bytecode::Jump(3),
+                Bytecode::Label("f".to_string()),
+                Bytecode::Return,
+                Bytecode::Constant(Value::new_string("say \"hi\"\n".to_string())),
+                Bytecode::Constant(Value::new_string("legacy string".to_string())),
+                Bytecode::Constant(Value::Null),
+                Bytecode::CallSubProgram(1, 0),
+                Bytecode::JumpIfFalse(7),
+                Bytecode::CallBuiltin("odd name".to_string(), 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = parse_bytecode_instructions("JUMP nowhere").unwrap_err();
+        assert_eq!(error.to_string(), "line 1: undefined label 'nowhere'");
+        let error = parse_bytecode_instructions("HALT\nLOAD_CONST \"open").unwrap_err();
+        assert_eq!(error.to_string(), "line 2: unterminated string");
+        let error = parse_bytecode_instructions("a:\na:").unwrap_err();
+        assert_eq!(error.to_string(), "line 2: duplicate label 'a'");
+    }
+
+    mod round_trip {
+        use super::*;
+        use proptest::prelude::*;
+
+        fn value() -> impl Strategy<Value = Value> {
+            prop_oneof![
+                Just(Value::Null),
+                any::<i64>().prop_map(Value::new_int),
+                any::<i64>().prop_map(Value::new_address),
+                any::<f64>()
+                    .prop_filter("NaN is not equal to itself", |f| !f.is_nan())
+                    .prop_map(Value::new_float),
+                any::<bool>().prop_map(Value::new_bool),
+                any::<String>().prop_map(Value::new_string),
+            ]
+        }
+
+        fn instruction() -> impl Strategy<Value = Bytecode> {
+            let name = any::<String>;
+            let simple = prop_oneof![
+                Just(Bytecode::Halt),
+                Just(Bytecode::Return),
+                Just(Bytecode::Not),
+                Just(Bytecode::And),
+                Just(Bytecode::Or),
+                Just(Bytecode::Negate),
+                Just(Bytecode::Add),
+                Just(Bytecode::Sub),
+                Just(Bytecode::Mul),
+                Just(Bytecode::Div),
+                Just(Bytecode::Mod),
+                Just(Bytecode::Equal),
+                Just(Bytecode::NotEqual),
+                Just(Bytecode::Greater),
+                Just(Bytecode::Less),
+                Just(Bytecode::GreaterEqual),
+                Just(Bytecode::LessEqual),
+                Just(Bytecode::Pop),
+                Just(Bytecode::Deref),
+                Just(Bytecode::MulDeref),
+                Just(Bytecode::Store),
+                Just(Bytecode::Alloc),
+                Just(Bytecode::Dup),
+                Just(Bytecode::StoreAddr),
+                Just(Bytecode::FreeAddr),
+                Just(Bytecode::PushScope),
+                Just(Bytecode::PopScope),
+                Just(Bytecode::Swap),
+                Just(Bytecode::Exchange),
+            ];
+            prop_oneof![
+                simple,
+                value().prop_map(Bytecode::Constant),
+                name().prop_map(Bytecode::Label),
+                name().prop_map(Bytecode::StoreVar),
+                name().prop_map(Bytecode::LoadVar),
+                name().prop_map(Bytecode::BindAddr),
+                (name(), any::<usize>()).prop_map(|(n, a)| Bytecode::CallBuiltin(n, a)),
+                any::<usize>().prop_map(Bytecode::AllocMany),
+                any::<usize>().prop_map(Bytecode::LoadSlot),
+                any::<usize>().prop_map(Bytecode::StoreSlot),
+                any::<usize>().prop_map(Bytecode::BindSlot),
+                // small targets mostly land inside the program and get labels
+                prop_oneof![0..40usize, any::<usize>()].prop_map(Bytecode::Jump),
+                prop_oneof![0..40usize, any::<usize>()].prop_map(Bytecode::JumpIfFalse),
+                (prop_oneof![0..40usize, any::<usize>()], any::<usize>())
+                    .prop_map(|(t, a)| Bytecode::CallSubProgram(t, a)),
+            ]
+        }
+
+        fn span() -> impl Strategy<Value = (Location, Location)> {
+            (
+                any::<usize>(),
+                any::<usize>(),
+                any::<usize>(),
+                any::<usize>(),
+                0..3usize,
+            )
+                .prop_map(|(a, b, c, d, file)| {
+                    (
+                        Location::new(a, b).in_file(file),
+                        Location::new(c, d).in_file(file),
+                    )
+                })
+        }
+
+        proptest! {
+            #[test]
+            fn test_every_instruction(bytecode in prop::collection::vec(instruction(), 0..40)) {
+                let listing = disassemble(&bytecode, None).unwrap();
+                prop_assert_eq!(parse_bytecode_instructions(&listing).unwrap(), bytecode);
+            }
+
+            #[test]
+            fn test_line_table(
+                bytecode in prop::collection::vec(instruction(), 0..10),
+                spans in prop::collection::vec(span(), 0..10),
+            ) {
+                let mut line_table = LineTable::new(spans);
+                line_table.set_sources(vec![
+                    "examples/hello world.adl".to_string(),
+                    "examples/lib.adl".to_string(),
+                    "examples/lib/list.adl".to_string(),
+                ]);
+                let listing = disassemble(&bytecode, Some(&line_table)).unwrap();
+                let program = parse_bytecode_with_line_table(&listing).unwrap();
+                prop_assert_eq!(program, (bytecode, line_table));
+            }
+        }
+    }
 }
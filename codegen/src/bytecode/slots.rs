@@ -0,0 +1,61 @@
+//! Resolution of variable names to frame slots.
+//!
+//! Control may jump from one subprogram's code into another's, so a name
+//! gets the same slot in every frame: a frame has a slot for every variable
+//! of the program, numbered in order of first appearance.
+
+use std::collections::HashMap;
+
+use crate::bytecode::Bytecode;
+
+/// Rewrites `LoadVar`, `StoreVar` and `BindAddr` in place into their slot
+/// counterparts and returns the variable names, indexed by slot. Offsets
+/// are kept, so jump targets and line tables stay valid.
+pub fn resolve_slots(bytecode: &mut [Bytecode]) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut slots: HashMap<String, usize> = HashMap::new();
+    let mut slot_of = |name: &str| {
+        *slots.entry(name.to_string()).or_insert_with(|| {
+            names.push(name.to_string());
+            names.len() - 1
+        })
+    };
+    for instruction in bytecode.iter_mut() {
+        let resolved = match instruction {
+            Bytecode::LoadVar(name) => Bytecode::LoadSlot(slot_of(name)),
+            Bytecode::StoreVar(name) => Bytecode::StoreSlot(slot_of(name)),
+            Bytecode::BindAddr(name) => Bytecode::BindSlot(slot_of(name)),
+            _ => continue,
+        };
+        *instruction = resolved;
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_slots() {
+        let mut bytecode = vec![
+            Bytecode::StoreVar("i".to_string()),
+            Bytecode::LoadVar("x".to_string()),
+            Bytecode::BindAddr("i".to_string()),
+            Bytecode::Add,
+            Bytecode::LoadVar("x".to_string()),
+        ];
+        let names = resolve_slots(&mut bytecode);
+        assert_eq!(names, ["i", "x"]);
+        assert_eq!(
+            bytecode,
+            [
+                Bytecode::StoreSlot(0),
+                Bytecode::LoadSlot(1),
+                Bytecode::BindSlot(0),
+                Bytecode::Add,
+                Bytecode::LoadSlot(1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,61 @@
+use common::location::Location;
+
+pub enum CompileError {
+    NotAddressable(Location, Location, String),
+    UndefinedLabel(Location, Location, String),
+    MalformedSubProgramHeader(Location, Location, String),
+    ArityMismatch(Location, Location, String, usize, usize),
+    Unsupported(Location, Location, String),
+}
+
+impl CompileError {
+    pub fn location(&self) -> (Location, Location) {
+        match self {
+            CompileError::NotAddressable(l, r, _)
+            | CompileError::UndefinedLabel(l, r, _)
+            | CompileError::MalformedSubProgramHeader(l, r, _)
+            | CompileError::ArityMismatch(l, r, ..)
+            | CompileError::Unsupported(l, r, _) => (*l, *r),
+        }
+    }
+}
+
+impl std::fmt::Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompileError::NotAddressable(left_loc, right_loc, target) => write!(
+                f,
+                "Cannot assign to {} between {} and {}: it does not evaluate to an address",
+                target, left_loc, right_loc
+            ),
+            CompileError::UndefinedLabel(left_loc, right_loc, label) => write!(
+                f,
+                "Undefined label '{}' between {} and {}",
+                label, left_loc, right_loc
+            ),
+            CompileError::MalformedSubProgramHeader(left_loc, right_loc, sp_name) => write!(
+                f,
+                "Malformed header of subprogram '{}' between {} and {}: expected `null => parameter` statements",
+                sp_name, left_loc, right_loc
+            ),
+            CompileError::ArityMismatch(left_loc, right_loc, sp_name, expected, actual) => write!(
+                f,
+                "Subprogram '{}' expects {} arguments, but {} were given between {} and {}",
+                sp_name, expected, actual, left_loc, right_loc
+            ),
+            CompileError::Unsupported(left_loc, right_loc, construct) => write!(
+                f,
+                "Unsupported construct between {} and {}: {}",
+                left_loc, right_loc, construct
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+impl std::fmt::Debug for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        <CompileError as std::fmt::Display>::fmt(self, f)
+    }
+}
@@ -1,16 +1,29 @@
 use std::collections::HashMap;
 
+use common::location::Location;
 use parser::ast::{visitor::Visitor, *};
 use value::Value;
 
-use crate::bytecode::Bytecode;
+use crate::bytecode::{line_table::LineTable, Bytecode};
+use crate::error::CompileError;
 pub struct BytecodeGenerator<'a> {
     bytecode: Vec<Bytecode>,
+    errors: Vec<CompileError>,
     labels: HashMap<String, usize>,
-    jumps: Vec<(usize, String)>,
+    jumps: Vec<PendingJump>,
     loop_context: Vec<LoopContext>,
-    ast: &'a Algorithm,      // Reference to the AST
-    current_position: usize, // Track the current position in the AST
+    ast: &'a Algorithm,                           // Reference to the AST
+    current_position: usize,                      // Track the current position in the AST
+    statement_span: Option<(Location, Location)>, // Span of the statement being generated
+    spans: Vec<(Location, Location)>,             // Span of every emitted instruction
+}
+
+/// A jump or call whose target label is patched in `resolve_jumps`.
+struct PendingJump {
+    position: usize,
+    label: String,
+    l_location: Location,
+    r_location: Location,
 }
 
 struct LoopContext {
@@ -22,32 +35,102 @@ impl<'a> BytecodeGenerator<'a> {
     pub fn new(ast: &'a Algorithm) -> Self {
         Self {
             bytecode: Vec::new(),
+            errors: Vec::new(),
             labels: HashMap::new(),
             jumps: Vec::new(),
             loop_context: Vec::new(),
             ast,
             current_position: 0,
+            statement_span: None,
+            spans: Vec::new(),
         }
     }
 
-    pub fn get_bytecode(mut self) -> Vec<Bytecode> {
+    pub fn get_bytecode(self) -> Result<Vec<Bytecode>, Vec<CompileError>> {
+        self.get_program().map(|(bytecode, _)| bytecode)
+    }
+
+    /// Returns the bytecode together with the span of every instruction.
+    pub fn get_program(mut self) -> Result<(Vec<Bytecode>, LineTable), Vec<CompileError>> {
         self.resolve_jumps();
-        self.bytecode
+        if self.errors.is_empty() {
+            // trailing labels belong to no statement
+            self.spans.resize(self.bytecode.len(), Default::default());
+            Ok((self.bytecode, LineTable::new(self.spans)))
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    /// Runs `generate` on a fresh generator inside a statement spanning
+    /// `span` and returns the instructions it emitted. For code without
+    /// control flow, which the IR builder puts in a basic block.
+    pub(crate) fn straight_line(
+        ast: &Algorithm,
+        span: (Location, Location),
+        generate: impl FnOnce(&mut BytecodeGenerator),
+    ) -> Result<(Vec<Bytecode>, LineTable), Vec<CompileError>> {
+        let mut generator = BytecodeGenerator::new(ast);
+        let outer = generator.enter_statement(span);
+        generate(&mut generator);
+        generator.leave_statement(outer);
+        generator.get_program()
+    }
+
+    pub(crate) fn emit(&mut self, instruction: Bytecode) {
+        self.bytecode.push(instruction);
+    }
+
+    fn span(&self) -> (Location, Location) {
+        self.statement_span.unwrap_or_default()
+    }
+
+    /// Attributes the instructions emitted since the last call to the
+    /// statement being generated. Outside of any statement (labels between
+    /// lines) they are left for the next statement.
+    fn sync_spans(&mut self) {
+        if let Some(span) = self.statement_span {
+            self.spans.resize(self.bytecode.len(), span);
+        }
+    }
+
+    fn enter_statement(&mut self, span: (Location, Location)) -> Option<(Location, Location)> {
+        self.sync_spans();
+        self.statement_span.replace(span)
+    }
+
+    fn leave_statement(&mut self, outer: Option<(Location, Location)>) {
+        self.sync_spans();
+        self.statement_span = outer;
+    }
+
+    fn push_jump(&mut self, position: usize, label: String) {
+        let (l_location, r_location) = self.span();
+        self.jumps.push(PendingJump {
+            position,
+            label,
+            l_location,
+            r_location,
+        });
     }
 
     fn resolve_jumps(&mut self) {
-        for (pos, label) in &self.jumps {
-            if let Some(&address) = self.labels.get(label) {
-                self.bytecode[*pos] = match self.bytecode[*pos] {
+        for jump in &self.jumps {
+            if let Some(&address) = self.labels.get(&jump.label) {
+                self.bytecode[jump.position] = match self.bytecode[jump.position] {
                     Bytecode::Jump(_) => Bytecode::Jump(address),
                     Bytecode::JumpIfFalse(_) => Bytecode::JumpIfFalse(address),
                     Bytecode::CallSubProgram(offset, arity) => {
                         Bytecode::CallSubProgram(offset + address, arity)
                     }
-                    _ => todo!(),
+                    ref other => unreachable!("{:?} is not a jump", other),
                 }
             } else {
-                panic!("Undefined label: {}", label);
+                self.errors.push(CompileError::UndefinedLabel(
+                    jump.l_location,
+                    jump.r_location,
+                    jump.label.clone(),
+                ));
             }
         }
     }
@@ -75,59 +158,182 @@ impl<'a> BytecodeGenerator<'a> {
         }
     }
 
-    fn bind_names(&mut self, name: &str, arity: usize) -> Vec<String> {
+    /// Collects the parameter names declared by the header of subprogram
+    /// `name`: a line labelled `name` made of `null => param` statements.
+    fn bind_names(&self, name: &str, arity: usize) -> Result<Vec<String>, CompileError> {
+        let Algorithm::Body(lines) = self.ast;
+        let Some(statements) = lines.iter().find_map(|line| match line {
+            FileLine::Line { labels, statements } if labels.iter().any(|l| l == name) => {
+                Some(statements)
+            }
+            _ => None,
+        }) else {
+            // an unknown subprogram is reported by `resolve_jumps`
+            return Ok(vec![]);
+        };
+
         let mut local_names: Vec<String> = vec![];
-        match self.ast {
-            Algorithm::Body(lines) => {
-                for FileLine::Line { labels, statements } in lines {
-                    if labels.contains(&name.to_string()) {
-                        match statements {
-                            Statements::OneLineStatement(_) => todo!(),
-                            Statements::SimpleStatements(stmts) => {
-                                for stmt in stmts.into_iter() {
-                                    match &stmt.node {
-                                        SimpleStatementKind::Send { lhs, rhs } => match &lhs.node {
-                                            ExpressionKind::Var { name } => {
-                                                local_names.push(name.to_string())
-                                            }
-                                            _ => todo!(),
-                                        },
-                                        _ => todo!(),
-                                    }
-                                }
-                                // self.bytecode.push(Bytecode::BindArgs(local_names.clone()));
-                                return local_names;
+        match statements {
+            Statements::OneLineStatement(statement) => {
+                return Err(CompileError::MalformedSubProgramHeader(
+                    statement.l_location,
+                    statement.r_location,
+                    name.to_string(),
+                ))
+            }
+            Statements::SimpleStatements(stmts) => {
+                for stmt in stmts {
+                    match &stmt.node {
+                        SimpleStatementKind::Send { lhs, rhs } => match (&lhs.node, &rhs.node) {
+                            (ExpressionKind::Var { name }, ExpressionKind::Null) => {
+                                local_names.push(name.to_string())
                             }
+                            _ => {
+                                return Err(CompileError::MalformedSubProgramHeader(
+                                    stmt.l_location,
+                                    stmt.r_location,
+                                    name.to_string(),
+                                ))
+                            }
+                        },
+                        _ => {
+                            return Err(CompileError::MalformedSubProgramHeader(
+                                stmt.l_location,
+                                stmt.r_location,
+                                name.to_string(),
+                            ))
                         }
                     }
                 }
             }
         }
-        return local_names;
+
+        if local_names.len() != arity {
+            let (l_location, r_location) = self.span();
+            return Err(CompileError::ArityMismatch(
+                l_location,
+                r_location,
+                name.to_string(),
+                local_names.len(),
+                arity,
+            ));
+        }
+        Ok(local_names)
+    }
+
+    /// Binds the arguments on the stack to the parameters of subprogram
+    /// `name`, the last argument first.
+    pub(crate) fn bind_parameters(&mut self, name: &str, arity: usize) {
+        let local_variables = match self.bind_names(name, arity) {
+            Ok(names) => names,
+            Err(e) => {
+                self.errors.push(e);
+                vec![]
+            }
+        };
+        for local_var in local_variables.iter().rev() {
+            self.bytecode
+                .push(Bytecode::BindAddr(local_var.to_string()));
+        }
+    }
+
+    /// Lowers an expression whose value is used as an address. An integer
+    /// literal there is the address of that cell, as in `x = 5` or `'5`.
+    fn generate_address(&mut self, expr: &Expression) {
+        match expr.node {
+            ExpressionKind::Int { value } => self
+                .bytecode
+                .push(Bytecode::Constant(Value::new_address(value))),
+            _ => expr.accept(self),
+        }
     }
 
+    /// Lowers `lhs = rhs`. A name is rebound to the address `rhs` evaluates
+    /// to; any other target is an expression whose value receives `rhs`,
+    /// which the VM checks is an address when storing to it.
+    fn generate_assign(&mut self, lhs: &Expression, rhs: &Expression) {
+        // literals other than integers, the address of their cell as in
+        // `generate_address`, never evaluate to an address
+        let target = match &lhs.node {
+            ExpressionKind::Null => Some("null"),
+            ExpressionKind::Float { .. } => Some("a float literal"),
+            ExpressionKind::Bool { .. } => Some("a boolean literal"),
+            ExpressionKind::String { .. } => Some("a string literal"),
+            ExpressionKind::List { .. } => Some("a list literal"),
+            _ => None,
+        };
+        if let Some(target) = target {
+            self.errors.push(CompileError::NotAddressable(
+                lhs.l_location,
+                lhs.r_location,
+                target.to_string(),
+            ));
+            return;
+        }
+
+        if let ExpressionKind::Var { name } = &lhs.node {
+            self.generate_address(rhs);
+            self.bytecode.push(Bytecode::BindAddr(name.to_string()));
+            return;
+        }
+        rhs.accept(self);
+        match &lhs.node {
+            // 'e = v stores v at the address e evaluates to
+            ExpressionKind::UnaryOp {
+                op: UnaryOp::Dereference,
+                expr,
+            } => self.generate_address(expr),
+            // D{e, n} = v stores v at the address reached by n - 1 dereferences
+            ExpressionKind::UnaryOp {
+                op: UnaryOp::MultipleDereference(n),
+                expr,
+            } => {
+                self.generate_address(expr);
+                n.accept(self);
+                self.bytecode.push(Bytecode::Constant(Value::new_int(1)));
+                self.bytecode.push(Bytecode::Sub);
+                self.bytecode.push(Bytecode::MulDeref);
+            }
+            // anything else, an integer literal or a computed address
+            _ => self.generate_address(lhs),
+        }
+        self.bytecode.push(Bytecode::Store);
+    }
+
+    /// Lowers a list literal. Every node is one block of two cells, the link
+    /// at its address and the value right after it, built from the head on
+    /// while the stack holds the head and the node before the current one.
     fn generate_list(&mut self, elements: &[Box<Expression>]) {
-        if elements.is_empty() {
+        let Some((head, tail)) = elements.split_first() else {
             self.bytecode.push(Bytecode::Constant(Value::Null));
             return;
+        };
+        self.generate_list_node(head); // stack: head
+        self.generate_copy();
+        for e in tail {
+            self.generate_copy(); // stack: head, previous, previous
+            self.generate_list_node(e); // stack: head, previous, previous, node
+            self.bytecode.push(Bytecode::Swap);
+            self.bytecode.push(Bytecode::Store); // link the previous node to this one
+            self.bytecode.push(Bytecode::Deref); // stack: head, node
         }
-        // allocate last element
-        // self.bytecode.push(Bytecode::Store);
         self.bytecode.push(Bytecode::Constant(Value::Null));
-        self.bytecode.push(Bytecode::StoreAddr); // stack: address to last element next address
-        elements.last().unwrap().accept(self);
-        self.bytecode.push(Bytecode::Alloc);
-        self.bytecode.push(Bytecode::Store);
+        self.bytecode.push(Bytecode::Swap);
+        self.bytecode.push(Bytecode::Store); // the last node links to null
+    }
 
-        let tail = &elements[0..elements.len() - 1];
-        for e in tail.iter().rev() {
-            self.bytecode.push(Bytecode::StoreAddr);
-            e.accept(self);
-            self.bytecode.push(Bytecode::Alloc); // stack:  address to last element value, address to previos element value
-                                                 // self.bytecode.push(Bytecode::Swap);           // stack: address to previos element value, stack: address to last element value
-            self.bytecode.push(Bytecode::Store); // stack: address to previos element value
-                                                 //  stack: address to last element value
-        }
+    /// Pushes one more copy of the top of the stack; `Dup` pushes two.
+    fn generate_copy(&mut self) {
+        self.bytecode.push(Bytecode::Dup);
+        self.bytecode.push(Bytecode::Pop);
+    }
+
+    /// Allocates a list node holding `element` and leaves its address.
+    fn generate_list_node(&mut self, element: &Expression) {
+        self.bytecode.push(Bytecode::AllocMany(2)); // stack: link, value
+        element.accept(self);
+        self.bytecode.push(Bytecode::Swap);
+        self.bytecode.push(Bytecode::Store);
     }
 }
 
@@ -168,7 +374,9 @@ impl<'a> Visitor for BytecodeGenerator<'a> {
     }
 
     fn visit_one_line_statement(&mut self, statement: &OneLineStatement) {
+        let outer = self.enter_statement((statement.l_location, statement.r_location));
         statement.node.accept(self);
+        self.leave_statement(outer);
     }
 
     fn visit_one_line_statement_kind(&mut self, kind: &OneLineStatementKind) {
@@ -234,8 +442,8 @@ impl<'a> Visitor for BytecodeGenerator<'a> {
 
                     // Resolve the conditional jump to the end of the loop
                     match label_to {
-                        Some(l) => self.jumps.push((jump_if_false_pos, l.clone())),
-                        None => self.jumps.push((jump_if_false_pos, end_label.clone())),
+                        Some(l) => self.push_jump(jump_if_false_pos, l.clone()),
+                        None => self.push_jump(jump_if_false_pos, end_label.clone()),
                     }
 
                     // Link the label_until to the end of the loop
@@ -244,14 +452,19 @@ impl<'a> Visitor for BytecodeGenerator<'a> {
                     // Restore the previous loop context
                     self.loop_context.pop();
                 } else {
-                    // Handle Address case
-                    todo!();
+                    let (l_location, r_location) = self.span();
+                    self.errors.push(CompileError::Unsupported(
+                        l_location,
+                        r_location,
+                        "a loop over a computed address; the loop parameter must be a name"
+                            .to_string(),
+                    ));
                 }
             }
             OneLineStatementKind::UnconditionalJump { label } => {
                 let jump_pos = self.bytecode.len();
                 self.bytecode.push(Bytecode::Jump(0)); // Placeholder
-                self.jumps.push((jump_pos, label.clone()));
+                self.push_jump(jump_pos, label.clone());
             }
             OneLineStatementKind::SubProgram {
                 sp_name,
@@ -265,16 +478,11 @@ impl<'a> Visitor for BytecodeGenerator<'a> {
                 }
 
                 self.bytecode.push(Bytecode::PushScope);
-
-                let local_variables = self.bind_names(&sp_name.identifier, args.len());
-                for local_var in local_variables.iter().rev() {
-                    self.bytecode
-                        .push(Bytecode::BindAddr(local_var.to_string()));
-                }
+                self.bind_parameters(&sp_name.identifier, args.len());
 
                 // Call the subprogram
                 let jump_pos = self.bytecode.len();
-                self.jumps.push((jump_pos, sp_name.identifier.clone()));
+                self.push_jump(jump_pos, sp_name.identifier.clone());
 
                 self.bytecode
                     .push(Bytecode::CallSubProgram(args.len() * 3 + 1, args.len()));
@@ -326,57 +534,34 @@ impl<'a> Visitor for BytecodeGenerator<'a> {
     }
 
     fn visit_simple_statement(&mut self, statement: &SimpleStatement) {
+        let outer = self.enter_statement((statement.l_location, statement.r_location));
         statement.node.accept(self);
+        self.leave_statement(outer);
     }
 
     fn visit_simple_statement_kind(&mut self, kind: &SimpleStatementKind) {
         match kind {
-            SimpleStatementKind::Assign { lhs, rhs } => {
-                rhs.accept(self);
-                match &lhs.node {
-                    ExpressionKind::Null => todo!(),
-                    ExpressionKind::Float { value } => todo!(),
-                    ExpressionKind::Bool { value } => todo!(),
-                    ExpressionKind::Int { value } => todo!(),
-                    ExpressionKind::String { value } => todo!(),
-                    ExpressionKind::Var { name } => {
-                        self.bytecode.push(Bytecode::BindAddr(name.to_string()));
-                    }
-                    ExpressionKind::List { elements } => todo!(),
-                    ExpressionKind::Call { .. } => todo!(),
-                    ExpressionKind::UnaryOp { op, expr } => match op {
-                        UnaryOp::Dereference => {
-                            expr.accept(self);
-                            self.bytecode.push(Bytecode::Store);
-                        }
-                        UnaryOp::MultipleDereference(n) => {
-                            expr.accept(self);
-                            n.accept(self);
-                            self.bytecode.push(Bytecode::Constant(Value::new_int(1)));
-                            self.bytecode.push(Bytecode::Sub);
-                            self.bytecode.push(Bytecode::MulDeref);
-                            self.bytecode.push(Bytecode::Store);
-                        }
-                        UnaryOp::Not => todo!(),
-                        UnaryOp::Minus => todo!(),
-                    },
-                    ExpressionKind::BinaryOp { .. } => todo!(),
-                }
+            SimpleStatementKind::Assign { lhs, rhs } => self.generate_assign(lhs, rhs),
+            // the value is not used, and the stack must be as deep after
+            // the statement as before it for the verifier
+            SimpleStatementKind::Expression { expression } => {
+                expression.accept(self);
+                self.bytecode.push(Bytecode::Pop);
             }
-            SimpleStatementKind::Expression { expression } => expression.accept(self),
-            SimpleStatementKind::Import {
-                labels,
-                path,
-                alias,
-            } => todo!(),
+            // imports are resolved by the linker before code generation
+            SimpleStatementKind::Import { .. } => {}
             SimpleStatementKind::Send { lhs, rhs } => {
                 rhs.accept(self);
-                lhs.accept(self);
+                self.generate_address(lhs);
                 self.bytecode.push(Bytecode::Store);
             }
-            SimpleStatementKind::Exchange { lhs, rhs } => todo!(),
+            SimpleStatementKind::Exchange { lhs, rhs } => {
+                self.generate_address(lhs);
+                self.generate_address(rhs);
+                self.bytecode.push(Bytecode::Exchange);
+            }
             SimpleStatementKind::Del { rhs } => {
-                rhs.accept(self);
+                self.generate_address(rhs);
                 self.bytecode.push(Bytecode::FreeAddr);
             }
         }
@@ -407,6 +592,8 @@ impl<'a> Visitor for BytecodeGenerator<'a> {
                     BinaryOp::NE => self.bytecode.push(Bytecode::NotEqual), // Example only
                     BinaryOp::GT => self.bytecode.push(Bytecode::Greater),
                     BinaryOp::LT => self.bytecode.push(Bytecode::Less),
+                    BinaryOp::GE => self.bytecode.push(Bytecode::GreaterEqual),
+                    BinaryOp::LE => self.bytecode.push(Bytecode::LessEqual),
                 }
             }
             ExpressionKind::Null => self.bytecode.push(Bytecode::Constant(Value::Null)),
@@ -428,7 +615,12 @@ impl<'a> Visitor for BytecodeGenerator<'a> {
                     .push(Bytecode::CallBuiltin(function.to_string(), args.len()))
             }
             ExpressionKind::UnaryOp { op, expr } => {
-                expr.accept(self);
+                match op {
+                    UnaryOp::Dereference | UnaryOp::MultipleDereference(_) => {
+                        self.generate_address(expr)
+                    }
+                    UnaryOp::Not | UnaryOp::Minus => expr.accept(self),
+                }
                 match op {
                     UnaryOp::Dereference => self.bytecode.push(Bytecode::Deref),
                     UnaryOp::MultipleDereference(expr) => {
@@ -456,14 +648,15 @@ mod tests {
         let mut generator = BytecodeGenerator::new(&algo);
         generator.visit_algorithm(&algo);
 
-        let bytecode = generator.get_bytecode();
+        let bytecode = generator.get_bytecode().unwrap();
         println!("{:?}", bytecode);
         assert_eq!(
             bytecode,
             vec![
                 Bytecode::Constant(Value::new_int(5)),
                 Bytecode::Constant(Value::new_int(3)),
-                Bytecode::Add
+                Bytecode::Add,
+                Bytecode::Pop,
             ]
         );
     }
@@ -476,14 +669,15 @@ mod tests {
         let mut generator = BytecodeGenerator::new(&algo);
         generator.visit_algorithm(&algo);
 
-        let bytecode = generator.get_bytecode();
+        let bytecode = generator.get_bytecode().unwrap();
         println!("{:?}", bytecode);
         assert_eq!(
             bytecode,
             vec![
                 Bytecode::LoadVar("var".to_string()),
                 Bytecode::Constant(Value::Int(4)),
-                Bytecode::MulDeref
+                Bytecode::MulDeref,
+                Bytecode::Pop,
             ]
         );
     }
@@ -496,11 +690,15 @@ mod tests {
         let mut generator = BytecodeGenerator::new(&algo);
         generator.visit_algorithm(&algo);
 
-        let bytecode = generator.get_bytecode();
+        let bytecode = generator.get_bytecode().unwrap();
         println!("{:?}", bytecode);
         assert_eq!(
             bytecode,
-            vec![Bytecode::Constant(Value::Int(4)), Bytecode::Deref]
+            vec![
+                Bytecode::Constant(Value::new_address(4)),
+                Bytecode::Deref,
+                Bytecode::Pop,
+            ]
         );
     }
 
@@ -512,11 +710,15 @@ mod tests {
         let mut generator = BytecodeGenerator::new(&algo);
         generator.visit_algorithm(&algo);
 
-        let bytecode = generator.get_bytecode();
+        let bytecode = generator.get_bytecode().unwrap();
         println!("{:?}", bytecode);
         assert_eq!(
             bytecode,
-            vec![Bytecode::Constant(Value::new_bool(true)), Bytecode::Not]
+            vec![
+                Bytecode::Constant(Value::new_bool(true)),
+                Bytecode::Not,
+                Bytecode::Pop,
+            ]
         );
     }
 
@@ -528,7 +730,7 @@ mod tests {
         let mut generator = BytecodeGenerator::new(&algo);
         generator.visit_algorithm(&algo);
 
-        let bytecode = generator.get_bytecode();
+        let bytecode = generator.get_bytecode().unwrap();
         println!("{:?}", bytecode);
         assert_eq!(
             bytecode,
@@ -536,10 +738,12 @@ mod tests {
                 Bytecode::Constant(Value::new_int(5)),
                 Bytecode::Constant(Value::new_int(3)),
                 Bytecode::Less,
-                Bytecode::JumpIfFalse(6),
+                Bytecode::JumpIfFalse(7),
                 Bytecode::Constant(Value::new_int(1)),
-                Bytecode::Jump(7),
+                Bytecode::Pop,
+                Bytecode::Jump(9),
                 Bytecode::Constant(Value::new_int(2)),
+                Bytecode::Pop,
             ]
         );
     }
@@ -555,7 +759,7 @@ mod tests {
         let mut generator = BytecodeGenerator::new(&algo);
         generator.visit_algorithm(&algo);
 
-        let bytecode = generator.get_bytecode();
+        let bytecode = generator.get_bytecode().unwrap();
         println!("{:?}", bytecode);
         assert_eq!(
             bytecode,
@@ -569,12 +773,13 @@ mod tests {
                 Bytecode::Deref,
                 Bytecode::Constant(Value::new_int(6)),
                 Bytecode::Less,
-                Bytecode::JumpIfFalse(23),
+                Bytecode::JumpIfFalse(24),
                 Bytecode::Label("loop_body_start_10".to_string()),
                 Bytecode::Constant(Value::new_string("i: ".to_string())),
                 Bytecode::LoadVar("i".to_string()),
                 Bytecode::Deref,
                 Bytecode::CallBuiltin("Print".to_string(), 2),
+                Bytecode::Pop,
                 Bytecode::Label("a".to_string()),
                 Bytecode::Constant(Value::new_int(1)),
                 Bytecode::LoadVar("i".to_string()),
@@ -603,23 +808,37 @@ mod tests {
         let mut generator = BytecodeGenerator::new(&algo);
         generator.visit_algorithm(&algo);
 
-        let bytecode = generator.get_bytecode();
+        let bytecode = generator.get_bytecode().unwrap();
         println!("{:?}", bytecode);
         assert_eq!(
             bytecode,
             vec![
-                Bytecode::Constant(Value::Null),
-                Bytecode::StoreAddr,
-                Bytecode::Constant(Value::new_int(3)),
-                Bytecode::Alloc,
+                Bytecode::AllocMany(2),
+                Bytecode::Constant(Value::new_int(1)),
+                Bytecode::Swap,
                 Bytecode::Store,
-                Bytecode::StoreAddr,
+                Bytecode::Dup,
+                Bytecode::Pop,
+                Bytecode::Dup,
+                Bytecode::Pop,
+                Bytecode::AllocMany(2),
                 Bytecode::Constant(Value::new_int(2)),
-                Bytecode::Alloc,
+                Bytecode::Swap,
                 Bytecode::Store,
-                Bytecode::StoreAddr,
-                Bytecode::Constant(Value::new_int(1)),
-                Bytecode::Alloc,
+                Bytecode::Swap,
+                Bytecode::Store,
+                Bytecode::Deref,
+                Bytecode::Dup,
+                Bytecode::Pop,
+                Bytecode::AllocMany(2),
+                Bytecode::Constant(Value::new_int(3)),
+                Bytecode::Swap,
+                Bytecode::Store,
+                Bytecode::Swap,
+                Bytecode::Store,
+                Bytecode::Deref,
+                Bytecode::Constant(Value::Null),
+                Bytecode::Swap,
                 Bytecode::Store,
                 Bytecode::BindAddr("l1".to_string()),
                 Bytecode::Constant(Value::new_int(1)),
@@ -632,9 +851,9 @@ mod tests {
                 Bytecode::BindAddr("e".to_string()),
                 Bytecode::BindAddr("index".to_string()),
                 Bytecode::BindAddr("list".to_string()),
-                Bytecode::CallSubProgram(38, 3),
+                Bytecode::CallSubProgram(52, 3),
                 Bytecode::PopScope,
-                Bytecode::Label("call_declaration_label_26".to_string()),
+                Bytecode::Label("call_declaration_label_40".to_string()),
                 Bytecode::Halt,
                 Bytecode::Label("get".to_string()),
                 Bytecode::Constant(Value::Null),
@@ -668,24 +887,39 @@ mod tests {
         let mut generator = BytecodeGenerator::new(&algo);
         generator.visit_algorithm(&algo);
 
-        let bytecode = generator.get_bytecode();
+        let bytecode = generator.get_bytecode().unwrap();
         println!("{:?}", bytecode);
         assert_eq!(
             bytecode,
             vec![
-                Bytecode::Constant(Value::Null),
-                Bytecode::StoreAddr,
-                Bytecode::Constant(Value::new_int(3)),
-                Bytecode::Alloc,
+                Bytecode::AllocMany(2),
+                Bytecode::Constant(Value::new_int(1)),
+                Bytecode::Swap,
                 Bytecode::Store,
-                Bytecode::StoreAddr,
+                Bytecode::Dup,
+                Bytecode::Pop,
+                Bytecode::Dup,
+                Bytecode::Pop,
+                Bytecode::AllocMany(2),
                 Bytecode::Constant(Value::new_int(2)),
-                Bytecode::Alloc,
+                Bytecode::Swap,
                 Bytecode::Store,
-                Bytecode::StoreAddr,
-                Bytecode::Constant(Value::new_int(1)),
-                Bytecode::Alloc,
+                Bytecode::Swap,
+                Bytecode::Store,
+                Bytecode::Deref,
+                Bytecode::Dup,
+                Bytecode::Pop,
+                Bytecode::AllocMany(2),
+                Bytecode::Constant(Value::new_int(3)),
+                Bytecode::Swap,
+                Bytecode::Store,
+                Bytecode::Swap,
                 Bytecode::Store,
+                Bytecode::Deref,
+                Bytecode::Constant(Value::Null),
+                Bytecode::Swap,
+                Bytecode::Store,
+                Bytecode::Pop,
             ]
         );
     }
@@ -698,17 +932,158 @@ mod tests {
         let mut generator = BytecodeGenerator::new(&algo);
         generator.visit_algorithm(&algo);
 
-        let bytecode = generator.get_bytecode();
+        let bytecode = generator.get_bytecode().unwrap();
         println!("{:?}", bytecode);
         assert_eq!(
             bytecode,
             vec![
-                Bytecode::Constant(Value::new_int(10)),
+                Bytecode::Constant(Value::new_address(10)),
                 Bytecode::BindAddr("x".to_string())
             ]
         );
     }
 
+    #[test]
+    fn test_visit_exchange_statement() {
+        let source_text = "a <=> 'b";
+        let algo: Algorithm = parser::parse(source_text).unwrap();
+
+        let mut generator = BytecodeGenerator::new(&algo);
+        generator.visit_algorithm(&algo);
+
+        let bytecode = generator.get_bytecode().unwrap();
+        assert_eq!(
+            bytecode,
+            vec![
+                Bytecode::LoadVar("a".to_string()),
+                Bytecode::LoadVar("b".to_string()),
+                Bytecode::Deref,
+                Bytecode::Exchange
+            ]
+        );
+    }
+
+    #[test]
+    fn test_visit_assign_to_computed_address() {
+        let source_text = "'(p + 2) = 10";
+        let algo: Algorithm = parser::parse(source_text).unwrap();
+
+        let mut generator = BytecodeGenerator::new(&algo);
+        generator.visit_algorithm(&algo);
+
+        let bytecode = generator.get_bytecode().unwrap();
+        assert_eq!(
+            bytecode,
+            vec![
+                Bytecode::Constant(Value::new_int(10)),
+                Bytecode::LoadVar("p".to_string()),
+                Bytecode::Constant(Value::new_int(2)),
+                Bytecode::Add,
+                Bytecode::Store
+            ]
+        );
+    }
+
+    #[test]
+    fn test_visit_assign_to_literal() {
+        let source_text = "\"text\" = 10";
+        let algo: Algorithm = parser::parse(source_text).unwrap();
+
+        let mut generator = BytecodeGenerator::new(&algo);
+        generator.visit_algorithm(&algo);
+
+        let errors = generator.get_bytecode().unwrap_err();
+        assert!(matches!(errors[..], [CompileError::NotAddressable(..)]));
+    }
+
+    #[test]
+    fn test_visit_assign_to_non_addresses() {
+        for source_text in ["null = 1", "1.5 = 1", "true = 1", "[1, 2] = 1"] {
+            let algo: Algorithm = parser::parse(source_text).unwrap();
+
+            let mut generator = BytecodeGenerator::new(&algo);
+            generator.visit_algorithm(&algo);
+
+            let errors = generator.get_bytecode().unwrap_err();
+            assert!(
+                matches!(errors[..], [CompileError::NotAddressable(..)]),
+                "{}",
+                source_text
+            );
+        }
+    }
+
+    #[test]
+    fn test_visit_assign_to_call() {
+        let source_text = "ToAddress {3} = 7";
+        let algo: Algorithm = parser::parse(source_text).unwrap();
+
+        let mut generator = BytecodeGenerator::new(&algo);
+        generator.visit_algorithm(&algo);
+
+        let bytecode = generator.get_bytecode().unwrap();
+        assert_eq!(
+            bytecode,
+            vec![
+                Bytecode::Constant(Value::new_int(7)),
+                Bytecode::Constant(Value::new_int(3)),
+                Bytecode::CallBuiltin("ToAddress".to_string(), 1),
+                Bytecode::Store
+            ]
+        );
+    }
+
+    #[test]
+    fn test_undefined_label() {
+        let source_text = "@nowhere";
+        let algo: Algorithm = parser::parse(source_text).unwrap();
+
+        let mut generator = BytecodeGenerator::new(&algo);
+        generator.visit_algorithm(&algo);
+
+        let errors = generator.get_bytecode().unwrap_err();
+        assert!(
+            matches!(&errors[..], [CompileError::UndefinedLabel(_, _, label)] if label == "nowhere")
+        );
+    }
+
+    #[test]
+    fn test_subprogram_header_errors() {
+        let source_text = "@main\nf ... null => a; 1 => b\nreturn\ng ... null => a\nreturn\nmain ... SP f {x}\nSP g {x, y}";
+        let algo: Algorithm = parser::parse(source_text).unwrap();
+
+        let mut generator = BytecodeGenerator::new(&algo);
+        generator.visit_algorithm(&algo);
+
+        let errors = generator.get_bytecode().unwrap_err();
+        assert!(matches!(
+            &errors[..],
+            [
+                CompileError::MalformedSubProgramHeader(..),
+                CompileError::ArityMismatch(_, _, _, 1, 2)
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_line_table() {
+        let source_text = "@end\nx = 10\nend ... Print {'x}";
+        let algo: Algorithm = parser::parse(source_text).unwrap();
+
+        let mut generator = BytecodeGenerator::new(&algo);
+        generator.visit_algorithm(&algo);
+
+        let (bytecode, line_table) = generator.get_program().unwrap();
+        assert_eq!(bytecode.len(), line_table.len());
+        let rows: Vec<usize> = line_table
+            .spans()
+            .iter()
+            .map(|(start, _)| start.row())
+            .collect();
+        // Jump | Constant, BindAddr | Label, LoadVar, Deref, CallBuiltin, Pop
+        assert_eq!(rows, vec![1, 2, 2, 3, 3, 3, 3, 3]);
+    }
+
     #[test]
     fn test_visit_logical_operations() {
         let source_text = "true and false or not true";
@@ -717,7 +1092,7 @@ mod tests {
         let mut generator = BytecodeGenerator::new(&algo);
         generator.visit_algorithm(&algo);
 
-        let bytecode = generator.get_bytecode();
+        let bytecode = generator.get_bytecode().unwrap();
         println!("{:?}", bytecode);
         assert_eq!(
             bytecode,
@@ -727,7 +1102,31 @@ mod tests {
                 Bytecode::And,
                 Bytecode::Constant(Value::new_bool(true)),
                 Bytecode::Not,
-                Bytecode::Or
+                Bytecode::Or,
+                Bytecode::Pop,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_visit_comparison_operations() {
+        let source_text = "1 <= 2 >= true";
+        let algo: Algorithm = parser::parse(source_text).unwrap();
+
+        let mut generator = BytecodeGenerator::new(&algo);
+        generator.visit_algorithm(&algo);
+
+        let bytecode = generator.get_bytecode().unwrap();
+        println!("{:?}", bytecode);
+        assert_eq!(
+            bytecode,
+            vec![
+                Bytecode::Constant(Value::new_int(1)),
+                Bytecode::Constant(Value::new_int(2)),
+                Bytecode::LessEqual,
+                Bytecode::Constant(Value::new_bool(true)),
+                Bytecode::GreaterEqual,
+                Bytecode::Pop,
             ]
         );
     }
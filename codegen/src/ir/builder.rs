@@ -0,0 +1,399 @@
+use std::collections::{HashMap, HashSet};
+
+use parser::ast::*;
+
+use super::{Block, BlockId, Cfg, Span, Terminator};
+use crate::{bytecode::Bytecode, error::CompileError, gen::BytecodeGenerator};
+
+/// Target of an edge to a label, patched once every label is known.
+const UNRESOLVED: BlockId = BlockId(usize::MAX);
+
+/// Builds the control-flow graph of `ast`. Statements without control flow
+/// are generated as `gen_bytecode` would, so lowering the graph gives a
+/// program that behaves the same.
+pub fn build(ast: &Algorithm) -> Result<Cfg, Vec<CompileError>> {
+    let mut builder = Builder {
+        ast,
+        blocks: Vec::new(),
+        order: Vec::new(),
+        current: BlockId(0),
+        labels: HashMap::new(),
+        subprograms: called_subprograms(ast),
+        entries: HashMap::new(),
+        edges: Vec::new(),
+        position: 0,
+        errors: Vec::new(),
+    };
+    let entry = builder.new_block();
+    builder.enter(entry);
+    let Algorithm::Body(lines) = ast;
+    while builder.position < lines.len() {
+        let FileLine::Line { labels, statements } = &lines[builder.position];
+        builder.place_labels(labels);
+        builder.line(labels, statements);
+        builder.position += 1;
+    }
+    builder.finish()
+}
+
+/// An edge to a label, resolved in `Builder::finish`.
+struct LabelEdge {
+    block: BlockId,
+    kind: EdgeKind,
+    label: String,
+    span: Span,
+}
+
+enum EdgeKind {
+    Jump,
+    IfFalse,
+    Call,
+}
+
+struct Builder<'a> {
+    ast: &'a Algorithm,
+    blocks: Vec<Block>,
+    /// Blocks in the order their code appears in the program.
+    order: Vec<BlockId>,
+    current: BlockId,
+    labels: HashMap<String, BlockId>,
+    /// Names of the subprograms called anywhere in the program.
+    subprograms: HashSet<String>,
+    /// Blocks starting past the header of each subprogram.
+    entries: HashMap<String, BlockId>,
+    edges: Vec<LabelEdge>,
+    /// Index of the line being built.
+    position: usize,
+    errors: Vec<CompileError>,
+}
+
+impl<'a> Builder<'a> {
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push(Block {
+            labels: Vec::new(),
+            code: Vec::new(),
+            spans: Vec::new(),
+            terminator: Terminator::Halt,
+            terminator_span: Span::default(),
+        });
+        BlockId(self.blocks.len() - 1)
+    }
+
+    /// Continues building in `block`, which follows the previous one.
+    fn enter(&mut self, block: BlockId) {
+        self.current = block;
+        self.order.push(block);
+    }
+
+    /// Ends the current block with `terminator` and enters `next`.
+    fn terminate(&mut self, terminator: Terminator, span: Span, next: BlockId) {
+        let block = &mut self.blocks[self.current.0];
+        block.terminator = terminator;
+        block.terminator_span = span;
+        self.enter(next);
+    }
+
+    /// Falls through from the current block to a new one.
+    fn fall_through(&mut self) -> BlockId {
+        let next = self.new_block();
+        self.terminate(Terminator::Jump(next), Span::default(), next);
+        next
+    }
+
+    fn edge_to_label(&mut self, kind: EdgeKind, label: &str, span: Span) {
+        self.edges.push(LabelEdge {
+            block: self.current,
+            kind,
+            label: label.to_string(),
+            span,
+        });
+    }
+
+    /// Appends what `generate` emits to the current block.
+    fn generate(&mut self, span: Span, generate: impl FnOnce(&mut BytecodeGenerator)) {
+        match BytecodeGenerator::straight_line(self.ast, span, generate) {
+            Ok((code, line_table)) => {
+                let block = &mut self.blocks[self.current.0];
+                block.code.extend(code);
+                block.spans.extend_from_slice(line_table.spans());
+            }
+            Err(errors) => self.errors.extend(errors),
+        }
+    }
+
+    fn place_labels(&mut self, labels: &[String]) {
+        if labels.is_empty() {
+            return;
+        }
+        let block = self.fall_through();
+        for label in labels {
+            self.labels.insert(label.clone(), block);
+        }
+        self.blocks[block.0].labels = labels.to_vec();
+    }
+
+    /// Builds the statements of a line. On the header of a called
+    /// subprogram, a block starts where calls enter.
+    fn line(&mut self, labels: &[String], statements: &Statements) {
+        self.statements(statements);
+        for label in labels {
+            if self.subprograms.contains(label) {
+                let entry = self.fall_through();
+                self.entries.insert(label.clone(), entry);
+            }
+        }
+    }
+
+    fn statements(&mut self, statements: &Statements) {
+        match statements {
+            Statements::OneLineStatement(statement) => self.one_line_statement(statement),
+            Statements::SimpleStatements(statements) => {
+                for statement in statements {
+                    let span = (statement.l_location, statement.r_location);
+                    self.generate(span, |generator| statement.accept(generator));
+                }
+            }
+        }
+    }
+
+    fn one_line_statement(&mut self, statement: &OneLineStatement) {
+        let span = (statement.l_location, statement.r_location);
+        match &statement.node {
+            OneLineStatementKind::Loop {
+                initial_value,
+                step,
+                last_value_or_condition,
+                iterator,
+                label_until,
+                label_to,
+            } => {
+                let ExpressionKind::Var { name } = &iterator.node else {
+                    self.errors.push(CompileError::Unsupported(
+                        span.0,
+                        span.1,
+                        "a loop over a computed address; the loop parameter must be a name"
+                            .to_string(),
+                    ));
+                    return;
+                };
+                self.generate(span, |generator| {
+                    initial_value.accept(generator);
+                    generator.emit(Bytecode::StoreVar(name.clone()));
+                    generator.emit(Bytecode::LoadVar(name.clone()));
+                    generator.emit(Bytecode::Store);
+                });
+                let header = self.fall_through();
+                self.generate(span, |generator| last_value_or_condition.accept(generator));
+                let (body, end) = (self.new_block(), self.new_block());
+                if let Some(label) = label_to {
+                    self.edge_to_label(EdgeKind::IfFalse, label, span);
+                }
+                let if_false = if label_to.is_some() { UNRESOLVED } else { end };
+                self.terminate(
+                    Terminator::Branch {
+                        if_true: body,
+                        if_false,
+                    },
+                    span,
+                    body,
+                );
+
+                self.position += 1;
+                self.loop_body(label_until);
+                self.generate(span, |generator| {
+                    step.accept(generator);
+                    generator.emit(Bytecode::LoadVar(name.clone()));
+                    generator.emit(Bytecode::Deref);
+                    generator.emit(Bytecode::Add);
+                    generator.emit(Bytecode::LoadVar(name.clone()));
+                    generator.emit(Bytecode::Store);
+                });
+                self.terminate(Terminator::Jump(header), span, end);
+                self.labels.insert(label_until.clone(), end);
+            }
+            OneLineStatementKind::UnconditionalJump { label } => {
+                self.edge_to_label(EdgeKind::Jump, label, span);
+                let next = self.new_block();
+                self.terminate(Terminator::Jump(UNRESOLVED), span, next);
+            }
+            OneLineStatementKind::SubProgram { sp_name, args, .. } => {
+                self.generate(span, |generator| {
+                    for arg in args {
+                        arg.accept(generator);
+                    }
+                    generator.emit(Bytecode::PushScope);
+                    generator.bind_parameters(&sp_name.identifier, args.len());
+                });
+                self.edge_to_label(EdgeKind::Call, &sp_name.identifier, span);
+                let continuation = self.new_block();
+                let call = Terminator::Call {
+                    entry: UNRESOLVED,
+                    arity: args.len(),
+                    continuation,
+                };
+                self.terminate(call, span, continuation);
+            }
+            OneLineStatementKind::Predicate {
+                condition,
+                if_true,
+                if_false,
+            } => {
+                self.generate(span, |generator| condition.accept(generator));
+                let (true_block, false_block, end) =
+                    (self.new_block(), self.new_block(), self.new_block());
+                let branch = Terminator::Branch {
+                    if_true: true_block,
+                    if_false: false_block,
+                };
+                self.terminate(branch, span, true_block);
+                self.statements(if_true);
+                self.terminate(Terminator::Jump(end), span, false_block);
+                self.statements(if_false);
+                self.terminate(Terminator::Jump(end), span, end);
+            }
+            OneLineStatementKind::Exit => {
+                let next = self.new_block();
+                self.terminate(Terminator::Halt, span, next);
+            }
+            OneLineStatementKind::Return => {
+                let next = self.new_block();
+                self.terminate(Terminator::Return, span, next);
+            }
+        }
+    }
+
+    /// Builds the lines of a loop body, up to the line labelled
+    /// `label_until`, whose labels mark where the step starts.
+    fn loop_body(&mut self, label_until: &str) {
+        let Algorithm::Body(lines) = self.ast;
+        while self.position < lines.len() {
+            let FileLine::Line { labels, statements } = &lines[self.position];
+            self.place_labels(labels);
+            if labels.iter().any(|label| label == label_until) {
+                break;
+            }
+            self.line(labels, statements);
+            self.position += 1;
+        }
+    }
+
+    /// Resolves the edges to labels and drops the empty blocks left
+    /// unreachable after jumps, returns and halts.
+    fn finish(mut self) -> Result<Cfg, Vec<CompileError>> {
+        for edge in std::mem::take(&mut self.edges) {
+            let target = match edge.kind {
+                EdgeKind::Call => self.entries.get(&edge.label),
+                EdgeKind::Jump | EdgeKind::IfFalse => self.labels.get(&edge.label),
+            };
+            let Some(&target) = target else {
+                self.errors.push(CompileError::UndefinedLabel(
+                    edge.span.0,
+                    edge.span.1,
+                    edge.label,
+                ));
+                continue;
+            };
+            match (&mut self.blocks[edge.block.0].terminator, edge.kind) {
+                (Terminator::Jump(block), EdgeKind::Jump)
+                | (
+                    Terminator::Branch {
+                        if_false: block, ..
+                    },
+                    EdgeKind::IfFalse,
+                )
+                | (Terminator::Call { entry: block, .. }, EdgeKind::Call) => *block = target,
+                _ => unreachable!("an edge to a label is patched into its terminator"),
+            }
+        }
+        if !self.errors.is_empty() {
+            return Err(self.errors);
+        }
+
+        let mut targeted = vec![false; self.blocks.len()];
+        targeted[self.order[0].0] = true;
+        for block in &self.blocks {
+            match block.terminator {
+                Terminator::Jump(target) => targeted[target.0] = true,
+                Terminator::Branch { if_true, if_false } => {
+                    targeted[if_true.0] = true;
+                    targeted[if_false.0] = true;
+                }
+                Terminator::Call {
+                    entry,
+                    continuation,
+                    ..
+                } => {
+                    targeted[entry.0] = true;
+                    targeted[continuation.0] = true;
+                }
+                Terminator::Return | Terminator::Halt => {}
+            }
+        }
+        let order: Vec<BlockId> = self
+            .order
+            .into_iter()
+            .filter(|block| {
+                let block_data = &self.blocks[block.0];
+                targeted[block.0] || !block_data.code.is_empty() || !block_data.labels.is_empty()
+            })
+            .collect();
+
+        let mut index = vec![UNRESOLVED; self.blocks.len()];
+        for (new, old) in order.iter().enumerate() {
+            index[old.0] = BlockId(new);
+        }
+        let mut blocks: Vec<Option<Block>> = self.blocks.into_iter().map(Some).collect();
+        let blocks = order
+            .iter()
+            .map(|old| {
+                let mut block = blocks[old.0].take().expect("a block is entered once");
+                match &mut block.terminator {
+                    Terminator::Jump(target) => *target = index[target.0],
+                    Terminator::Branch { if_true, if_false } => {
+                        *if_true = index[if_true.0];
+                        *if_false = index[if_false.0];
+                    }
+                    Terminator::Call {
+                        entry,
+                        continuation,
+                        ..
+                    } => {
+                        *entry = index[entry.0];
+                        *continuation = index[continuation.0];
+                    }
+                    Terminator::Return | Terminator::Halt => {}
+                }
+                block
+            })
+            .collect();
+        Ok(Cfg { blocks })
+    }
+}
+
+/// Names of the subprograms `ast` calls.
+fn called_subprograms(ast: &Algorithm) -> HashSet<String> {
+    fn visit(statements: &Statements, names: &mut HashSet<String>) {
+        let Statements::OneLineStatement(statement) = statements else {
+            return;
+        };
+        match &statement.node {
+            OneLineStatementKind::SubProgram { sp_name, .. } => {
+                names.insert(sp_name.identifier.clone());
+            }
+            OneLineStatementKind::Predicate {
+                if_true, if_false, ..
+            } => {
+                visit(if_true, names);
+                visit(if_false, names);
+            }
+            _ => {}
+        }
+    }
+
+    let Algorithm::Body(lines) = ast;
+    let mut names = HashSet::new();
+    for FileLine::Line { statements, .. } in lines {
+        visit(statements, &mut names);
+    }
+    names
+}
@@ -0,0 +1,72 @@
+use super::{Cfg, Terminator};
+use crate::bytecode::{line_table::LineTable, Bytecode};
+
+/// Lowers `cfg` to bytecode, laying blocks out in order. Jumps to the next
+/// block are left out, and every block starts with the labels of its line.
+pub fn lower(cfg: &Cfg) -> (Vec<Bytecode>, LineTable) {
+    let is_next = |index: usize, target: usize| target == index + 1;
+    let mut starts = Vec::with_capacity(cfg.blocks.len() + 1);
+    let mut offset = 0;
+    for (index, block) in cfg.blocks.iter().enumerate() {
+        starts.push(offset);
+        offset += block.labels.len() + block.code.len();
+        offset += match block.terminator {
+            Terminator::Jump(target) => usize::from(!is_next(index, target.0)),
+            Terminator::Branch { if_true, .. } => 1 + usize::from(!is_next(index, if_true.0)),
+            // a return resumes past the `PopScope` following the call
+            Terminator::Call { continuation, .. } => {
+                2 + usize::from(!is_next(index, continuation.0))
+            }
+            Terminator::Return | Terminator::Halt => 1,
+        };
+    }
+
+    let mut bytecode = Vec::with_capacity(offset);
+    let mut spans = Vec::with_capacity(offset);
+    for (index, block) in cfg.blocks.iter().enumerate() {
+        let mut emit = |instruction, span| {
+            bytecode.push(instruction);
+            spans.push(span);
+        };
+        // labels belong to the statement that follows them
+        let first_span = block
+            .spans
+            .first()
+            .copied()
+            .unwrap_or(block.terminator_span);
+        for label in &block.labels {
+            emit(Bytecode::Label(label.clone()), first_span);
+        }
+        for (instruction, span) in block.code.iter().zip(&block.spans) {
+            emit(instruction.clone(), *span);
+        }
+        let span = block.terminator_span;
+        match block.terminator {
+            Terminator::Jump(target) => {
+                if !is_next(index, target.0) {
+                    emit(Bytecode::Jump(starts[target.0]), span);
+                }
+            }
+            Terminator::Branch { if_true, if_false } => {
+                emit(Bytecode::JumpIfFalse(starts[if_false.0]), span);
+                if !is_next(index, if_true.0) {
+                    emit(Bytecode::Jump(starts[if_true.0]), span);
+                }
+            }
+            Terminator::Call {
+                entry,
+                arity,
+                continuation,
+            } => {
+                emit(Bytecode::CallSubProgram(starts[entry.0], arity), span);
+                emit(Bytecode::PopScope, span);
+                if !is_next(index, continuation.0) {
+                    emit(Bytecode::Jump(starts[continuation.0]), span);
+                }
+            }
+            Terminator::Return => emit(Bytecode::Return, span),
+            Terminator::Halt => emit(Bytecode::Halt, span),
+        }
+    }
+    (bytecode, LineTable::new(spans))
+}
@@ -0,0 +1,239 @@
+//! A control-flow graph between the AST and bytecode.
+//!
+//! Labels, loops, predicates and subprogram calls become explicit edges
+//! between basic blocks of straight-line bytecode, see `build` and `lower`.
+
+mod builder;
+mod lower;
+
+use std::fmt::Write;
+
+use common::location::Location;
+
+use crate::bytecode::Bytecode;
+
+pub use builder::build;
+pub use lower::lower;
+
+pub type Span = (Location, Location);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BlockId(pub usize);
+
+/// How control leaves a block.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Jump(BlockId),
+    /// Pops a condition and goes to `if_true` or `if_false`.
+    Branch {
+        if_true: BlockId,
+        if_false: BlockId,
+    },
+    /// Calls the subprogram entered at `entry`, past the header binding its
+    /// parameters. Its `Return` comes back to `continuation`.
+    Call {
+        entry: BlockId,
+        arity: usize,
+        continuation: BlockId,
+    },
+    Return,
+    Halt,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    /// Source labels of the line the block starts at.
+    pub labels: Vec<String>,
+    /// Instructions without labels, jumps, calls, returns or halts.
+    pub code: Vec<Bytecode>,
+    /// Span of the statement each instruction comes from.
+    pub spans: Vec<Span>,
+    pub terminator: Terminator,
+    pub terminator_span: Span,
+}
+
+/// Basic blocks in program order, the first being the entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cfg {
+    pub blocks: Vec<Block>,
+}
+
+impl Cfg {
+    pub fn block(&self, id: BlockId) -> &Block {
+        &self.blocks[id.0]
+    }
+
+    /// Blocks control may go to from `id`, a call returning to its
+    /// continuation.
+    pub fn successors(&self, id: BlockId) -> Vec<BlockId> {
+        match self.block(id).terminator {
+            Terminator::Jump(target) => vec![target],
+            Terminator::Branch { if_true, if_false } => vec![if_true, if_false],
+            Terminator::Call {
+                entry,
+                continuation,
+                ..
+            } => vec![entry, continuation],
+            Terminator::Return | Terminator::Halt => vec![],
+        }
+    }
+
+    /// Renders the graph as a Graphviz digraph: a box per block listing its
+    /// instructions, with labelled edges for branches and calls.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        dot.push_str("digraph cfg {\n");
+        dot.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+        for (index, block) in self.blocks.iter().enumerate() {
+            let mut label = format!("B{}", index);
+            if !block.labels.is_empty() {
+                write!(label, " ({})", block.labels.join(", ")).unwrap();
+            }
+            label.push('\n');
+            for instruction in &block.code {
+                writeln!(label, "{:?}", instruction).unwrap();
+            }
+            match block.terminator {
+                Terminator::Return => label.push_str("Return\n"),
+                Terminator::Halt => label.push_str("Halt\n"),
+                _ => {}
+            }
+            writeln!(dot, "    B{} [label={}];", index, quote(&label)).unwrap();
+        }
+        for (index, block) in self.blocks.iter().enumerate() {
+            let edges: Vec<(BlockId, &str)> = match block.terminator {
+                Terminator::Jump(target) => vec![(target, "")],
+                Terminator::Branch { if_true, if_false } => {
+                    vec![(if_true, "label=true"), (if_false, "label=false")]
+                }
+                Terminator::Call {
+                    entry,
+                    continuation,
+                    ..
+                } => vec![
+                    (entry, "label=call"),
+                    (continuation, "label=return, style=dashed"),
+                ],
+                Terminator::Return | Terminator::Halt => vec![],
+            };
+            for (target, attributes) in edges {
+                write!(dot, "    B{} -> B{}", index, target.0).unwrap();
+                if !attributes.is_empty() {
+                    write!(dot, " [{}]", attributes).unwrap();
+                }
+                dot.push_str(";\n");
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Quotes a node label, its lines left-justified.
+fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\l"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use parser::ast::Algorithm;
+
+    use super::*;
+    use crate::error::CompileError;
+
+    fn cfg(source_text: &str) -> Cfg {
+        let algo: Algorithm = parser::parse(source_text).unwrap();
+        build(&algo).unwrap()
+    }
+
+    #[test]
+    fn test_build_loop() {
+        let graph = cfg("L {1, 1, 'i < 3 => i} a\nPrint {'i}\na ...\n!\n");
+        let terminators: Vec<&Terminator> =
+            graph.blocks.iter().map(|block| &block.terminator).collect();
+        assert_eq!(
+            terminators,
+            [
+                &Terminator::Jump(BlockId(1)),
+                &Terminator::Branch {
+                    if_true: BlockId(2),
+                    if_false: BlockId(4),
+                },
+                &Terminator::Jump(BlockId(3)),
+                &Terminator::Jump(BlockId(1)),
+                &Terminator::Halt,
+            ]
+        );
+        assert_eq!(graph.block(BlockId(3)).labels, ["a"]);
+        assert_eq!(graph.successors(BlockId(1)), [BlockId(2), BlockId(4)]);
+    }
+
+    #[test]
+    fn test_build_call() {
+        let graph = cfg("SP sq {2}\n!\nsq... null => n\n'n * 'n => n\nreturn\n");
+        assert_eq!(
+            graph.block(BlockId(0)).terminator,
+            Terminator::Call {
+                entry: BlockId(3),
+                arity: 1,
+                continuation: BlockId(1),
+            }
+        );
+        assert_eq!(graph.block(BlockId(2)).labels, ["sq"]);
+        assert_eq!(graph.block(BlockId(3)).terminator, Terminator::Return);
+
+        let dot = graph.to_dot();
+        assert!(dot.contains("B0 -> B3 [label=call];"));
+        assert!(dot.contains("B0 -> B1 [label=return, style=dashed];"));
+        assert!(dot.contains("B2 (sq)"));
+    }
+
+    #[test]
+    fn test_lower() {
+        let algo: Algorithm =
+            parser::parse("P {'x < 3} @a | Print {'x}\na ... Print {1}\n").unwrap();
+        let (bytecode, line_table) = lower(&build(&algo).unwrap());
+        assert_eq!(line_table.spans().len(), bytecode.len());
+        assert_eq!(
+            bytecode,
+            [
+                Bytecode::LoadVar("x".to_string()),
+                Bytecode::Deref,
+                Bytecode::Constant(value::Value::Int(3)),
+                Bytecode::Less,
+                Bytecode::JumpIfFalse(6),
+                Bytecode::Jump(10),
+                Bytecode::LoadVar("x".to_string()),
+                Bytecode::Deref,
+                Bytecode::CallBuiltin("Print".to_string(), 1),
+                Bytecode::Pop,
+                Bytecode::Label("a".to_string()),
+                Bytecode::Constant(value::Value::Int(1)),
+                Bytecode::CallBuiltin("Print".to_string(), 1),
+                Bytecode::Pop,
+                Bytecode::Halt,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_undefined_label() {
+        let algo: Algorithm = parser::parse("@nowhere\n").unwrap();
+        let Err(errors) = build(&algo) else {
+            panic!("a jump to a missing label is an error");
+        };
+        assert!(
+            matches!(&errors[..], [CompileError::UndefinedLabel(_, _, label)] if label == "nowhere")
+        );
+    }
+}
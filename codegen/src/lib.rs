@@ -1,20 +1,35 @@
 use std::io;
 
-use bytecode::{serializer::write_bytecode_to_file, Bytecode};
+use bytecode::{line_table::LineTable, serializer::write_program_to_file, Bytecode};
+use error::CompileError;
 use gen::BytecodeGenerator;
 use parser::ast::{visitor::Visitor, Algorithm};
 
 pub mod bytecode;
+pub mod error;
 mod gen;
+pub mod ir;
+pub mod opt;
 
-pub fn gen_bytecode(ast: Algorithm) -> Vec<Bytecode> {
+pub fn gen_bytecode(ast: Algorithm) -> Result<Vec<Bytecode>, Vec<CompileError>> {
     let mut generator = BytecodeGenerator::new(&ast);
     generator.visit_algorithm(&ast);
     generator.get_bytecode()
 }
 
-pub fn gen_bytecode_to_file(ast: Algorithm, file_path: &str) -> Result<(), io::Error> {
+/// Like `gen_bytecode`, but also returns the span of every instruction.
+pub fn gen_bytecode_with_line_table(
+    ast: Algorithm,
+) -> Result<(Vec<Bytecode>, LineTable), Vec<CompileError>> {
     let mut generator = BytecodeGenerator::new(&ast);
     generator.visit_algorithm(&ast);
-    write_bytecode_to_file(&generator.get_bytecode(), file_path)
+    generator.get_program()
+}
+
+pub fn gen_bytecode_to_file(ast: Algorithm, file_path: &str) -> Result<(), io::Error> {
+    let (bytecode, line_table) = gen_bytecode_with_line_table(ast).map_err(|errors| {
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        io::Error::new(io::ErrorKind::InvalidData, messages.join("\n"))
+    })?;
+    write_program_to_file(&bytecode, Some(&line_table), file_path)
 }
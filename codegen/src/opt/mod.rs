@@ -0,0 +1,371 @@
+//! Optimizations of generated bytecode, all preserving what a program does.
+//!
+//! - labels, which do nothing at runtime, are removed and jumps retargeted
+//!   to the instruction that followed them;
+//! - jumps to jumps go to the final target, and jumps to the next
+//!   instruction are removed;
+//! - operations on constants are folded, except address arithmetic, which
+//!   the sanitizer checks at runtime, and operations that fail;
+//! - instructions no path reaches are removed;
+//! - constants popped right after being pushed are removed.
+//!
+//! A return resumes two instructions past its call, so the instruction
+//! following a call is always kept.
+
+use crate::bytecode::{line_table::LineTable, Bytecode};
+use common::location::Location;
+use value::{error::ValueError, Value};
+
+type Span = (Location, Location);
+type BinaryFn = fn(&Value, &Value) -> Result<Value, ValueError>;
+type UnaryFn = fn(&Value) -> Result<Value, ValueError>;
+
+/// Optimizes `bytecode`, keeping `line_table` in step with it.
+pub fn optimize(bytecode: Vec<Bytecode>, line_table: &LineTable) -> (Vec<Bytecode>, LineTable) {
+    let spans = (0..bytecode.len()).map(|i| line_table.get(i)).collect();
+    let mut program = Program {
+        code: bytecode,
+        spans,
+    };
+    program.strip_labels();
+    loop {
+        let folded = program.fold_constants();
+        let popped = program.remove_dead_pushes();
+        let threaded = program.thread_jumps();
+        let removed = program.remove_unreachable();
+        if !(folded || popped || threaded || removed) {
+            break;
+        }
+    }
+
+    let spans: Option<Vec<Span>> = program.spans.into_iter().collect();
+    let mut optimized_table = LineTable::new(spans.unwrap_or_default());
+    optimized_table.set_sources(line_table.sources().to_vec());
+    (program.code, optimized_table)
+}
+
+struct Program {
+    code: Vec<Bytecode>,
+    spans: Vec<Option<Span>>,
+}
+
+impl Program {
+    fn strip_labels(&mut self) -> bool {
+        let keep: Vec<bool> = self
+            .code
+            .iter()
+            .map(|instruction| !matches!(instruction, Bytecode::Label(_)))
+            .collect();
+        self.retain(keep)
+    }
+
+    fn fold_constants(&mut self) -> bool {
+        let targets = self.targets();
+        let mut keep = vec![true; self.code.len()];
+        let mut i = 0;
+        while i < self.code.len() {
+            let folded = match &self.code[i..] {
+                [Bytecode::Constant(lhs), Bytecode::Constant(rhs), op, ..]
+                    if !targets[i + 1] && !targets[i + 2] =>
+                {
+                    binary_op(op)
+                        .filter(|_| !is_address(lhs) && !is_address(rhs))
+                        .and_then(|op| op(lhs, rhs).ok())
+                        .map(|value| (value, 2))
+                }
+                [Bytecode::Constant(operand), op, ..] if !targets[i + 1] => unary_op(op)
+                    .filter(|_| !is_address(operand))
+                    .and_then(|op| op(operand).ok())
+                    .map(|value| (value, 1)),
+                _ => None,
+            };
+            match folded {
+                Some((value, operands)) => {
+                    keep[i..i + operands].fill(false);
+                    self.code[i + operands] = Bytecode::Constant(value);
+                    i += operands + 1;
+                }
+                None => i += 1,
+            }
+        }
+        self.retain(keep)
+    }
+
+    fn remove_dead_pushes(&mut self) -> bool {
+        let targets = self.targets();
+        let mut keep = vec![true; self.code.len()];
+        for i in 1..self.code.len() {
+            if let [Bytecode::Constant(_), Bytecode::Pop] = &self.code[i - 1..=i] {
+                if keep[i - 1] && !targets[i] {
+                    keep[i - 1] = false;
+                    keep[i] = false;
+                }
+            }
+        }
+        self.retain(keep)
+    }
+
+    fn thread_jumps(&mut self) -> bool {
+        let mut changed = false;
+        for i in 0..self.code.len() {
+            if let Bytecode::Jump(target) | Bytecode::JumpIfFalse(target) = self.code[i] {
+                let threaded = self.final_target(target);
+                if threaded != target {
+                    changed = true;
+                    match &mut self.code[i] {
+                        Bytecode::Jump(target) | Bytecode::JumpIfFalse(target) => {
+                            *target = threaded
+                        }
+                        _ => unreachable!(),
+                    }
+                }
+            }
+        }
+        let keep: Vec<bool> = self
+            .code
+            .iter()
+            .enumerate()
+            .map(|(i, instruction)| *instruction != Bytecode::Jump(i + 1))
+            .collect();
+        self.retain(keep) || changed
+    }
+
+    /// Where a jump to `target` ends up after following jumps, stopping at
+    /// a loop of jumps.
+    fn final_target(&self, mut target: usize) -> usize {
+        for _ in 0..self.code.len() {
+            match self.code.get(target) {
+                Some(Bytecode::Jump(next)) if *next != target => target = *next,
+                _ => break,
+            }
+        }
+        target
+    }
+
+    fn remove_unreachable(&mut self) -> bool {
+        let mut reachable = vec![false; self.code.len()];
+        let mut pending = vec![0];
+        while let Some(offset) = pending.pop() {
+            if offset >= self.code.len() || reachable[offset] {
+                continue;
+            }
+            reachable[offset] = true;
+            match self.code[offset] {
+                Bytecode::Jump(target) => pending.push(target),
+                Bytecode::JumpIfFalse(target) => pending.extend([target, offset + 1]),
+                Bytecode::CallSubProgram(target, _) => pending.extend([target, offset + 2]),
+                Bytecode::Halt | Bytecode::Return => {}
+                _ => pending.push(offset + 1),
+            }
+        }
+        self.retain(reachable)
+    }
+
+    /// Offsets control may reach other than from the previous instruction.
+    fn targets(&self) -> Vec<bool> {
+        let mut targets = vec![false; self.code.len() + 3];
+        for (offset, instruction) in self.code.iter().enumerate() {
+            match instruction {
+                Bytecode::Jump(target) | Bytecode::JumpIfFalse(target) => {
+                    if let Some(entry) = targets.get_mut(*target) {
+                        *entry = true;
+                    }
+                }
+                Bytecode::CallSubProgram(target, _) => {
+                    if let Some(entry) = targets.get_mut(*target) {
+                        *entry = true;
+                    }
+                    targets[offset + 2] = true;
+                }
+                _ => {}
+            }
+        }
+        targets
+    }
+
+    /// Removes the instructions not to `keep` and retargets jumps and
+    /// calls to the first instruction kept at or after their target.
+    /// Returns whether anything was removed.
+    fn retain(&mut self, mut keep: Vec<bool>) -> bool {
+        let len = self.code.len();
+        for offset in 0..len {
+            if keep[offset] && matches!(self.code[offset], Bytecode::CallSubProgram(..)) {
+                if let Some(next) = keep.get_mut(offset + 1) {
+                    *next = true;
+                }
+            }
+        }
+        if keep.iter().all(|&kept| kept) {
+            return false;
+        }
+
+        let mut indices = Vec::with_capacity(len + 1);
+        let mut kept = 0;
+        for &keep in &keep {
+            indices.push(kept);
+            kept += usize::from(keep);
+        }
+        indices.push(kept);
+        // out of range targets stay as far past the end
+        let index = |target: usize| {
+            indices
+                .get(target)
+                .copied()
+                .unwrap_or_else(|| target - len + kept)
+        };
+
+        let code = std::mem::take(&mut self.code);
+        let spans = std::mem::take(&mut self.spans);
+        for ((mut instruction, span), keep) in code.into_iter().zip(spans).zip(keep) {
+            if !keep {
+                continue;
+            }
+            match &mut instruction {
+                Bytecode::Jump(target)
+                | Bytecode::JumpIfFalse(target)
+                | Bytecode::CallSubProgram(target, _) => *target = index(*target),
+                _ => {}
+            }
+            self.code.push(instruction);
+            self.spans.push(span);
+        }
+        true
+    }
+}
+
+fn is_address(value: &Value) -> bool {
+    matches!(value, Value::Address(_))
+}
+
+fn binary_op(instruction: &Bytecode) -> Option<BinaryFn> {
+    Some(match instruction {
+        Bytecode::Add => Value::sum,
+        Bytecode::Sub => Value::sub,
+        Bytecode::Mul => Value::mul,
+        Bytecode::Div => Value::div,
+        Bytecode::Mod => Value::modulus,
+        Bytecode::And => Value::and,
+        Bytecode::Or => Value::or,
+        Bytecode::Equal => Value::eq,
+        Bytecode::NotEqual => Value::ne,
+        Bytecode::Greater => Value::gt,
+        Bytecode::Less => Value::lt,
+        Bytecode::GreaterEqual => Value::ge,
+        Bytecode::LessEqual => Value::le,
+        _ => return None,
+    })
+}
+
+fn unary_op(instruction: &Bytecode) -> Option<UnaryFn> {
+    match instruction {
+        Bytecode::Not => Some(Value::not),
+        Bytecode::Negate => Some(Value::negate),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn optimized(bytecode: Vec<Bytecode>) -> Vec<Bytecode> {
+        optimize(bytecode, &LineTable::default()).0
+    }
+
+    #[test]
+    fn test_strip_labels_and_thread_jumps() {
+        let bytecode = vec![
+            Bytecode::Label("start".to_string()),
+            Bytecode::LoadVar("x".to_string()),
+            Bytecode::Deref,
+            Bytecode::JumpIfFalse(6),
+            Bytecode::Alloc,
+            Bytecode::Jump(0),
+            Bytecode::Label("end".to_string()),
+            Bytecode::Jump(8),
+            Bytecode::Halt,
+        ];
+        assert_eq!(
+            optimized(bytecode),
+            [
+                Bytecode::LoadVar("x".to_string()),
+                Bytecode::Deref,
+                Bytecode::JumpIfFalse(5),
+                Bytecode::Alloc,
+                Bytecode::Jump(0),
+                Bytecode::Halt,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        let bytecode = vec![
+            Bytecode::LoadVar("p".to_string()),
+            Bytecode::Constant(Value::new_int(2)),
+            Bytecode::Constant(Value::new_int(3)),
+            Bytecode::Mul,
+            Bytecode::Constant(Value::new_int(1)),
+            Bytecode::Sub,
+            Bytecode::MulDeref,
+            // left for the sanitizer to check
+            Bytecode::Constant(Value::new_address(4)),
+            Bytecode::Constant(Value::new_int(1)),
+            Bytecode::Add,
+            // left to fail at runtime
+            Bytecode::Constant(Value::new_int(1)),
+            Bytecode::Constant(Value::new_int(0)),
+            Bytecode::Div,
+            Bytecode::Constant(Value::new_bool(true)),
+            Bytecode::Not,
+            Bytecode::Pop,
+            Bytecode::Halt,
+        ];
+        assert_eq!(
+            optimized(bytecode),
+            [
+                Bytecode::LoadVar("p".to_string()),
+                Bytecode::Constant(Value::new_int(5)),
+                Bytecode::MulDeref,
+                Bytecode::Constant(Value::new_address(4)),
+                Bytecode::Constant(Value::new_int(1)),
+                Bytecode::Add,
+                Bytecode::Constant(Value::new_int(1)),
+                Bytecode::Constant(Value::new_int(0)),
+                Bytecode::Div,
+                Bytecode::Halt,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_remove_unreachable() {
+        let location = |row| Location::new(row, 0);
+        let bytecode = vec![
+            Bytecode::PushScope,
+            Bytecode::CallSubProgram(5, 0),
+            Bytecode::PopScope,
+            Bytecode::Halt,
+            Bytecode::Alloc,
+            Bytecode::Label("sub".to_string()),
+            Bytecode::Return,
+            Bytecode::Alloc,
+        ];
+        let spans = (0..bytecode.len())
+            .map(|row| (location(row), location(row)))
+            .collect();
+        let (bytecode, line_table) = optimize(bytecode, &LineTable::new(spans));
+        assert_eq!(
+            bytecode,
+            [
+                Bytecode::PushScope,
+                Bytecode::CallSubProgram(4, 0),
+                Bytecode::PopScope,
+                Bytecode::Halt,
+                Bytecode::Return,
+            ]
+        );
+        let rows: Vec<usize> = line_table.spans().iter().map(|(s, _)| s.row()).collect();
+        assert_eq!(rows, [0, 1, 2, 3, 6]);
+    }
+}
@@ -1,2 +1,4 @@
 pub mod location;
+pub mod protocol;
+pub mod snapshot;
 pub mod util;
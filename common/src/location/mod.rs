@@ -5,12 +5,15 @@ use std::fmt;
 pub struct Location {
     row: usize,
     column: usize,
-    previos_column: usize,
+    previos_column: u32,
+    /// Index of the source file in the linked program, 0 for the entry file.
+    #[serde(default)]
+    file: u32,
 }
 
 impl PartialEq for Location {
     fn eq(&self, other: &Self) -> bool {
-        self.row == other.row && self.column == other.column
+        self.row == other.row && self.column == other.column && self.file == other.file
     }
 }
 
@@ -66,10 +69,21 @@ impl Location {
         Location {
             row,
             column,
-            previos_column: column,
+            previos_column: narrow(column),
+            file: 0,
         }
     }
 
+    /// The same location in the source file with index `file`.
+    pub fn in_file(mut self, file: usize) -> Self {
+        self.file = u32::try_from(file).expect("too many source files");
+        self
+    }
+
+    pub fn file(&self) -> usize {
+        self.file as usize
+    }
+
     pub fn row(&self) -> usize {
         self.row
     }
@@ -84,7 +98,7 @@ impl Location {
     }
 
     pub fn go_right(&mut self) {
-        self.previos_column = self.column;
+        self.previos_column = narrow(self.column);
         self.column += 1;
     }
 
@@ -93,7 +107,7 @@ impl Location {
     }
 
     pub fn newline(&mut self) {
-        self.previos_column = self.column;
+        self.previos_column = narrow(self.column);
         self.row += 1;
         self.column = 0;
     }
@@ -101,7 +115,13 @@ impl Location {
     pub fn move_back_newline(&mut self) {
         if self.row > 1 {
             self.row -= 1;
-            self.column = self.previos_column;
+            self.column = self.previos_column as usize;
         }
     }
 }
+
+/// The previous column is kept in 32 bits, so that the file index fits
+/// beside it without making locations, and every AST node, larger.
+fn narrow(column: usize) -> u32 {
+    u32::try_from(column).unwrap_or(u32::MAX)
+}
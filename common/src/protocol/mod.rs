@@ -0,0 +1,78 @@
+//! `Content-Length` framing shared by the Debug Adapter Protocol and the
+//! Language Server Protocol servers.
+
+use std::io::{self, BufRead, Write};
+
+use serde_json::Value;
+
+/// Reads one `Content-Length` framed message, `None` at the end of input.
+/// A frame without `Content-Length`, or whose body is not JSON, is consumed
+/// whole and reported as `InvalidData`, so the next one can still be read.
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut content_length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let content_length = content_length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header")
+    })?;
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_write_and_read_messages() {
+        let messages = [
+            json!({"seq": 1, "type": "request", "command": "initialize"}),
+            json!({"seq": 2, "type": "event", "event": "output", "body": {"output": "ß\n"}}),
+        ];
+        let mut buffer = vec![];
+        for message in &messages {
+            write_message(&mut buffer, message).unwrap();
+        }
+
+        let mut reader = buffer.as_slice();
+        for message in &messages {
+            assert_eq!(read_message(&mut reader).unwrap().as_ref(), Some(message));
+        }
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn test_read_after_malformed_messages() {
+        let input =
+            "Content-Length: 5\r\n\r\n{\"a\":Content-Type: json\r\n\r\nContent-Length: 2\r\n\r\n{}";
+        let mut reader = input.as_bytes();
+        for _ in 0..2 {
+            let error = read_message(&mut reader).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+        assert_eq!(read_message(&mut reader).unwrap(), Some(json!({})));
+    }
+}
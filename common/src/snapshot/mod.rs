@@ -0,0 +1,267 @@
+//! A picture of a heap at one moment: its cells, the variables bound to
+//! them and the pointers between them, exported as JSON or Graphviz DOT.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SnapshotCell {
+    pub address: i64,
+    /// The value as the program would print it.
+    pub value: String,
+    /// The address the cell holds, if it holds one.
+    pub pointer: Option<i64>,
+    /// Whether the cell belongs to the partition the runtime keeps for
+    /// itself rather than to the program.
+    pub reserved: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Binding {
+    pub name: String,
+    /// Depth of the scope the variable lives in, 0 for the outermost one.
+    pub scope: usize,
+    pub address: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Edge {
+    pub from: i64,
+    pub to: i64,
+}
+
+/// An edge goes from every cell holding the address of another cell.
+///
+/// A list built by the runtime is a chain of two-cell nodes: the first cell
+/// holds the address of the next node, or `Null` at the end, the second one
+/// the element.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HeapSnapshot {
+    pub cells: Vec<SnapshotCell>,
+    pub bindings: Vec<Binding>,
+    pub edges: Vec<Edge>,
+    /// The node addresses of every list, head first.
+    pub lists: Vec<Vec<i64>>,
+}
+
+impl HeapSnapshot {
+    /// Builds a snapshot, following the pointers between `cells` and the
+    /// lists starting at `list_heads`. Heads that are no longer cells are
+    /// skipped.
+    pub fn new(
+        mut cells: Vec<SnapshotCell>,
+        mut bindings: Vec<Binding>,
+        list_heads: &[i64],
+    ) -> HeapSnapshot {
+        cells.sort_by_key(|cell| cell.address);
+        bindings.sort_by(|a, b| (a.scope, &a.name).cmp(&(b.scope, &b.name)));
+        let pointers: HashMap<i64, Option<i64>> = cells
+            .iter()
+            .map(|cell| (cell.address, cell.pointer))
+            .collect();
+
+        let edges = cells
+            .iter()
+            .filter_map(|cell| {
+                let to = cell.pointer.filter(|to| pointers.contains_key(to))?;
+                Some(Edge {
+                    from: cell.address,
+                    to,
+                })
+            })
+            .collect();
+
+        let mut lists = Vec::new();
+        let mut in_list = HashSet::new();
+        for &head in list_heads {
+            let mut nodes = Vec::new();
+            let mut node = Some(head);
+            while let Some(address) = node.filter(|a| pointers.contains_key(a)) {
+                if !in_list.insert(address) {
+                    break;
+                }
+                nodes.push(address);
+                node = pointers[&address];
+            }
+            if !nodes.is_empty() {
+                lists.push(nodes);
+            }
+        }
+
+        HeapSnapshot {
+            cells,
+            bindings,
+            edges,
+            lists,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a snapshot only holds plain data")
+    }
+
+    /// Renders the snapshot as a Graphviz digraph: a record per cell, a
+    /// dashed edge from each variable to its cell and a shaded cluster per
+    /// list.
+    pub fn to_dot(&self) -> String {
+        let mut list_of: HashMap<i64, usize> = HashMap::new();
+        for (index, nodes) in self.lists.iter().enumerate() {
+            for &node in nodes {
+                list_of.insert(node, index);
+                list_of.entry(node + 1).or_insert(index);
+            }
+        }
+
+        let mut dot = String::new();
+        dot.push_str("digraph heap {\n");
+        dot.push_str("    rankdir=LR;\n");
+        dot.push_str("    node [shape=record, fontname=\"monospace\"];\n");
+
+        for (index, nodes) in self.lists.iter().enumerate() {
+            writeln!(dot, "    subgraph cluster_list_{} {{", index).unwrap();
+            writeln!(dot, "        label=\"list at {}\";", nodes[0]).unwrap();
+            dot.push_str("        style=filled;\n");
+            dot.push_str("        fillcolor=lightyellow;\n");
+            for cell in self
+                .cells
+                .iter()
+                .filter(|c| list_of.get(&c.address) == Some(&index))
+            {
+                writeln!(dot, "        {}", cell_node(cell, true)).unwrap();
+            }
+            dot.push_str("    }\n");
+        }
+        for cell in self
+            .cells
+            .iter()
+            .filter(|c| !list_of.contains_key(&c.address))
+        {
+            writeln!(dot, "    {}", cell_node(cell, false)).unwrap();
+        }
+
+        for binding in &self.bindings {
+            let id = format!("var {} {}", binding.scope, binding.name);
+            writeln!(
+                dot,
+                "    {} [shape=plaintext, label={}];",
+                quote(&id),
+                quote(&binding.name)
+            )
+            .unwrap();
+            writeln!(
+                dot,
+                "    {} -> {} [style=dashed];",
+                quote(&id),
+                cell_id(binding.address)
+            )
+            .unwrap();
+        }
+        for edge in &self.edges {
+            writeln!(dot, "    {} -> {};", cell_id(edge.from), cell_id(edge.to)).unwrap();
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+fn cell_id(address: i64) -> String {
+    format!("cell_{}", address)
+}
+
+fn cell_node(cell: &SnapshotCell, in_list: bool) -> String {
+    let label = format!("{}|{}", cell.address, escape_record(&cell.value));
+    let mut attributes = vec![format!("label={}", quote(&label))];
+    if in_list {
+        attributes.push("style=filled".to_string());
+        attributes.push("fillcolor=lightblue".to_string());
+    }
+    if cell.reserved {
+        attributes.push("color=gray50".to_string());
+    }
+    format!("{} [{}];", cell_id(cell.address), attributes.join(", "))
+}
+
+/// Escapes the characters that separate fields in a record label.
+fn escape_record(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if matches!(c, '{' | '}' | '|' | '<' | '>' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(address: i64, pointer: Option<i64>) -> SnapshotCell {
+        SnapshotCell {
+            address,
+            value: pointer.map_or("Null".to_string(), |p| p.to_string()),
+            pointer,
+            reserved: false,
+        }
+    }
+
+    #[test]
+    fn test_snapshot() {
+        // x = 7 at 0, and the list [0, 5] at 1..5
+        let cells = vec![
+            cell(4, Some(5)),
+            cell(3, None),
+            cell(2, Some(0)),
+            cell(1, Some(3)),
+            cell(0, Some(7)),
+        ];
+        let bindings = vec![Binding {
+            name: "x".to_string(),
+            scope: 0,
+            address: 0,
+        }];
+        let snapshot = HeapSnapshot::new(cells, bindings, &[1, 100]);
+
+        assert_eq!(snapshot.cells[0].address, 0);
+        assert_eq!(
+            snapshot.edges,
+            [Edge { from: 1, to: 3 }, Edge { from: 2, to: 0 }]
+        );
+        assert_eq!(snapshot.lists, [vec![1, 3]]);
+
+        let dot = snapshot.to_dot();
+        assert!(dot.contains("subgraph cluster_list_0"));
+        assert!(dot.contains("\"var 0 x\" -> cell_0 [style=dashed];"));
+        assert!(dot.contains("cell_1 -> cell_3;"));
+
+        let json: serde_json::Value = serde_json::from_str(&snapshot.to_json()).unwrap();
+        assert_eq!(json["bindings"][0]["name"], "x");
+        assert_eq!(json["lists"][0][1], 3);
+    }
+
+    #[test]
+    fn test_escaping() {
+        let mut cell = cell(0, None);
+        cell.value = "{\"a|b\"}".to_string();
+        let snapshot = HeapSnapshot::new(vec![cell], Vec::new(), &[]);
+        assert!(snapshot
+            .to_dot()
+            .contains(r#"cell_0 [label="0|\{\"a\|b\"\}"];"#));
+    }
+}
@@ -4,3 +4,37 @@ pub fn read_file(path: &str) -> String {
     let contents = fs::read_to_string(&path).expect("Should have been able to read the file");
     return contents;
 }
+
+/// Joins the names of nested calls with arrows, runs of the same name
+/// written once with their count, as in `main -> dfs (x998)`.
+pub fn call_chain(names: &[String]) -> String {
+    let mut runs: Vec<(&str, usize)> = Vec::new();
+    for name in names {
+        match runs.last_mut() {
+            Some((last, count)) if last == name => *count += 1,
+            _ => runs.push((name, 1)),
+        }
+    }
+    runs.iter()
+        .map(|(name, count)| match count {
+            1 => name.to_string(),
+            _ => format!("{} (x{})", name, count),
+        })
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_call_chain() {
+        let names: Vec<String> = ["main", "dfs", "dfs", "dfs", "sons", "dfs"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        assert_eq!(call_chain(&names), "main -> dfs (x3) -> sons -> dfs");
+        assert_eq!(call_chain(&[]), "");
+    }
+}
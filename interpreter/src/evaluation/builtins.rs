@@ -11,3 +11,19 @@ pub fn print_(args: Vec<Value>) -> Result<Value, String> {
 pub fn to_string_(args: Vec<Value>) -> Result<Value, String> {
     Ok(Value::new_string(format!("{}", args[0])))
 }
+
+pub fn to_address_(args: Vec<Value>) -> Result<Value, String> {
+    match args.as_slice() {
+        [Value::Int(address) | Value::Address(address)] => Ok(Value::new_address(*address)),
+        [arg] => Err(format!("Invalid argument for ToAddress(): {}", arg)),
+        _ => Err("ToAddress() takes exactly one argument".to_string()),
+    }
+}
+
+pub fn to_int_(args: Vec<Value>) -> Result<Value, String> {
+    match args.as_slice() {
+        [Value::Int(address) | Value::Address(address)] => Ok(Value::new_int(*address)),
+        [arg] => Err(format!("Invalid argument for ToInt(): {}", arg)),
+        _ => Err("ToInt() takes exactly one argument".to_string()),
+    }
+}
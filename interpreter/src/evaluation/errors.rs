@@ -1,10 +1,11 @@
 use crate::evaluation::*;
-use common::location::Location;
+use common::{location::Location, util::call_chain};
 use value::error::ValueError;
 
 pub enum RuntimeError {
     NullReference,
     DivisionByZero,
+    Overflow(String), // operation
     TypeError(ValueError),
     IndexOutOfBounds(usize, usize),
     VariableNotFound(String),
@@ -14,6 +15,8 @@ pub enum RuntimeError {
     InvalidArgument(String),
     FunctionCallError(String, String),
     InvalidArgumentsNumber(String, usize, usize),
+    // subprograms called, outermost first
+    StackOverflow(Vec<String>),
     // ...other runtime errors
 }
 
@@ -22,6 +25,9 @@ impl std::fmt::Display for RuntimeError {
         match self {
             RuntimeError::NullReference => write!(f, "Null reference error"),
             RuntimeError::DivisionByZero => write!(f, "Division by zero error"),
+            RuntimeError::Overflow(operation) => {
+                write!(f, "Integer overflow error in '{}'", operation)
+            }
             RuntimeError::TypeError(error) => write!(f, "Type error: {}", error),
             RuntimeError::IndexOutOfBounds(index, length) => write!(
                 f,
@@ -50,6 +56,12 @@ impl std::fmt::Display for RuntimeError {
                     sp_name, expected_number, actual_number
                 )
             }
+            RuntimeError::StackOverflow(calls) => write!(
+                f,
+                "Stack overflow: more than {} nested subprogram calls ({})",
+                calls.len() - 1,
+                call_chain(calls)
+            ),
             RuntimeError::LabelAlreadyRegistered(label_name, registered_line, try_line) => {
                 write!(
                     f,
@@ -61,6 +73,16 @@ impl std::fmt::Display for RuntimeError {
     }
 }
 
+impl From<ValueError> for RuntimeError {
+    fn from(error: ValueError) -> Self {
+        match error {
+            ValueError::DivisionByZero => RuntimeError::DivisionByZero,
+            ValueError::Overflow { operation } => RuntimeError::Overflow(operation),
+            error => RuntimeError::TypeError(error),
+        }
+    }
+}
+
 impl std::error::Error for RuntimeError {}
 
 impl std::fmt::Debug for RuntimeError {
@@ -145,12 +167,20 @@ impl std::fmt::Debug for EvaluationError {
 
 use colored::*;
 pub struct EvaluationErrorPrinter {
-    source_text: String,
+    source_texts: Vec<String>,
 }
 
 impl EvaluationErrorPrinter {
     pub fn new(source_text: String) -> Self {
-        EvaluationErrorPrinter { source_text }
+        EvaluationErrorPrinter {
+            source_texts: vec![source_text],
+        }
+    }
+
+    /// A printer for a linked program, with the text of every file it was
+    /// loaded from, indexed by `Location::file`.
+    pub fn for_files(source_texts: Vec<String>) -> Self {
+        EvaluationErrorPrinter { source_texts }
     }
 
     pub fn print_error(&self, error: &EvaluationError) {
@@ -185,7 +215,7 @@ impl EvaluationErrorPrinter {
         message: &str,
         error_type: &str,
     ) {
-        if let Ok(code_line) = self.get_code_snippet(start_loc.row()) {
+        if let Ok(code_line) = self.get_code_snippet(start_loc) {
             let indent = " ".repeat(start_loc.row().to_string().len() + 1);
             let error_message = format!("\n{}: {}", error_type.red().bold(), message.red());
             let location_indicator = format!(
@@ -219,10 +249,12 @@ impl EvaluationErrorPrinter {
         }
     }
 
-    fn get_code_snippet(&self, line_number: usize) -> Result<String, &'static str> {
-        self.source_text
+    fn get_code_snippet(&self, location: &Location) -> Result<String, &'static str> {
+        self.source_texts
+            .get(location.file())
+            .ok_or("File not found")?
             .lines()
-            .nth(line_number.saturating_sub(1)) // Account for zero-based indexing of nth
+            .nth(location.row().saturating_sub(1)) // Account for zero-based indexing of nth
             .map(|line| line.to_string())
             .ok_or("Line not found")
     }
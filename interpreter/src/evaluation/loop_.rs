@@ -74,7 +74,7 @@ impl Evaluator {
                     }
                 };
 
-                let iterator_v = match self.eval_expression(iterator.clone())?.extract_int() {
+                let iterator_v = match self.eval_expression(iterator.clone())?.extract_address() {
                     Ok(v) => v,
                     Err(e) => {
                         return Err(EvaluationError::RuntimeError(
@@ -11,10 +11,18 @@ use runtime_context::*;
 use value::error::ValueError;
 use value::typings::Type;
 use value::*;
+/// Subprogram calls that may be active at once unless
+/// `Evaluator::set_max_call_depth` says otherwise. Each one takes a few
+/// evaluation frames on the Rust stack.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
 pub struct Evaluator {
     lines: Vec<FileLine>,
     context: RuntimeContext,
     current_line: usize,
+    /// Names of the subprograms being evaluated, outermost first.
+    calls: Vec<String>,
+    max_call_depth: usize,
 }
 
 pub enum StatementResult {
@@ -30,10 +38,20 @@ impl Evaluator {
             lines,
             context,
             current_line: 0,
+            calls: Vec::new(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
         };
         evaluator
     }
 
+    pub fn context(&self) -> &RuntimeContext {
+        &self.context
+    }
+
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+
     pub fn increment_line(&mut self) {
         self.current_line += 1;
     }
@@ -162,7 +180,7 @@ impl Evaluator {
         lhs: &Located<ExpressionKind>,
         rhs: &Located<ExpressionKind>,
     ) -> Result<StatementResult, EvaluationError> {
-        let address = match self.eval_expression(lhs.clone())?.extract_int() {
+        let address = match self.eval_address(lhs.clone())?.extract_address() {
             Ok(address) => address,
             Err(e) => {
                 return Err(EvaluationError::RuntimeError(
@@ -184,7 +202,7 @@ impl Evaluator {
         variable: &String,
         rhs: &Located<ExpressionKind>,
     ) -> Result<StatementResult, EvaluationError> {
-        let address = match self.eval_expression(rhs.clone())?.extract_int() {
+        let address = match self.eval_address(rhs.clone())?.extract_address() {
             Ok(address) => address,
             Err(e) => {
                 return Err(EvaluationError::RuntimeError(
@@ -234,7 +252,7 @@ impl Evaluator {
                             lhs.l_location,
                             lhs.r_location,
                             RuntimeError::TypeError(ValueError::UnexpectedType {
-                                expected_type: Type::Int,
+                                expected_type: Type::Address,
                                 actual_type: Type::Null,
                                 actual_value: format!("{}", Value::Null),
                             }),
@@ -245,7 +263,7 @@ impl Evaluator {
                             lhs.l_location,
                             lhs.r_location,
                             RuntimeError::TypeError(ValueError::UnexpectedType {
-                                expected_type: Type::Int,
+                                expected_type: Type::Address,
                                 actual_type: Type::Float,
                                 actual_value: format!("{}", value),
                             }),
@@ -256,7 +274,7 @@ impl Evaluator {
                             lhs.l_location,
                             lhs.r_location,
                             RuntimeError::TypeError(ValueError::UnexpectedType {
-                                expected_type: Type::Int,
+                                expected_type: Type::Address,
                                 actual_type: Type::Bool,
                                 actual_value: format!("{}", value),
                             }),
@@ -268,36 +286,27 @@ impl Evaluator {
                             lhs.l_location,
                             lhs.r_location,
                             RuntimeError::TypeError(ValueError::UnexpectedType {
-                                expected_type: Type::Int,
+                                expected_type: Type::Address,
                                 actual_type: Type::String,
                                 actual_value: format!("{}", value),
                             }),
                         ))
                     }
                     ExpressionKind::Var { name } => return self.assign_to_variable(name, rhs),
-                    ExpressionKind::Call { function, .. } => {
-                        return Err(EvaluationError::RuntimeError(
-                            lhs.l_location,
-                            lhs.r_location,
-                            RuntimeError::TypeError(ValueError::UnexpectedType {
-                                expected_type: Type::Int,
-                                actual_type: Type::Function,
-                                actual_value: format!("{}", function),
-                            }),
-                        ))
-                    }
                     ExpressionKind::UnaryOp { op, expr } => match op {
                         UnaryOp::Dereference => self.assign_to_dereference(expr, rhs),
-                        UnaryOp::Not => match self.eval_expression(lhs.clone())?.extract_int() {
-                            Ok(address) => self.assign_to_address(address, rhs),
-                            Err(e) => {
-                                return Err(EvaluationError::RuntimeError(
-                                    lhs.l_location,
-                                    lhs.r_location,
-                                    RuntimeError::TypeError(e),
-                                ))
+                        UnaryOp::Not | UnaryOp::Minus => {
+                            match self.eval_expression(lhs.clone())?.extract_address() {
+                                Ok(address) => self.assign_to_address(address, rhs),
+                                Err(e) => {
+                                    return Err(EvaluationError::RuntimeError(
+                                        lhs.l_location,
+                                        lhs.r_location,
+                                        RuntimeError::TypeError(e),
+                                    ))
+                                }
                             }
-                        },
+                        }
                         UnaryOp::MultipleDereference(expression) => {
                             match self.eval_expression(*expression.clone()) {
                                 Ok(value) => match value.extract_int() {
@@ -309,7 +318,7 @@ impl Evaluator {
                                                 lhs.l_location,
                                                 lhs.r_location,
                                             )?
-                                            .extract_int()
+                                            .extract_address()
                                         {
                                             Ok(address) => {
                                                 return self.assign_to_address(address, rhs)
@@ -334,10 +343,9 @@ impl Evaluator {
                                 Err(e) => return Err(e),
                             };
                         }
-                        UnaryOp::Minus => todo!(),
                     },
-                    ExpressionKind::BinaryOp { .. } => {
-                        match self.eval_expression(lhs.clone())?.extract_int() {
+                    ExpressionKind::Call { .. } | ExpressionKind::BinaryOp { .. } => {
+                        match self.eval_expression(lhs.clone())?.extract_address() {
                             Ok(address) => self.assign_to_address(address, rhs),
                             Err(e) => {
                                 return Err(EvaluationError::RuntimeError(
@@ -349,7 +357,7 @@ impl Evaluator {
                         }
                     }
                     ExpressionKind::List { .. } => {
-                        match self.eval_expression(lhs.clone())?.extract_int() {
+                        match self.eval_expression(lhs.clone())?.extract_address() {
                             Ok(address) => return self.assign_to_address(address, rhs),
                             Err(e) => {
                                 return Err(EvaluationError::RuntimeError(
@@ -364,8 +372,8 @@ impl Evaluator {
             }
 
             SimpleStatementKind::Send { lhs, rhs } => {
-                let address = match self.eval_expression(lhs.clone()) {
-                    Ok(v) => match v.extract_int() {
+                let address = match self.eval_address(lhs.clone()) {
+                    Ok(v) => match v.extract_address() {
                         Ok(v) => v,
                         Err(e) => {
                             return Err(EvaluationError::RuntimeError(
@@ -386,6 +394,44 @@ impl Evaluator {
                 Ok(StatementResult::Continue)
             }
 
+            SimpleStatementKind::Exchange { lhs, rhs } => {
+                let mut addresses = vec![];
+                for operand in [lhs, rhs] {
+                    let address = self
+                        .eval_address(operand.clone())?
+                        .extract_address()
+                        .map_err(|e| {
+                            EvaluationError::RuntimeError(
+                                operand.l_location,
+                                operand.r_location,
+                                RuntimeError::TypeError(e),
+                            )
+                        })?;
+                    addresses.push(address);
+                }
+                let (lhs_address, rhs_address) = (addresses[0], addresses[1]);
+                let lhs_value = self.context.read_from_address(lhs_address).clone();
+                let rhs_value = self.context.read_from_address(rhs_address).clone();
+                self.context.write_to_address(lhs_address, rhs_value);
+                self.context.write_to_address(rhs_address, lhs_value);
+                Ok(StatementResult::Continue)
+            }
+
+            SimpleStatementKind::Del { rhs } => {
+                let address = self
+                    .eval_address(rhs.clone())?
+                    .extract_address()
+                    .map_err(|e| {
+                        EvaluationError::RuntimeError(
+                            rhs.l_location,
+                            rhs.r_location,
+                            RuntimeError::TypeError(e),
+                        )
+                    })?;
+                self.context.free_address(address);
+                Ok(StatementResult::Continue)
+            }
+
             _ => Err(EvaluationError::UnhandledStatement(
                 statement.l_location,
                 statement.r_location,
@@ -394,6 +440,15 @@ impl Evaluator {
         }
     }
 
+    /// Evaluates an expression whose value is used as an address. An integer
+    /// literal there is the address of that cell, as in `x = 5` or `'5`.
+    fn eval_address(&mut self, expression: Expression) -> Result<Value, EvaluationError> {
+        match expression.node {
+            ExpressionKind::Int { value } => Ok(Value::new_address(value)),
+            _ => self.eval_expression(expression),
+        }
+    }
+
     fn eval_expression(&mut self, expression: Expression) -> Result<Value, EvaluationError> {
         let node = expression.node;
         match node {
@@ -462,7 +517,7 @@ impl Evaluator {
                         Err(e) => Err(EvaluationError::RuntimeError(
                             expression.l_location,
                             expression.r_location,
-                            RuntimeError::TypeError(e),
+                            RuntimeError::from(e),
                         )),
                     },
                     BinaryOp::Sub => match Value::sub(&lv, &rv) {
@@ -470,7 +525,7 @@ impl Evaluator {
                         Err(e) => Err(EvaluationError::RuntimeError(
                             expression.l_location,
                             expression.r_location,
-                            RuntimeError::TypeError(e),
+                            RuntimeError::from(e),
                         )),
                     },
                     BinaryOp::Mul => match Value::mul(&lv, &rv) {
@@ -478,7 +533,7 @@ impl Evaluator {
                         Err(e) => Err(EvaluationError::RuntimeError(
                             expression.l_location,
                             expression.r_location,
-                            RuntimeError::TypeError(e),
+                            RuntimeError::from(e),
                         )),
                     },
                     BinaryOp::EQ => match Value::eq(&lv, &rv) {
@@ -513,7 +568,15 @@ impl Evaluator {
                             RuntimeError::TypeError(e),
                         )),
                     },
-                    BinaryOp::Div => match Value::div(&lv, &rv) {
+                    BinaryOp::GE => match Value::ge(&lv, &rv) {
+                        Ok(v) => Ok(v),
+                        Err(e) => Err(EvaluationError::RuntimeError(
+                            expression.l_location,
+                            expression.r_location,
+                            RuntimeError::TypeError(e),
+                        )),
+                    },
+                    BinaryOp::LE => match Value::le(&lv, &rv) {
                         Ok(v) => Ok(v),
                         Err(e) => Err(EvaluationError::RuntimeError(
                             expression.l_location,
@@ -521,7 +584,22 @@ impl Evaluator {
                             RuntimeError::TypeError(e),
                         )),
                     },
-                    BinaryOp::Mod => todo!(),
+                    BinaryOp::Div => match Value::div(&lv, &rv) {
+                        Ok(v) => Ok(v),
+                        Err(e) => Err(EvaluationError::RuntimeError(
+                            expression.l_location,
+                            expression.r_location,
+                            RuntimeError::from(e),
+                        )),
+                    },
+                    BinaryOp::Mod => match Value::modulus(&lv, &rv) {
+                        Ok(v) => Ok(v),
+                        Err(e) => Err(EvaluationError::RuntimeError(
+                            expression.l_location,
+                            expression.r_location,
+                            RuntimeError::from(e),
+                        )),
+                    },
                     BinaryOp::And => match Value::and(&lv, &rv) {
                         Ok(v) => Ok(v),
                         Err(e) => Err(EvaluationError::RuntimeError(
@@ -542,14 +620,14 @@ impl Evaluator {
             }
 
             ExpressionKind::Var { name } => match self.context.get_variable_address(&name) {
-                Ok(v) => Ok(Value::new_int(v)),
-                Err(_) => Ok(Value::new_int(self.context.allocate_variable(&name))),
+                Ok(v) => Ok(Value::new_address(v)),
+                Err(_) => Ok(Value::new_address(self.context.allocate_variable(&name))),
             },
 
             ExpressionKind::UnaryOp { op, expr } => match op {
-                UnaryOp::Dereference => match self.eval_expression(*expr.clone()) {
+                UnaryOp::Dereference => match self.eval_address(*expr.clone()) {
                     Ok(v) => {
-                        match v.extract_int() {
+                        match v.extract_address() {
                             Ok(v) => return Ok(self.context.read_from_address(v).clone()),
                             Err(e) => {
                                 return Err(EvaluationError::RuntimeError(
@@ -599,7 +677,14 @@ impl Evaluator {
                         Err(e) => return Err(e),
                     };
                 }
-                UnaryOp::Minus => todo!(),
+                UnaryOp::Minus => match self.eval_expression(*expr.clone())?.negate() {
+                    Ok(v) => Ok(v),
+                    Err(e) => Err(EvaluationError::RuntimeError(
+                        expr.l_location,
+                        expr.r_location,
+                        RuntimeError::from(e),
+                    )),
+                },
             },
             ExpressionKind::Bool { value } => Ok(Value::new_bool(value)),
             ExpressionKind::String { value } => Ok(Value::new_string(value)),
@@ -610,7 +695,7 @@ impl Evaluator {
                 for e in elements {
                     v_elements.push(self.eval_expression(*e)?)
                 }
-                Ok(Value::new_int(self.context.allocate_list(v_elements)))
+                Ok(Value::new_address(self.context.allocate_list(v_elements)))
             }
         }
     }
@@ -622,11 +707,11 @@ impl Evaluator {
         l_location: Location,
         r_location: Location,
     ) -> Result<Value, EvaluationError> {
-        let val = self.eval_expression(expression.clone())?;
+        let val = self.eval_address(expression.clone())?;
         if n == 0 {
             return Ok(val);
         };
-        let mut address = match val.extract_int() {
+        let mut address = match val.extract_address() {
             Ok(v) => v,
             Err(e) => {
                 return Err(EvaluationError::RuntimeError(
@@ -644,7 +729,7 @@ impl Evaluator {
                 return Ok(temp.clone());
             }
 
-            address = match temp.extract_int() {
+            address = match temp.extract_address() {
                 Ok(v) => v,
                 Err(e) => {
                     return Err(EvaluationError::RuntimeError(
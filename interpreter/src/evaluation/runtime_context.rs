@@ -1,4 +1,5 @@
 use super::RuntimeError;
+use common::snapshot::{Binding, HeapSnapshot, SnapshotCell};
 use std::collections::HashMap;
 use value::*;
 pub struct RuntimeContext {
@@ -6,6 +7,7 @@ pub struct RuntimeContext {
     variable_addresses: HashMap<String, i64>,
     values_by_address: HashMap<i64, Value>,
     labels: HashMap<String, usize>,
+    list_heads: Vec<i64>,
 }
 
 impl RuntimeContext {
@@ -15,6 +17,7 @@ impl RuntimeContext {
             variable_addresses: HashMap::new(),
             values_by_address: HashMap::new(),
             labels: HashMap::new(),
+            list_heads: Vec::new(),
         }
     }
 
@@ -41,6 +44,8 @@ impl RuntimeContext {
 
     pub fn allocate_variable(&mut self, name: &str) -> i64 {
         let address = self.generate_free_address();
+        // occupied right away, so the next variable gets another cell
+        self.write_to_address(address, Value::Null);
         self.variable_addresses.insert(name.to_string(), address);
         address
     }
@@ -55,11 +60,12 @@ impl RuntimeContext {
             self.write_to_address(addresses.1, elem);
             let next = self.generate_free_address_for_list_element();
             if i != elements.len() {
-                self.write_to_address(addresses.0, Value::new_int(next.0));
+                self.write_to_address(addresses.0, Value::new_address(next.0));
             }
 
             addresses = next;
         }
+        self.list_heads.push(head);
         head
     }
 
@@ -67,6 +73,13 @@ impl RuntimeContext {
         self.values_by_address.insert(address, value);
     }
 
+    /// Frees the cell at `address`. A variable bound to it stays bound, as
+    /// after `del` in the VM.
+    pub fn free_address(&mut self, address: i64) {
+        self.values_by_address.remove(&address);
+        self.list_heads.retain(|&head| head != address);
+    }
+
     pub fn read_from_address(&self, address: i64) -> &Value {
         match self.values_by_address.get(&address) {
             Some(v) => v,
@@ -74,6 +87,34 @@ impl RuntimeContext {
         }
     }
 
+    /// The written cells, the variables bound to them and the lists built
+    /// by `allocate_list`.
+    pub fn heap_snapshot(&self) -> HeapSnapshot {
+        let cells = self
+            .values_by_address
+            .iter()
+            .map(|(&address, value)| SnapshotCell {
+                address,
+                value: value.to_string(),
+                pointer: match value {
+                    Value::Address(pointer) => Some(*pointer),
+                    _ => None,
+                },
+                reserved: false,
+            })
+            .collect();
+        let bindings = self
+            .variable_addresses
+            .iter()
+            .map(|(name, &address)| Binding {
+                name: name.clone(),
+                scope: 0,
+                address,
+            })
+            .collect();
+        HeapSnapshot::new(cells, bindings, &self.list_heads)
+    }
+
     pub fn free_variable(&mut self, name: &String) -> () {
         self.variable_addresses.remove(name);
     }
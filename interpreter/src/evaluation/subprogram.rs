@@ -4,6 +4,28 @@ impl Evaluator {
     pub fn eval_subprogram_call(
         &mut self,
         statement: OneLineStatement,
+    ) -> Result<StatementResult, EvaluationError> {
+        let OneLineStatementKind::SubProgram { sp_name, .. } = &statement.node else {
+            return Ok(StatementResult::Continue);
+        };
+        if self.calls.len() >= self.max_call_depth {
+            let mut calls = self.calls.clone();
+            calls.push(sp_name.identifier.clone());
+            return Err(EvaluationError::RuntimeError(
+                statement.l_location,
+                statement.r_location,
+                RuntimeError::StackOverflow(calls),
+            ));
+        }
+        self.calls.push(sp_name.identifier.clone());
+        let result = self.eval_subprogram(statement);
+        self.calls.pop();
+        result
+    }
+
+    fn eval_subprogram(
+        &mut self,
+        statement: OneLineStatement,
     ) -> Result<StatementResult, EvaluationError> {
         let l_location = statement.l_location;
         let r_location = statement.r_location;
@@ -107,7 +129,7 @@ impl Evaluator {
 
                             let mut addresses: Vec<i64> = vec![];
                             for statement in args.clone() {
-                                match self.eval_expression(*statement.clone())?.extract_int() {
+                                match self.eval_expression(*statement.clone())?.extract_address() {
                                     Ok(e) => addresses.push(e),
                                     Err(e) => {
                                         return Err(EvaluationError::RuntimeError(
@@ -1,29 +1,78 @@
+use colored::*;
+use common::util::read_file;
 use evaluation::{
-    builtins::{print_, to_string_},
+    builtins::{print_, to_address_, to_int_, to_string_},
     errors::EvaluationErrorPrinter,
     runtime_context::RuntimeContext,
-    Evaluator,
+    Evaluator, DEFAULT_MAX_CALL_DEPTH,
 };
 use parser::ast::Algorithm;
+use std::thread;
 use value::Value;
 
 pub mod evaluation;
 
+/// Rust stack reserved for each subprogram call being evaluated, enough
+/// for the frames of a debug build.
+const STACK_PER_CALL: usize = 64 * 1024;
+
 pub fn interpret(source_text: String) {
+    let ast: Algorithm = parser::parse(&source_text).unwrap();
+    evaluate(ast, vec![source_text], DEFAULT_MAX_CALL_DEPTH);
+}
+
+/// Interprets the program at `path` together with every module it imports,
+/// with at most `max_call_depth` subprogram calls active at once.
+pub fn interpret_file(path: &str, max_call_depth: usize) {
+    match parser::parse_program_with_files(path) {
+        Ok((ast, files)) => {
+            let source_texts = files.iter().map(|file| read_file(file)).collect();
+            evaluate(ast, source_texts, max_call_depth)
+        }
+        Err(e) => println!("{}", format!("{}", e).red()),
+    }
+}
+
+/// Evaluates `ast` on a thread whose stack fits `max_call_depth` nested
+/// calls, so that exceeding it fails with a stack overflow error instead
+/// of aborting. A depth the system cannot reserve a stack for is reported
+/// like any other error.
+fn evaluate(ast: Algorithm, source_texts: Vec<String>, max_call_depth: usize) {
+    let stack_size = max_call_depth
+        .saturating_add(1)
+        .saturating_mul(STACK_PER_CALL);
+    let spawned = thread::Builder::new()
+        .stack_size(stack_size)
+        .spawn(move || evaluate_on_this_thread(ast, source_texts, max_call_depth));
+    match spawned {
+        Ok(handle) => handle.join().expect("The interpreter panicked"),
+        Err(e) => println!(
+            "{}",
+            format!(
+                "Cannot reserve a stack for a call depth of {}: {}",
+                max_call_depth, e
+            )
+            .red()
+        ),
+    }
+}
+
+fn evaluate_on_this_thread(ast: Algorithm, source_texts: Vec<String>, max_call_depth: usize) {
     let mut env = RuntimeContext::new();
     env.add_function("Print", Value::new_function(print_));
     env.add_function("Str", Value::new_function(to_string_));
-
-    let ast: Algorithm = parser::parse(&source_text).unwrap();
+    env.add_function("ToAddress", Value::new_function(to_address_));
+    env.add_function("ToInt", Value::new_function(to_int_));
 
     let lines = match ast {
         Algorithm::Body(lines) => lines,
     };
 
     let mut eval = Evaluator::new(lines, env);
+    eval.set_max_call_depth(max_call_depth);
     let result = eval.eval();
     match result {
         Ok(_) => {}
-        Err(e) => EvaluationErrorPrinter::new(source_text).print_error(&e),
+        Err(e) => EvaluationErrorPrinter::for_files(source_texts).print_error(&e),
     }
 }
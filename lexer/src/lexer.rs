@@ -4,6 +4,7 @@ use std::str::CharIndices;
 
 use crate::errors::LexError;
 use crate::matcher::*;
+use crate::notation::{normalize, Notation};
 use crate::token::TokenKind;
 use common::location::Location;
 
@@ -15,6 +16,8 @@ pub struct Lexer<'a> {
     location: Location,
     is_eof: bool,
     skipped_chars: Queue<Option<(usize, char)>>,
+    notation: Notation,
+    normalized: Option<std::vec::IntoIter<Result<Span, LexError>>>,
 }
 
 impl<'a> Lexer<'a> {
@@ -35,9 +38,24 @@ impl<'a> Lexer<'a> {
             location,
             is_eof: false,
             skipped_chars: queue![],
+            notation: Notation::Ascii,
+            normalized: None,
         }
     }
 
+    pub fn with_notation(chars: &'a str, notation: Notation) -> Self {
+        let mut lexer = Lexer::new(chars);
+        lexer.notation = notation;
+        lexer
+    }
+
+    /// Makes the locations of the tokens point into the source file with
+    /// index `file` of a linked program.
+    pub fn in_file(mut self, file: usize) -> Self {
+        self.location = self.location.in_file(file);
+        self
+    }
+
     fn skip_whitespace_and_comments(&mut self) {
         while let Some(c) = self.peek_char() {
             if c == '#' {
@@ -120,7 +138,7 @@ impl<'a> Lexer<'a> {
         let start_loc = self.loc();
 
         while let Some(c) = self.peek_char() {
-            if !c.is_ascii_alphanumeric() && c != '_' {
+            if !(c.is_alphanumeric() || c == '_') || match_original_symbol_token(c).is_some() {
                 break;
             }
             self.next_char();
@@ -147,6 +165,17 @@ impl<'a> Lexer<'a> {
             "import" => TokenKind::Import,
             "from" => TokenKind::From,
             "as" => TokenKind::As,
+            // Cyrillic spellings of the original notation
+            "П" => TokenKind::SubProgram,
+            "Ц" => TokenKind::Loop,
+            "Р" => TokenKind::Predicate,
+            "З" => TokenKind::Replace,
+            "и" => TokenKind::And,
+            "или" => TokenKind::Or,
+            "не" => TokenKind::Not,
+            "истина" => TokenKind::True,
+            "ложь" => TokenKind::False,
+            "Печать" => TokenKind::Identifier("Print".to_string()),
             s => TokenKind::Identifier(s.to_string()),
         };
 
@@ -372,6 +401,34 @@ impl<'a> Iterator for Lexer<'a> {
     type Item = Result<Span, LexError>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        match self.notation {
+            Notation::Ascii => self.next_token(),
+            Notation::Original => {
+                if self.normalized.is_none() {
+                    let mut tokens = vec![];
+                    let mut error = None;
+                    while let Some(token) = self.next_token() {
+                        match token {
+                            Ok(span) => tokens.push(span),
+                            Err(e) => {
+                                error = Some(Err(e));
+                                break;
+                            }
+                        }
+                    }
+                    let mut items: Vec<Self::Item> =
+                        normalize(tokens).into_iter().map(Ok).collect();
+                    items.extend(error);
+                    self.normalized = Some(items.into_iter());
+                }
+                self.normalized.as_mut().and_then(Iterator::next)
+            }
+        }
+    }
+}
+
+impl<'a> Lexer<'a> {
+    fn next_token(&mut self) -> Option<Result<Span, LexError>> {
         self.skip_whitespace_and_comments();
 
         // Return None if no characters left, handling the end of a
@@ -391,6 +448,12 @@ impl<'a> Iterator for Lexer<'a> {
         // Processing next token based on the current character
         Some(if c.is_ascii_alphabetic() {
             Ok(self.next_keyword_or_identifier_literal())
+        } else if let Some(t) = match_original_symbol_token(c) {
+            let start_loc = self.loc();
+            self.next_char();
+            Ok((start_loc, t, self.loc()))
+        } else if c.is_alphabetic() {
+            Ok(self.next_keyword_or_identifier_literal())
         } else if c.is_ascii_digit() {
             self.determine_number()
         } else if c == '"' {
@@ -4,6 +4,7 @@ use lexer::Lexer;
 pub mod errors;
 pub mod lexer;
 mod matcher;
+pub mod notation;
 pub mod token;
 
 pub fn tokenize(
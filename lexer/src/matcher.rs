@@ -27,6 +27,21 @@ pub fn match_single_symbol_token(c: char) -> Option<TokenKind> {
     }
 }
 
+/// Symbols of the original (Yushchenko's) notation that have an ASCII
+/// counterpart in the updated syntax.
+pub fn match_original_symbol_token(c: char) -> Option<TokenKind> {
+    match c {
+        '∅' => Some(TokenKind::Null),
+        '⇒' => Some(TokenKind::Send),
+        'ᗺ' => Some(TokenKind::Return),
+        '↓' => Some(TokenKind::VerticalBar),
+        '≠' => Some(TokenKind::NotEqual),
+        '≤' => Some(TokenKind::LessThanEqual),
+        '≥' => Some(TokenKind::GreaterThanEqual),
+        _ => None,
+    }
+}
+
 pub fn match_double_symbol_token(a: char, b: char) -> Option<TokenKind> {
     match (a, b) {
         ('!', '=') => Some(TokenKind::NotEqual),
@@ -81,6 +96,21 @@ mod tests {
         assert_eq!(match_single_symbol_token(' '), None);
     }
 
+    #[test]
+    fn test_match_original_symbol_token() {
+        assert_eq!(match_original_symbol_token('∅'), Some(TokenKind::Null));
+        assert_eq!(match_original_symbol_token('⇒'), Some(TokenKind::Send));
+        assert_eq!(match_original_symbol_token('ᗺ'), Some(TokenKind::Return));
+        assert_eq!(
+            match_original_symbol_token('↓'),
+            Some(TokenKind::VerticalBar)
+        );
+        assert_eq!(match_original_symbol_token('≠'), Some(TokenKind::NotEqual));
+
+        assert_eq!(match_original_symbol_token('П'), None);
+        assert_eq!(match_original_symbol_token('|'), None);
+    }
+
     #[test]
     fn test_match_double_symbol_token() {
         assert_eq!(
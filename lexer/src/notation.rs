@@ -0,0 +1,244 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::lexer::Span;
+use crate::matcher::match_original_symbol_token;
+use crate::token::TokenKind;
+
+/// Surface syntax a program is written in.
+///
+/// `Ascii` is the updated syntax used by the `.adl` examples, `Original`
+/// is the notation from Yushchenko's papers (`∅`, `⇒`, `↓`, `ᗺ`, `П`,
+/// bare label jumps, `=` as comparison inside conditions, ...).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Notation {
+    #[default]
+    Ascii,
+    Original,
+}
+
+impl Notation {
+    /// Guesses the notation of `source`: any symbol or Cyrillic keyword of the
+    /// original notation outside string literals and comments selects it.
+    pub fn detect(source: &str) -> Notation {
+        let mut chars = source.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => {
+                    for c in chars.by_ref() {
+                        if c == '"' {
+                            break;
+                        }
+                    }
+                }
+                '#' => {
+                    for c in chars.by_ref() {
+                        if c == '\n' {
+                            break;
+                        }
+                    }
+                }
+                c if match_original_symbol_token(c).is_some() || is_cyrillic(c) => {
+                    return Notation::Original;
+                }
+                _ => {}
+            }
+        }
+        Notation::Ascii
+    }
+}
+
+fn is_cyrillic(c: char) -> bool {
+    ('\u{0400}'..='\u{04FF}').contains(&c)
+}
+
+impl fmt::Display for Notation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Notation::Ascii => f.write_str("ascii"),
+            Notation::Original => f.write_str("original"),
+        }
+    }
+}
+
+impl FromStr for Notation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ascii" => Ok(Notation::Ascii),
+            "original" => Ok(Notation::Original),
+            _ => Err(format!(
+                "unknown notation '{}', expected 'ascii' or 'original'",
+                s
+            )),
+        }
+    }
+}
+
+/// Rewrites a token stream of the original notation into the token stream
+/// the grammar expects:
+/// - `=` inside `{ }` / `( )` and in statements that send a value is a
+///   comparison;
+/// - a statement consisting of a single label is a jump to that label;
+/// - `Печать x, y` is a call of `Print {x, y}`.
+pub fn normalize(tokens: Vec<Span>) -> Vec<Span> {
+    let mut result = Vec::with_capacity(tokens.len());
+    let mut segment: Vec<Span> = vec![];
+    let mut depth = 0usize;
+
+    for (l, token, r) in tokens {
+        match token {
+            TokenKind::LeftCurlyBrace | TokenKind::LeftParenthesis => {
+                depth += 1;
+                segment.push((l, token, r));
+            }
+            TokenKind::RightCurlyBrace | TokenKind::RightParenthesis => {
+                depth = depth.saturating_sub(1);
+                let closes_condition = depth == 0
+                    && token == TokenKind::RightCurlyBrace
+                    && matches!(segment.first(), Some((_, TokenKind::Predicate, _)));
+                segment.push((l, token, r));
+                if closes_condition {
+                    flush(&mut result, std::mem::take(&mut segment), None);
+                }
+            }
+            TokenKind::Equal if depth > 0 => segment.push((l, TokenKind::EqualEqual, r)),
+            TokenKind::NewLine
+            | TokenKind::EndOfFile
+            | TokenKind::Semicolon
+            | TokenKind::VerticalBar
+            | TokenKind::Ellipsis
+                if depth == 0 =>
+            {
+                flush(
+                    &mut result,
+                    std::mem::take(&mut segment),
+                    Some((l, token, r)),
+                );
+            }
+            _ => segment.push((l, token, r)),
+        }
+    }
+    flush(&mut result, segment, None);
+    result
+}
+
+fn flush(result: &mut Vec<Span>, mut segment: Vec<Span>, terminator: Option<Span>) {
+    match segment.first().map(|(_, t, _)| t) {
+        None | Some(TokenKind::Predicate | TokenKind::SubProgram | TokenKind::Loop) => {}
+        Some(TokenKind::Identifier(_))
+            if segment.len() == 1 && !matches!(terminator, Some((_, TokenKind::Ellipsis, _))) =>
+        {
+            let (l, ..) = segment[0];
+            segment.insert(0, (l, TokenKind::At, l));
+        }
+        Some(TokenKind::Identifier(name))
+            if name == "Print"
+                && !matches!(segment.get(1), Some((_, TokenKind::LeftCurlyBrace, _))) =>
+        {
+            let (_, _, print_end) = segment[0];
+            let (_, _, end) = segment[segment.len() - 1];
+            segment.insert(1, (print_end, TokenKind::LeftCurlyBrace, print_end));
+            segment.push((end, TokenKind::RightCurlyBrace, end));
+        }
+        _ => {
+            if segment.iter().any(|(_, t, _)| *t == TokenKind::Send) {
+                for (_, t, _) in segment.iter_mut() {
+                    if *t == TokenKind::Equal {
+                        *t = TokenKind::EqualEqual;
+                    }
+                }
+            }
+        }
+    }
+    result.extend(segment);
+    result.extend(terminator);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn kinds(input: &str) -> Vec<TokenKind> {
+        Lexer::with_notation(input, Notation::Original)
+            .map(|t| t.unwrap().1)
+            .collect()
+    }
+
+    fn ident(s: &str) -> TokenKind {
+        TokenKind::Identifier(s.to_string())
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Notation::detect("null => a\nPrint{'a}"), Notation::Ascii);
+        assert_eq!(Notation::detect("Print{\"∅ ⇒\"} # ᗺ"), Notation::Ascii);
+        assert_eq!(Notation::detect("∅ ⇒ a"), Notation::Original);
+        assert_eq!(Notation::detect("Печать 'a"), Notation::Original);
+    }
+
+    #[test]
+    fn test_normalize_conditions_and_jumps() {
+        use TokenKind::*;
+        assert_eq!(
+            kinds("P { 'a = ∅ } end ↓ ᗺ"),
+            vec![
+                Predicate,
+                LeftCurlyBrace,
+                Apostrophe,
+                ident("a"),
+                EqualEqual,
+                Null,
+                RightCurlyBrace,
+                At,
+                ident("end"),
+                VerticalBar,
+                Return,
+                EndOfFile,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_normalize_send_and_print() {
+        use TokenKind::*;
+        assert_eq!(
+            kinds("a = ∅ ⇒ b\nПечать 'a, 1"),
+            vec![
+                ident("a"),
+                EqualEqual,
+                Null,
+                Send,
+                ident("b"),
+                NewLine,
+                ident("Print"),
+                LeftCurlyBrace,
+                Apostrophe,
+                ident("a"),
+                Comma,
+                IntegerLiteral(1),
+                RightCurlyBrace,
+                EndOfFile,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_normalize_keeps_labels_and_assignments() {
+        use TokenKind::*;
+        assert_eq!(
+            kinds("M ...\nl = 1"),
+            vec![
+                ident("M"),
+                Ellipsis,
+                NewLine,
+                ident("l"),
+                Equal,
+                IntegerLiteral(1),
+                EndOfFile,
+            ]
+        );
+    }
+}
@@ -2,6 +2,7 @@ use common::location::Location;
 use core::fmt;
 use serde::{Deserialize, Serialize};
 
+pub mod printer;
 pub mod serializer;
 pub mod visitor;
 
@@ -118,6 +119,15 @@ pub struct Path {
     pub ids: Vec<String>,
 }
 
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.absolute {
+            write!(f, "::")?;
+        }
+        write!(f, "{}", self.ids.join("::"))
+    }
+}
+
 pub type Expression = Located<ExpressionKind>;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -211,6 +221,8 @@ pub enum BinaryOp {
     NE,
     GT,
     LT,
+    GE,
+    LE,
     Sum,
     Sub,
     Mul,
@@ -0,0 +1,352 @@
+use super::*;
+use lexer::notation::Notation;
+
+/// Renders `algorithm` as source text in the requested notation.
+///
+/// Layout is not preserved: every file line is printed on its own line
+/// without indentation, expressions get only the parentheses precedence
+/// requires.
+pub fn print_algorithm(algorithm: &Algorithm, notation: Notation) -> String {
+    let printer = Printer { notation };
+    match algorithm {
+        Algorithm::Body(lines) => lines
+            .iter()
+            .map(|line| printer.file_line(line))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+struct Printer {
+    notation: Notation,
+}
+
+impl Printer {
+    fn original(&self) -> bool {
+        self.notation == Notation::Original
+    }
+
+    fn symbol(&self, ascii: &'static str, original: &'static str) -> &'static str {
+        if self.original() {
+            original
+        } else {
+            ascii
+        }
+    }
+
+    fn file_line(&self, line: &FileLine) -> String {
+        match line {
+            FileLine::Line { labels, statements } => {
+                let statements = self.statements(statements);
+                match (labels.is_empty(), statements.is_empty()) {
+                    (true, _) => statements,
+                    (false, true) => format!("{} ...", labels.join(", ")),
+                    (false, false) => format!("{} ... {}", labels.join(", "), statements),
+                }
+            }
+        }
+    }
+
+    fn statements(&self, statements: &Statements) -> String {
+        match statements {
+            Statements::OneLineStatement(statement) => self.one_line_statement(&statement.node),
+            Statements::SimpleStatements(statements) => statements
+                .iter()
+                .map(|s| self.simple_statement(&s.node))
+                .collect::<Vec<_>>()
+                .join("; "),
+        }
+    }
+
+    fn one_line_statement(&self, kind: &OneLineStatementKind) -> String {
+        match kind {
+            OneLineStatementKind::SubProgram {
+                sp_name,
+                args,
+                label_to,
+            } => {
+                let mut s = format!(
+                    "{} {} {{{}}}",
+                    self.symbol("SP", "П"),
+                    sp_name,
+                    self.arguments(args)
+                );
+                if let Some(label) = label_to {
+                    s.push(' ');
+                    s.push_str(label);
+                }
+                s
+            }
+            OneLineStatementKind::Loop {
+                initial_value,
+                step,
+                last_value_or_condition,
+                iterator,
+                label_until,
+                label_to,
+            } => {
+                let mut s = format!(
+                    "{} {{{}, {}, {} {} {}}} {}",
+                    self.symbol("L", "Ц"),
+                    self.expression(initial_value, true),
+                    self.expression(step, true),
+                    self.expression(last_value_or_condition, true),
+                    self.symbol("=>", "⇒"),
+                    self.expression(iterator, true),
+                    label_until
+                );
+                if let Some(label) = label_to {
+                    s.push(' ');
+                    s.push_str(label);
+                }
+                s
+            }
+            OneLineStatementKind::Predicate {
+                condition,
+                if_true,
+                if_false,
+            } => {
+                let mut s = format!("P {{{}}}", self.expression(condition, true));
+                for (branch, separator) in [(if_true, self.symbol(" |", " ↓")), (if_false, "")] {
+                    let branch = self.statements(branch);
+                    if !branch.is_empty() {
+                        s.push(' ');
+                        s.push_str(&branch);
+                    }
+                    s.push_str(separator);
+                }
+                s
+            }
+            OneLineStatementKind::Exit => "!".to_string(),
+            OneLineStatementKind::Return => self.symbol("return", "ᗺ").to_string(),
+            OneLineStatementKind::UnconditionalJump { label } => {
+                if self.original() {
+                    label.clone()
+                } else {
+                    format!("@{}", label)
+                }
+            }
+        }
+    }
+
+    fn simple_statement(&self, kind: &SimpleStatementKind) -> String {
+        match kind {
+            SimpleStatementKind::Import {
+                labels,
+                path,
+                alias,
+            } => {
+                let mut s = format!("import {{{}}} from {}", labels.join(", "), path);
+                if let Some(alias) = alias {
+                    s.push_str(" as ");
+                    s.push_str(alias);
+                }
+                s
+            }
+            SimpleStatementKind::Del { rhs } => format!("del {}", self.expression(rhs, false)),
+            SimpleStatementKind::Assign { lhs, rhs } => format!(
+                "{} = {}",
+                self.expression(lhs, false),
+                self.expression(rhs, false)
+            ),
+            // the grammar stores `a => b` as `Send { lhs: b, rhs: a }`
+            SimpleStatementKind::Send { lhs, rhs } => format!(
+                "{} {} {}",
+                self.expression(rhs, true),
+                self.symbol("=>", "⇒"),
+                self.expression(lhs, true)
+            ),
+            SimpleStatementKind::Exchange { lhs, rhs } => format!(
+                "{} <=> {}",
+                self.expression(lhs, false),
+                self.expression(rhs, false)
+            ),
+            SimpleStatementKind::Expression { expression } => match &expression.node {
+                ExpressionKind::Call { function, args }
+                    if self.original() && function == "Print" && !args.is_empty() =>
+                {
+                    let args: Vec<String> =
+                        args.iter().map(|arg| self.expression(arg, false)).collect();
+                    format!("Печать {}", args.join(", "))
+                }
+                // a lone name is a jump in the original notation
+                ExpressionKind::Var { .. } if self.original() => {
+                    format!("({})", self.expression(expression, false))
+                }
+                _ => self.expression(expression, false),
+            },
+        }
+    }
+
+    fn arguments(&self, args: &[Box<Expression>]) -> String {
+        args.iter()
+            .map(|arg| self.expression(arg, true))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// `nested` tells whether the expression is enclosed in braces or
+    /// parentheses, where the original notation reads `=` as a comparison.
+    fn expression(&self, expression: &Expression, nested: bool) -> String {
+        match &expression.node {
+            ExpressionKind::Null => self.symbol("null", "∅").to_string(),
+            ExpressionKind::Float { value } => format!("{:?}", value),
+            ExpressionKind::Bool { value } => value.to_string(),
+            ExpressionKind::Int { value } => value.to_string(),
+            ExpressionKind::String { value } => format!("\"{}\"", value),
+            ExpressionKind::Var { name } => name.clone(),
+            ExpressionKind::List { elements } => format!(
+                "[{}]",
+                elements
+                    .iter()
+                    .map(|e| self.expression(e, nested))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            ExpressionKind::Call { function, args } => {
+                format!("{}{{{}}}", function, self.arguments(args))
+            }
+            ExpressionKind::UnaryOp { op, expr } => match op {
+                UnaryOp::MultipleDereference(count) => format!(
+                    "D{{{}, {}}}",
+                    self.expression(expr, true),
+                    self.expression(count, true)
+                ),
+                UnaryOp::Dereference => format!("'{}", self.operand(expr, 2, nested)),
+                UnaryOp::Not => format!("not {}", self.operand(expr, 2, nested)),
+                UnaryOp::Minus => match &expr.node {
+                    ExpressionKind::UnaryOp {
+                        op: UnaryOp::Minus, ..
+                    } => format!("-({})", self.expression(expr, true)),
+                    _ => format!("-{}", self.operand(expr, 2, nested)),
+                },
+            },
+            ExpressionKind::BinaryOp { op, lhs, rhs } => {
+                let precedence = binary_precedence(op);
+                format!(
+                    "{} {} {}",
+                    self.operand(lhs, precedence, nested),
+                    self.binary_op(op, nested),
+                    self.operand(rhs, precedence - 1, nested)
+                )
+            }
+        }
+    }
+
+    /// Prints an operand, wrapping it in parentheses when it binds looser
+    /// than `max_precedence` allows.
+    fn operand(&self, expression: &Expression, max_precedence: u8, nested: bool) -> String {
+        if precedence(expression) > max_precedence {
+            format!("({})", self.expression(expression, true))
+        } else {
+            self.expression(expression, nested)
+        }
+    }
+
+    fn binary_op(&self, op: &BinaryOp, nested: bool) -> &'static str {
+        match op {
+            BinaryOp::EQ if self.original() && nested => "=",
+            BinaryOp::EQ => "==",
+            BinaryOp::NE => self.symbol("!=", "≠"),
+            BinaryOp::GT => ">",
+            BinaryOp::LT => "<",
+            BinaryOp::GE => self.symbol(">=", "≥"),
+            BinaryOp::LE => self.symbol("<=", "≤"),
+            BinaryOp::Sum => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Mod => "%",
+            BinaryOp::And => "and",
+            BinaryOp::Or => "or",
+        }
+    }
+}
+
+/// Precedence levels mirror the `ExpressionPrecedenceN` rules of the grammar.
+fn binary_precedence(op: &BinaryOp) -> u8 {
+    match op {
+        BinaryOp::Or => 8,
+        BinaryOp::And => 7,
+        BinaryOp::EQ | BinaryOp::NE | BinaryOp::GT | BinaryOp::LT | BinaryOp::GE | BinaryOp::LE => {
+            6
+        }
+        BinaryOp::Sum | BinaryOp::Sub => 5,
+        BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => 4,
+    }
+}
+
+fn precedence(expression: &Expression) -> u8 {
+    match &expression.node {
+        ExpressionKind::BinaryOp { op, .. } => binary_precedence(op),
+        ExpressionKind::UnaryOp {
+            op: UnaryOp::MultipleDereference(_),
+            ..
+        } => 1,
+        ExpressionKind::UnaryOp { .. } => 2,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn round_trip(source: &str, notation: Notation) -> String {
+        let ast = parse(source).expect("source should parse");
+        let printed = print_algorithm(&ast, notation);
+        let reparsed = parse(&printed)
+            .unwrap_or_else(|e| panic!("printed source should parse: {:?}\n{}", e, printed));
+        assert_eq!(print_algorithm(&reparsed, notation), printed);
+        printed
+    }
+
+    #[test]
+    fn test_print_ascii() {
+        let printed = round_trip(
+            "@m\nf ... null => x\n  P {'x == 1 and not (a or b)} return | 'x * (2 + 3) => x\nreturn\nm ... SP f {a, -(-1)}\nPrint {\"a\", 1.0, 1 <= 2 >= 3}; 'a <=> b",
+            Notation::Ascii,
+        );
+        assert_eq!(
+            printed,
+            "@m\nf ... null => x\nP {'x == 1 and not (a or b)} return | 'x * (2 + 3) => x\nreturn\nm ... SP f {a, -(-1)}\nPrint{\"a\", 1.0, 1 <= 2 >= 3}; 'a <=> b"
+        );
+    }
+
+    #[test]
+    fn test_print_original() {
+        let printed = round_trip(
+            "@m\nf ... null => x\nP {'x == null} @m | x = 1 == 2\nm ... Print {'x != 1, x == 2, 'x <= 1, x >= 2}",
+            Notation::Original,
+        );
+        assert_eq!(
+            printed,
+            "m\nf ... ∅ ⇒ x\nP {'x = ∅} m ↓ x = 1 == 2\nm ... Печать 'x ≠ 1, x == 2, 'x ≤ 1, x ≥ 2"
+        );
+    }
+
+    #[test]
+    fn test_print_examples_in_both_notations() {
+        let examples = concat!(env!("CARGO_MANIFEST_DIR"), "/../examples");
+        let mut pending = vec![std::path::PathBuf::from(examples)];
+        while let Some(dir) = pending.pop() {
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    pending.push(path);
+                } else if path.extension().is_some_and(|e| e == "adl") {
+                    let source = std::fs::read_to_string(&path).unwrap();
+                    let ascii = round_trip(&source, Notation::Ascii);
+                    let original = round_trip(&ascii, Notation::Original);
+                    assert_eq!(
+                        print_algorithm(&parse(&original).unwrap(), Notation::Ascii),
+                        ascii,
+                        "{}",
+                        path.display()
+                    );
+                }
+            }
+        }
+    }
+}
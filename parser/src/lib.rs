@@ -3,9 +3,12 @@ use common::location::Location;
 use common::util::read_file;
 use lexer::errors::*;
 use lexer::lexer::Lexer;
+use lexer::notation::Notation;
 use lexer::token::*;
+use linker::{error::ImportError, link_program};
 
 pub mod ast;
+pub mod linker;
 
 #[allow(clippy::all)]
 mod address_language {
@@ -15,18 +18,44 @@ mod address_language {
 pub fn parse(
     source_text: &str,
 ) -> Result<ast::Algorithm, lalrpop_util::ParseError<Location, TokenKind, LexError>> {
-    let lexer = Lexer::new(source_text);
+    let lexer = Lexer::with_notation(source_text, Notation::detect(source_text));
     let ast: Result<ast::Algorithm, lalrpop_util::ParseError<Location, TokenKind, LexError>> =
         AlgorithmParser::new().parse(lexer);
     ast
 }
 
+/// Parses the source text of the file with index `file` in a linked
+/// program, so that its locations point into that file.
+pub(crate) fn parse_in_file(
+    source_text: &str,
+    file: usize,
+) -> Result<ast::Algorithm, lalrpop_util::ParseError<Location, TokenKind, LexError>> {
+    let lexer = Lexer::with_notation(source_text, Notation::detect(source_text)).in_file(file);
+    AlgorithmParser::new().parse(lexer)
+}
+
 pub fn parse_by_path(
     path: &str,
 ) -> Result<ast::Algorithm, lalrpop_util::ParseError<Location, TokenKind, LexError>> {
     let file = read_file(path);
-    let lexer = Lexer::new(&file);
+    let lexer = Lexer::with_notation(&file, Notation::detect(&file));
     let ast: Result<ast::Algorithm, lalrpop_util::ParseError<Location, TokenKind, LexError>> =
         AlgorithmParser::new().parse(lexer);
     ast
 }
+
+/// Parses the file at `path` together with every module it imports.
+pub fn parse_program(path: &str) -> Result<ast::Algorithm, ImportError> {
+    link_program(path).map(|(ast, _)| ast)
+}
+
+/// Like `parse_program`, also returning the paths of the files the program
+/// was loaded from, indexed by `Location::file`.
+pub fn parse_program_with_files(path: &str) -> Result<(ast::Algorithm, Vec<String>), ImportError> {
+    let (ast, files) = link_program(path)?;
+    let files = files
+        .iter()
+        .map(|file| file.to_string_lossy().into_owned())
+        .collect();
+    Ok((ast, files))
+}
@@ -0,0 +1,106 @@
+use common::location::Location;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        message: String,
+    },
+    Cycle(Vec<PathBuf>),
+    UnknownLabel {
+        label: String,
+        module: PathBuf,
+        path: PathBuf,
+        location: Location,
+    },
+    UnknownAlias {
+        alias: String,
+        path: PathBuf,
+        location: Location,
+    },
+    LabelCollision {
+        label: String,
+        path: PathBuf,
+        location: Location,
+    },
+    AliasCollision {
+        alias: String,
+        path: PathBuf,
+        location: Location,
+    },
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::Io { path, error } => {
+                write!(f, "Failed to read module '{}': {}", path.display(), error)
+            }
+            ImportError::Parse { path, message } => {
+                write!(
+                    f,
+                    "Failed to parse module '{}': {}",
+                    path.display(),
+                    message
+                )
+            }
+            ImportError::Cycle(chain) => {
+                let chain: Vec<String> = chain.iter().map(|p| p.display().to_string()).collect();
+                write!(f, "Import cycle detected: {}", chain.join(" -> "))
+            }
+            ImportError::UnknownLabel {
+                label,
+                module,
+                path,
+                location,
+            } => write!(
+                f,
+                "Label '{}' is not defined in module '{}' (imported at {} in '{}')",
+                label,
+                module.display(),
+                location,
+                path.display()
+            ),
+            ImportError::UnknownAlias {
+                alias,
+                path,
+                location,
+            } => write!(
+                f,
+                "Module alias '{}' is not declared (used at {} in '{}')",
+                alias,
+                location,
+                path.display()
+            ),
+            ImportError::LabelCollision {
+                label,
+                path,
+                location,
+            } => write!(
+                f,
+                "Imported label '{}' collides with an existing label (at {} in '{}')",
+                label,
+                location,
+                path.display()
+            ),
+            ImportError::AliasCollision {
+                alias,
+                path,
+                location,
+            } => write!(
+                f,
+                "Module alias '{}' is already bound to another module (at {} in '{}')",
+                alias,
+                location,
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
@@ -0,0 +1,542 @@
+pub mod error;
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path as FsPath, PathBuf};
+
+use common::location::Location;
+use error::ImportError;
+
+use crate::ast::*;
+
+/// Loads the program rooted at `path` and every module it imports, and links
+/// them into a single algorithm.
+///
+/// Labels of imported modules are prefixed with the module name
+/// (`list::len`), so they never clash with the labels of the importing file.
+/// References to imported labels (`SP len {..}` or `SP l::len {..}` for an
+/// aliased import) are rewritten to the prefixed names and the `Import`
+/// statements themselves are removed. Imported modules are appended after an
+/// `Exit` line, so their top-level code is never executed.
+///
+/// Locations in the linked algorithm keep pointing into the file they were
+/// parsed from: `Location::file` indexes the returned paths, the entry file
+/// being the first.
+pub fn link_program(path: &str) -> Result<(Algorithm, Vec<PathBuf>), ImportError> {
+    let path = canonicalize(FsPath::new(path))?;
+    let root = path.parent().map(FsPath::to_path_buf).unwrap_or_default();
+
+    let mut linker = Linker {
+        root,
+        modules: Vec::new(),
+        files: Vec::new(),
+        by_path: HashMap::new(),
+        keys: HashSet::new(),
+        in_progress: Vec::new(),
+    };
+    linker.load(path, None)?;
+    let files = std::mem::take(&mut linker.files);
+    Ok((linker.into_algorithm(), files))
+}
+
+struct Module {
+    key: Option<String>,
+    labels: HashSet<String>,
+    lines: Vec<FileLine>,
+}
+
+impl Module {
+    fn mangle(&self, label: &str) -> String {
+        match &self.key {
+            Some(key) => format!("{}::{}", key, label),
+            None => label.to_string(),
+        }
+    }
+}
+
+struct Import {
+    labels: Vec<String>,
+    path: Path,
+    alias: Option<String>,
+    location: Location,
+}
+
+/// Label names visible inside one module.
+#[derive(Default)]
+struct Names {
+    direct: HashMap<String, String>,
+    aliased: HashMap<String, Alias>,
+}
+
+struct Alias {
+    module: PathBuf,
+    labels: HashMap<String, String>,
+}
+
+struct Linker {
+    root: PathBuf,
+    modules: Vec<Module>,
+    files: Vec<PathBuf>,
+    by_path: HashMap<PathBuf, usize>,
+    keys: HashSet<String>,
+    in_progress: Vec<PathBuf>,
+}
+
+impl Linker {
+    fn load(&mut self, path: PathBuf, key: Option<String>) -> Result<usize, ImportError> {
+        if let Some(position) = self.in_progress.iter().position(|p| *p == path) {
+            let mut chain = self.in_progress[position..].to_vec();
+            chain.push(path);
+            return Err(ImportError::Cycle(chain));
+        }
+        if let Some(&index) = self.by_path.get(&path) {
+            return Ok(index);
+        }
+
+        let source_text = fs::read_to_string(&path).map_err(|error| ImportError::Io {
+            path: path.clone(),
+            error,
+        })?;
+        let file = self.files.len();
+        self.files.push(path.clone());
+        let lines = match crate::parse_in_file(&source_text, file) {
+            Ok(Algorithm::Body(lines)) => lines,
+            Err(e) => {
+                return Err(ImportError::Parse {
+                    path,
+                    message: e.to_string(),
+                })
+            }
+        };
+
+        let key = key.map(|key| self.unique_key(key));
+        let mut module = Module {
+            key,
+            labels: lines.iter().flat_map(|l| l.labels().clone()).collect(),
+            lines: vec![],
+        };
+
+        self.in_progress.push(path.clone());
+        let (lines, imports) = take_imports(lines);
+        let names = self.resolve_imports(&path, &module, imports)?;
+        module.lines = lines
+            .into_iter()
+            .map(|line| rename_line(line, &names, &path))
+            .collect::<Result<_, _>>()?;
+        self.in_progress.pop();
+
+        let index = self.modules.len();
+        self.modules.push(module);
+        self.by_path.insert(path, index);
+        Ok(index)
+    }
+
+    fn resolve_imports(
+        &mut self,
+        path: &FsPath,
+        module: &Module,
+        imports: Vec<Import>,
+    ) -> Result<Names, ImportError> {
+        let mut names = Names::default();
+        for label in &module.labels {
+            names.direct.insert(label.clone(), module.mangle(label));
+        }
+
+        for import in imports {
+            let file = self.resolve_path(path, &import.path);
+            let file = canonicalize(&file)?;
+            let index = self.load(file.clone(), Some(import.path.ids.join("::")))?;
+            let imported = &self.modules[index];
+
+            let mut mapping = HashMap::new();
+            for label in &import.labels {
+                if !imported.labels.contains(label) {
+                    return Err(ImportError::UnknownLabel {
+                        label: label.clone(),
+                        module: file,
+                        path: path.to_path_buf(),
+                        location: import.location,
+                    });
+                }
+                mapping.insert(label.clone(), imported.mangle(label));
+            }
+
+            match import.alias {
+                Some(alias) => match names.aliased.get_mut(&alias) {
+                    Some(bound) if bound.module == file => bound.labels.extend(mapping),
+                    Some(_) => {
+                        return Err(ImportError::AliasCollision {
+                            alias,
+                            path: path.to_path_buf(),
+                            location: import.location,
+                        })
+                    }
+                    None => {
+                        let alias_module = Alias {
+                            module: file,
+                            labels: mapping,
+                        };
+                        names.aliased.insert(alias, alias_module);
+                    }
+                },
+                None => {
+                    for (label, mangled) in mapping {
+                        match names.direct.get(&label) {
+                            Some(existing) if *existing != mangled => {
+                                return Err(ImportError::LabelCollision {
+                                    label,
+                                    path: path.to_path_buf(),
+                                    location: import.location,
+                                })
+                            }
+                            _ => {
+                                names.direct.insert(label, mangled);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(names)
+    }
+
+    /// Relative paths are resolved against the importing file, absolute ones
+    /// (`::lib::list`) against the directory of the program entry file.
+    fn resolve_path(&self, importer: &FsPath, path: &Path) -> PathBuf {
+        let mut file = if path.absolute {
+            self.root.clone()
+        } else {
+            importer
+                .parent()
+                .map(FsPath::to_path_buf)
+                .unwrap_or_default()
+        };
+        for id in &path.ids {
+            file.push(id);
+        }
+        file.set_extension("adl");
+        file
+    }
+
+    fn unique_key(&mut self, key: String) -> String {
+        let mut unique = key.clone();
+        let mut n = 1;
+        while self.keys.contains(&unique) {
+            n += 1;
+            unique = format!("{}#{}", key, n);
+        }
+        self.keys.insert(unique.clone());
+        unique
+    }
+
+    fn into_algorithm(self) -> Algorithm {
+        let mut modules = self.modules;
+        // the entry module is loaded last, after everything it depends on
+        let entry = modules.pop().expect("entry module is always loaded");
+        let mut lines = entry.lines;
+        if !modules.is_empty() {
+            let location = Location::default();
+            lines.push(FileLine::Line {
+                labels: vec![],
+                statements: Statements::OneLineStatement(OneLineStatement {
+                    l_location: location,
+                    r_location: location,
+                    node: OneLineStatementKind::Exit,
+                }),
+            });
+            for module in modules {
+                lines.extend(module.lines);
+            }
+        }
+        Algorithm::Body(lines)
+    }
+}
+
+fn canonicalize(path: &FsPath) -> Result<PathBuf, ImportError> {
+    fs::canonicalize(path).map_err(|error| ImportError::Io {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+fn take_imports(lines: Vec<FileLine>) -> (Vec<FileLine>, Vec<Import>) {
+    let mut imports = vec![];
+    let lines = lines
+        .into_iter()
+        .map(|line| match line {
+            FileLine::Line {
+                labels,
+                statements: Statements::SimpleStatements(stmts),
+            } => {
+                let mut rest = vec![];
+                for stmt in stmts {
+                    match stmt.node {
+                        SimpleStatementKind::Import {
+                            labels,
+                            path,
+                            alias,
+                        } => imports.push(Import {
+                            labels,
+                            path,
+                            alias,
+                            location: stmt.l_location,
+                        }),
+                        node => rest.push(SimpleStatement { node, ..stmt }),
+                    }
+                }
+                FileLine::Line {
+                    labels,
+                    statements: Statements::SimpleStatements(rest),
+                }
+            }
+            line => line,
+        })
+        .collect();
+    (lines, imports)
+}
+
+fn rename_line(line: FileLine, names: &Names, path: &FsPath) -> Result<FileLine, ImportError> {
+    match line {
+        FileLine::Line { labels, statements } => Ok(FileLine::Line {
+            labels: labels.iter().map(|l| rename(l, names)).collect(),
+            statements: rename_statements(statements, names, path)?,
+        }),
+    }
+}
+
+fn rename_statements(
+    statements: Statements,
+    names: &Names,
+    path: &FsPath,
+) -> Result<Statements, ImportError> {
+    match statements {
+        Statements::SimpleStatements(stmts) => Ok(Statements::SimpleStatements(stmts)),
+        Statements::OneLineStatement(stmt) => {
+            let node = match stmt.node {
+                OneLineStatementKind::SubProgram {
+                    sp_name,
+                    args,
+                    label_to,
+                } => {
+                    let identifier = match &sp_name.mod_alias {
+                        Some(alias) => {
+                            let bound = names.aliased.get(alias).ok_or_else(|| {
+                                ImportError::UnknownAlias {
+                                    alias: alias.clone(),
+                                    path: path.to_path_buf(),
+                                    location: stmt.l_location,
+                                }
+                            })?;
+                            bound
+                                .labels
+                                .get(&sp_name.identifier)
+                                .cloned()
+                                .ok_or_else(|| ImportError::UnknownLabel {
+                                    label: sp_name.identifier.clone(),
+                                    module: bound.module.clone(),
+                                    path: path.to_path_buf(),
+                                    location: stmt.l_location,
+                                })?
+                        }
+                        None => rename(&sp_name.identifier, names),
+                    };
+                    OneLineStatementKind::SubProgram {
+                        sp_name: Label {
+                            identifier,
+                            mod_alias: None,
+                        },
+                        args,
+                        label_to: label_to.map(|l| rename(&l, names)),
+                    }
+                }
+                OneLineStatementKind::Loop {
+                    initial_value,
+                    step,
+                    last_value_or_condition,
+                    iterator,
+                    label_until,
+                    label_to,
+                } => OneLineStatementKind::Loop {
+                    initial_value,
+                    step,
+                    last_value_or_condition,
+                    iterator,
+                    label_until: rename(&label_until, names),
+                    label_to: label_to.map(|l| rename(&l, names)),
+                },
+                OneLineStatementKind::Predicate {
+                    condition,
+                    if_true,
+                    if_false,
+                } => OneLineStatementKind::Predicate {
+                    condition,
+                    if_true: Box::new(rename_statements(*if_true, names, path)?),
+                    if_false: Box::new(rename_statements(*if_false, names, path)?),
+                },
+                OneLineStatementKind::UnconditionalJump { label } => {
+                    OneLineStatementKind::UnconditionalJump {
+                        label: rename(&label, names),
+                    }
+                }
+                node => node,
+            };
+            Ok(Statements::OneLineStatement(OneLineStatement {
+                node,
+                ..stmt
+            }))
+        }
+    }
+}
+
+fn rename(label: &str, names: &Names) -> String {
+    names
+        .direct
+        .get(label)
+        .cloned()
+        .unwrap_or_else(|| label.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Modules written for one test, in a directory of its own that is
+    /// removed when the test ends, whether it passes or not.
+    struct Modules(PathBuf);
+
+    impl Modules {
+        fn write(test: &str, files: &[(&str, &str)]) -> Self {
+            let dir = std::env::temp_dir().join(format!("adl_{}_{}", test, std::process::id()));
+            let modules = Modules(dir);
+            for (name, source) in files {
+                let path = modules.0.join(name);
+                fs::create_dir_all(path.parent().unwrap())
+                    .expect("Failed to create test directory");
+                fs::write(path, source).expect("Failed to write test module");
+            }
+            modules
+        }
+
+        fn path(&self, name: &str) -> String {
+            self.0.join(name).to_str().unwrap().to_string()
+        }
+    }
+
+    impl Drop for Modules {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn sp_names(algorithm: &Algorithm) -> Vec<String> {
+        let Algorithm::Body(lines) = algorithm;
+        lines
+            .iter()
+            .filter_map(|line| match line {
+                FileLine::Line {
+                    statements: Statements::OneLineStatement(stmt),
+                    ..
+                } => match &stmt.node {
+                    OneLineStatementKind::SubProgram { sp_name, .. } => Some(sp_name.to_string()),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_link_aliased_and_direct_imports() {
+        let modules = Modules::write(
+            "link_aliased_and_direct_imports",
+            &[
+                (
+                    "main.adl",
+                    "import {len} from lib::list\nimport {len} from lib::list as l\nSP len {}\nSP l::len {}\n",
+                ),
+                ("lib/list.adl", "!\nlen ... return\n"),
+            ],
+        );
+
+        let (algorithm, _) = link_program(&modules.path("main.adl")).unwrap();
+        assert_eq!(
+            sp_names(&algorithm),
+            vec!["lib::list::len", "lib::list::len"]
+        );
+
+        let Algorithm::Body(lines) = &algorithm;
+        assert!(lines
+            .iter()
+            .any(|line| line.labels() == &vec!["lib::list::len".to_string()]));
+    }
+
+    #[test]
+    fn test_link_keeps_the_file_of_locations() {
+        let modules = Modules::write(
+            "link_keeps_the_file_of_locations",
+            &[
+                ("main.adl", "import {len} from lib\nSP len {}\n"),
+                ("lib.adl", "!\nlen ... return\n"),
+            ],
+        );
+
+        let (Algorithm::Body(lines), files) = link_program(&modules.path("main.adl")).unwrap();
+        let files: Vec<_> = files.iter().map(|f| f.file_name().unwrap()).collect();
+        assert_eq!(files, vec!["main.adl", "lib.adl"]);
+
+        let locations: Vec<(usize, usize)> = lines
+            .iter()
+            .filter_map(|line| match line {
+                FileLine::Line {
+                    statements: Statements::OneLineStatement(stmt),
+                    ..
+                } => Some((stmt.l_location.file(), stmt.l_location.row())),
+                _ => None,
+            })
+            .collect();
+        // the call, the exit before the imported module, then its `!` and `return`
+        assert_eq!(locations, vec![(0, 2), (0, 0), (1, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn test_link_detects_cycle() {
+        let modules = Modules::write(
+            "link_detects_cycle",
+            &[
+                ("a.adl", "import {g} from b\nf ... return\n"),
+                ("b.adl", "import {f} from a\ng ... return\n"),
+            ],
+        );
+
+        let result = link_program(&modules.path("a.adl"));
+        assert!(matches!(result, Err(ImportError::Cycle(chain)) if chain.len() == 3));
+    }
+
+    #[test]
+    fn test_link_detects_label_collision() {
+        let modules = Modules::write(
+            "link_detects_label_collision",
+            &[
+                ("main.adl", "import {len} from lib\nlen ... return\n"),
+                ("lib.adl", "len ... return\n"),
+            ],
+        );
+
+        let result = link_program(&modules.path("main.adl"));
+        assert!(matches!(result, Err(ImportError::LabelCollision { label, .. }) if label == "len"));
+    }
+
+    #[test]
+    fn test_link_unknown_label() {
+        let modules = Modules::write(
+            "link_unknown_label",
+            &[
+                ("main.adl", "import {size} from lib\n"),
+                ("lib.adl", "len ... return\n"),
+            ],
+        );
+
+        let result = link_program(&modules.path("main.adl"));
+        assert!(matches!(result, Err(ImportError::UnknownLabel { label, .. }) if label == "size"));
+    }
+}
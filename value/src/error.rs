@@ -19,6 +19,10 @@ pub enum ValueError {
         actual_type: Type,
         actual_value: String,
     },
+    DivisionByZero,
+    Overflow {
+        operation: String,
+    },
 }
 
 impl std::fmt::Display for ValueError {
@@ -53,6 +57,10 @@ impl std::fmt::Display for ValueError {
                 "Expect types '{:?}', but actual : ({}: {})",
                 expected_types, actual_type, actual_value
             ),
+            ValueError::DivisionByZero => write!(f, "Division by zero"),
+            ValueError::Overflow { operation } => {
+                write!(f, "Integer overflow in '{}'", operation)
+            }
         }
     }
 }
@@ -13,6 +13,9 @@ pub enum Value {
     String(String),
     Bool(bool),
     Int(i64),
+    /// A heap address. Unlike an `Int`, only addresses can be dereferenced
+    /// or stored to.
+    Address(i64),
     Function(fn(Vec<Value>) -> Result<Value, String>),
 }
 
@@ -24,6 +27,7 @@ impl PartialEq for Value {
             (Value::String(a), Value::String(b)) => a == b,
             (Value::Bool(a), Value::Bool(b)) => a == b,
             (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Address(a), Value::Address(b)) => a == b,
             // Comparing function pointers directly
             (Value::Function(a), Value::Function(b)) => a as *const _ == b as *const _,
             _ => false,
@@ -38,6 +42,10 @@ impl Value {
         Value::Int(value)
     }
 
+    pub fn new_address(address: i64) -> Value {
+        Value::Address(address)
+    }
+
     pub fn new_float(value: f64) -> Value {
         Value::Float(value)
     }
@@ -61,6 +69,7 @@ impl Value {
             Value::String(_) => Type::String,
             Value::Bool(_) => Type::Bool,
             Value::Int(_) => Type::Int,
+            Value::Address(_) => Type::Address,
             Value::Function(_) => Type::Function,
         }
     }
@@ -72,6 +81,13 @@ impl Value {
         }
     }
 
+    pub fn extract_address(&self) -> Result<i64, ValueError> {
+        match self {
+            Value::Address(address) => Ok(*address),
+            _ => Err(Value::raise_unexpected_type_error(Type::Address, self)),
+        }
+    }
+
     pub fn extract_float(&self) -> Result<f64, ValueError> {
         match self {
             Value::Float(value) => Ok(*value),
@@ -95,12 +111,40 @@ impl Value {
 
     pub fn sum(lv: &Value, rv: &Value) -> Result<Value, ValueError> {
         match (lv, rv) {
-            (Value::Int(lv), Value::Int(rv)) => Ok(Value::Int(lv + rv)),
-            (Value::Float(lv), Value::Float(rv)) => Ok(Value::Float(lv + rv)),
+            (Value::Int(lv), Value::Int(rv)) => Ok(Value::Int(checked(lv.checked_add(*rv), "+")?)),
+            (Value::Address(address), Value::Int(offset))
+            | (Value::Int(offset), Value::Address(address)) => {
+                Ok(Value::Address(checked(address.checked_add(*offset), "+")?))
+            }
+            (Value::String(_), _) | (_, Value::String(_)) => Value::concat(lv, rv),
+            _ => match Value::promote(lv, rv) {
+                Some((lv, rv)) => Ok(Value::Float(lv + rv)),
+                None => Err(Value::raise_incompatible_types_error(
+                    lv,
+                    rv,
+                    "+".to_owned(),
+                )),
+            },
+        }
+    }
+
+    /// Joins two strings, or a string and a number, boolean or address
+    /// written as `Print` shows it: the original notation builds messages
+    /// as `"I'm " + 'age`.
+    pub fn concat(lv: &Value, rv: &Value) -> Result<Value, ValueError> {
+        match (lv, rv) {
             (Value::String(lv), Value::String(rv)) => Ok(Value::String(lv.to_string() + rv)),
+            (
+                Value::String(lv),
+                rv @ (Value::Int(_) | Value::Address(_) | Value::Float(_) | Value::Bool(_)),
+            ) => Ok(Value::String(format!("{}{}", lv, rv))),
+            (
+                lv @ (Value::Int(_) | Value::Address(_) | Value::Float(_) | Value::Bool(_)),
+                Value::String(rv),
+            ) => Ok(Value::String(format!("{}{}", lv, rv))),
             _ => Err(Value::raise_incompatible_types_error(
-                &lv,
-                &rv,
+                lv,
+                rv,
                 "+".to_owned(),
             )),
         }
@@ -130,37 +174,52 @@ impl Value {
 
     pub fn mul(lv: &Value, rv: &Value) -> Result<Value, ValueError> {
         match (lv, rv) {
-            (Value::Int(lv), Value::Int(rv)) => Ok(Value::Int(lv * rv)),
-            (Value::Float(lv), Value::Float(rv)) => Ok(Value::Float(lv * rv)),
-            _ => Err(Value::raise_incompatible_types_error(
-                &lv,
-                &rv,
-                "*".to_owned(),
-            )),
+            (Value::Int(lv), Value::Int(rv)) => Ok(Value::Int(checked(lv.checked_mul(*rv), "*")?)),
+            _ => match Value::promote(lv, rv) {
+                Some((lv, rv)) => Ok(Value::Float(lv * rv)),
+                None => Err(Value::raise_incompatible_types_error(
+                    lv,
+                    rv,
+                    "*".to_owned(),
+                )),
+            },
         }
     }
 
     pub fn div(lv: &Value, rv: &Value) -> Result<Value, ValueError> {
         match (lv, rv) {
-            (Value::Int(lv), Value::Int(rv)) => Ok(Value::Int(lv / rv)),
-            (Value::Float(lv), Value::Float(rv)) => Ok(Value::Float(lv / rv)),
-            _ => Err(Value::raise_incompatible_types_error(
-                &lv,
-                &rv,
-                "*".to_owned(),
-            )),
+            (Value::Int(_), Value::Int(0)) => Err(ValueError::DivisionByZero),
+            // `i64::MIN / -1` is the only quotient that does not fit
+            (Value::Int(lv), Value::Int(rv)) => Ok(Value::Int(checked(lv.checked_div(*rv), "/")?)),
+            _ => match Value::promote(lv, rv) {
+                Some((lv, rv)) => Ok(Value::Float(lv / rv)),
+                None => Err(Value::raise_incompatible_types_error(
+                    lv,
+                    rv,
+                    "/".to_owned(),
+                )),
+            },
         }
     }
 
     pub fn sub(lv: &Value, rv: &Value) -> Result<Value, ValueError> {
         match (lv, rv) {
-            (Value::Int(lv), Value::Int(rv)) => Ok(Value::Int(lv.wrapping_sub(*rv))),
-            (Value::Float(lv), Value::Float(rv)) => Ok(Value::Float(lv - rv)),
-            _ => Err(Value::raise_incompatible_types_error(
-                &lv,
-                &rv,
-                "-".to_owned(),
-            )),
+            (Value::Int(lv), Value::Int(rv)) => Ok(Value::Int(checked(lv.checked_sub(*rv), "-")?)),
+            (Value::Address(address), Value::Int(offset)) => {
+                Ok(Value::Address(checked(address.checked_sub(*offset), "-")?))
+            }
+            // the distance between two addresses
+            (Value::Address(lv), Value::Address(rv)) => {
+                Ok(Value::Int(checked(lv.checked_sub(*rv), "-")?))
+            }
+            _ => match Value::promote(lv, rv) {
+                Some((lv, rv)) => Ok(Value::Float(lv - rv)),
+                None => Err(Value::raise_incompatible_types_error(
+                    lv,
+                    rv,
+                    "-".to_owned(),
+                )),
+            },
         }
     }
 
@@ -168,10 +227,13 @@ impl Value {
         match (self, other) {
             (Value::Bool(lhs), Value::Bool(rhs)) => Ok(Value::Bool(lhs == rhs)),
             (Value::Int(lhs), Value::Int(rhs)) => Ok(Value::Bool(lhs == rhs)),
-            (Value::Float(lhs), Value::Float(rhs)) => Ok(Value::Bool(lhs == rhs)),
+            (Value::Address(lhs), Value::Address(rhs)) => Ok(Value::Bool(lhs == rhs)),
             (Value::String(lhs), Value::String(rhs)) => Ok(Value::Bool(lhs == rhs)),
             (Value::Null, Value::Null) => Ok(Value::Bool(true)),
-            _ => Ok(Value::Bool(false)),
+            _ => match Value::promote(self, other) {
+                Some((lhs, rhs)) => Ok(Value::Bool(lhs == rhs)),
+                None => Ok(Value::Bool(false)),
+            },
         }
     }
 
@@ -179,68 +241,83 @@ impl Value {
         match (self, other) {
             (Value::Bool(lhs), Value::Bool(rhs)) => Ok(Value::Bool(lhs != rhs)),
             (Value::Int(lhs), Value::Int(rhs)) => Ok(Value::Bool(lhs != rhs)),
-            (Value::Float(lhs), Value::Float(rhs)) => Ok(Value::Bool(lhs != rhs)),
+            (Value::Address(lhs), Value::Address(rhs)) => Ok(Value::Bool(lhs != rhs)),
             (Value::String(lhs), Value::String(rhs)) => Ok(Value::Bool(lhs != rhs)),
             (Value::Null, Value::Null) => Ok(Value::Bool(false)),
-            _ => Ok(Value::Bool(true)),
+            _ => match Value::promote(self, other) {
+                Some((lhs, rhs)) => Ok(Value::Bool(lhs != rhs)),
+                None => Ok(Value::Bool(true)),
+            },
         }
     }
 
     pub fn lt(&self, other: &Value) -> Result<Value, ValueError> {
         match (self, other) {
             (Value::Int(lhs), Value::Int(rhs)) => Ok(Value::Bool(lhs < rhs)),
-            (Value::Float(lhs), Value::Float(rhs)) => Ok(Value::Bool(lhs < rhs)),
+            (Value::Address(lhs), Value::Address(rhs)) => Ok(Value::Bool(lhs < rhs)),
             (Value::String(lhs), Value::String(rhs)) => Ok(Value::Bool(lhs < rhs)),
-            _ => Err(Value::raise_incompatible_types_error(
-                self,
-                other,
-                "<".to_owned(),
-            )),
+            _ => match Value::promote(self, other) {
+                Some((lhs, rhs)) => Ok(Value::Bool(lhs < rhs)),
+                None => Err(Value::raise_incompatible_types_error(
+                    self,
+                    other,
+                    "<".to_owned(),
+                )),
+            },
         }
     }
 
     pub fn le(&self, other: &Value) -> Result<Value, ValueError> {
         match (self, other) {
             (Value::Int(lhs), Value::Int(rhs)) => Ok(Value::Bool(lhs <= rhs)),
-            (Value::Float(lhs), Value::Float(rhs)) => Ok(Value::Bool(lhs <= rhs)),
+            (Value::Address(lhs), Value::Address(rhs)) => Ok(Value::Bool(lhs <= rhs)),
             (Value::String(lhs), Value::String(rhs)) => Ok(Value::Bool(lhs <= rhs)),
-            _ => Err(Value::raise_incompatible_types_error(
-                self,
-                other,
-                "<=".to_owned(),
-            )),
+            _ => match Value::promote(self, other) {
+                Some((lhs, rhs)) => Ok(Value::Bool(lhs <= rhs)),
+                None => Err(Value::raise_incompatible_types_error(
+                    self,
+                    other,
+                    "<=".to_owned(),
+                )),
+            },
         }
     }
 
     pub fn gt(&self, other: &Value) -> Result<Value, ValueError> {
         match (self, other) {
             (Value::Int(lhs), Value::Int(rhs)) => Ok(Value::Bool(lhs > rhs)),
-            (Value::Float(lhs), Value::Float(rhs)) => Ok(Value::Bool(lhs > rhs)),
+            (Value::Address(lhs), Value::Address(rhs)) => Ok(Value::Bool(lhs > rhs)),
             (Value::String(lhs), Value::String(rhs)) => Ok(Value::Bool(lhs > rhs)),
-            _ => Err(Value::raise_incompatible_types_error(
-                self,
-                other,
-                ">".to_owned(),
-            )),
+            _ => match Value::promote(self, other) {
+                Some((lhs, rhs)) => Ok(Value::Bool(lhs > rhs)),
+                None => Err(Value::raise_incompatible_types_error(
+                    self,
+                    other,
+                    ">".to_owned(),
+                )),
+            },
         }
     }
 
     pub fn ge(&self, other: &Value) -> Result<Value, ValueError> {
         match (self, other) {
             (Value::Int(lhs), Value::Int(rhs)) => Ok(Value::Bool(lhs >= rhs)),
-            (Value::Float(lhs), Value::Float(rhs)) => Ok(Value::Bool(lhs >= rhs)),
+            (Value::Address(lhs), Value::Address(rhs)) => Ok(Value::Bool(lhs >= rhs)),
             (Value::String(lhs), Value::String(rhs)) => Ok(Value::Bool(lhs >= rhs)),
-            _ => Err(Value::raise_incompatible_types_error(
-                self,
-                other,
-                ">=".to_owned(),
-            )),
+            _ => match Value::promote(self, other) {
+                Some((lhs, rhs)) => Ok(Value::Bool(lhs >= rhs)),
+                None => Err(Value::raise_incompatible_types_error(
+                    self,
+                    other,
+                    ">=".to_owned(),
+                )),
+            },
         }
     }
 
     pub fn negate(&self) -> Result<Value, ValueError> {
         match self {
-            Value::Int(value) => Ok(Value::Int(-value)),
+            Value::Int(value) => Ok(Value::Int(checked(value.checked_neg(), "-")?)),
             Value::Float(value) => Ok(Value::Float(-value)),
             _ => Err(Value::raise_unexpected_type_error(Type::Int, self)),
         }
@@ -248,13 +325,16 @@ impl Value {
 
     pub fn modulus(lv: &Value, rv: &Value) -> Result<Value, ValueError> {
         match (lv, rv) {
-            (Value::Int(lv), Value::Int(rv)) => Ok(Value::Int(lv % rv)),
-            (Value::Float(lv), Value::Float(rv)) => Ok(Value::Float(lv % rv)),
-            _ => Err(Value::raise_incompatible_types_error(
-                lv,
-                rv,
-                "%".to_owned(),
-            )),
+            (Value::Int(_), Value::Int(0)) => Err(ValueError::DivisionByZero),
+            (Value::Int(lv), Value::Int(rv)) => Ok(Value::Int(checked(lv.checked_rem(*rv), "%")?)),
+            _ => match Value::promote(lv, rv) {
+                Some((lv, rv)) => Ok(Value::Float(lv % rv)),
+                None => Err(Value::raise_incompatible_types_error(
+                    lv,
+                    rv,
+                    "%".to_owned(),
+                )),
+            },
         }
     }
 
@@ -265,6 +345,17 @@ impl Value {
         }
     }
 
+    /// Both operands as floats, when they are numbers and at least one of
+    /// them is a float: an int mixed with a float is promoted.
+    fn promote(lv: &Value, rv: &Value) -> Option<(f64, f64)> {
+        match (lv, rv) {
+            (Value::Float(lv), Value::Float(rv)) => Some((*lv, *rv)),
+            (Value::Int(lv), Value::Float(rv)) => Some((*lv as f64, *rv)),
+            (Value::Float(lv), Value::Int(rv)) => Some((*lv, *rv as f64)),
+            _ => None,
+        }
+    }
+
     fn raise_incompatible_types_error(v1: &Value, v2: &Value, op: String) -> ValueError {
         ValueError::IncompatibleTypes {
             operation: op,
@@ -297,10 +388,17 @@ impl fmt::Display for Value {
         match self {
             Value::Null => write!(f, "Null"),
             Value::Bool(value) => write!(f, "{}", value),
-            Value::Int(value) => write!(f, "{}", value),
+            Value::Int(value) | Value::Address(value) => write!(f, "{}", value),
             Value::Function(_) => write!(f, "Function"),
             Value::String(value) => write!(f, "{}", value),
             Value::Float(value) => write!(f, "{}", value),
         }
     }
 }
+
+/// The result of a checked integer operation, or an overflow error for `op`.
+fn checked(result: Option<i64>, op: &str) -> Result<i64, ValueError> {
+    result.ok_or_else(|| ValueError::Overflow {
+        operation: op.to_owned(),
+    })
+}
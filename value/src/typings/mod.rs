@@ -6,6 +6,7 @@ pub enum Type {
     String,
     Bool,
     Int,
+    Address,
     Function,
     Error,
     Unresolved,
@@ -18,6 +19,7 @@ impl Display for Type {
             Type::Float => "float",
             Type::String => "string",
             Type::Int => "int",
+            Type::Address => "address",
             Type::Bool => "bool",
             Type::Function => "function",
             Type::Error => "?",
@@ -36,6 +38,7 @@ impl Type {
             "string" => Some(Type::String),
             "bool" => Some(Type::Bool),
             "int" => Some(Type::Int),
+            "address" => Some(Type::Address),
             _ => None,
         }
     }
@@ -0,0 +1,50 @@
+use std::io;
+
+use codegen::{bytecode::Bytecode, gen_bytecode};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use vm::{
+    config::{Backend, VmConfig},
+    new_vm,
+};
+
+fn compile(example: &str) -> Vec<Bytecode> {
+    let path = format!("{}/../examples/{}", env!("CARGO_MANIFEST_DIR"), example);
+    let ast = parser::parse_program(&path).expect("the example should parse");
+    gen_bytecode(ast).expect("the example should compile")
+}
+
+/// Runs an example on each backend, leaving out loading it into a fresh
+/// VM. The register backend's time includes lowering the bytecode.
+fn bench_example(c: &mut Criterion, name: &str, example: &str) {
+    let bytecode = compile(example);
+    let mut group = c.benchmark_group(name);
+    for backend in [Backend::Stack, Backend::Register] {
+        let config = VmConfig {
+            backend,
+            ..VmConfig::default()
+        };
+        group.bench_function(backend.to_string(), |b| {
+            b.iter_batched(
+                || {
+                    let mut vm = new_vm(bytecode.clone(), &config);
+                    vm.set_output(io::sink());
+                    vm
+                },
+                |mut vm| vm.run().expect("the example should run"),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn loop_example(c: &mut Criterion) {
+    bench_example(c, "loop example", "loop/loop_list_squaring.adl");
+}
+
+fn tree_example(c: &mut Criterion) {
+    bench_example(c, "tree example", "tree/binary_tree/common_approach.adl");
+}
+
+criterion_group!(benches, loop_example, tree_example);
+criterion_main!(benches);
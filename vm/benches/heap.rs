@@ -0,0 +1,141 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use value::Value;
+use vm::heap::Heap;
+
+const NODES: i64 = 5000;
+
+/// What the benchmarks need from a heap, so the current one can be
+/// compared with `baseline::HashMapHeap`.
+trait Cells {
+    fn allocate(&mut self, count: usize) -> Vec<i64>;
+    fn store(&mut self, address: i64, value: Value);
+    fn load(&self, address: i64) -> Value;
+}
+
+impl Cells for Heap {
+    fn allocate(&mut self, count: usize) -> Vec<i64> {
+        self.allocate_consecutive_addresses(count, false).unwrap()
+    }
+
+    fn store(&mut self, address: i64, value: Value) {
+        Heap::store(self, address, value).unwrap()
+    }
+
+    fn load(&self, address: i64) -> Value {
+        self.lookup_address(address).unwrap()
+    }
+}
+
+/// The heap before cells moved to pages: a `HashMap` from addresses to
+/// values, reduced to the general partition and the calls benchmarked.
+mod baseline {
+    use super::Cells;
+    use std::collections::HashMap;
+    use value::Value;
+
+    #[derive(Default)]
+    pub struct HashMapHeap {
+        values_by_address: HashMap<i64, Value>,
+        next_address: i64,
+        free_list: Vec<i64>,
+    }
+
+    impl HashMapHeap {
+        fn is_block_free(&self, start: i64, count: usize) -> bool {
+            (start..start + count as i64).all(|address| {
+                !self.values_by_address.contains_key(&address) && !self.free_list.contains(&address)
+            })
+        }
+    }
+
+    impl Cells for HashMapHeap {
+        /// Looks for the first free block from address 0 on, as the old
+        /// `allocate_consecutive_addresses` did.
+        fn allocate(&mut self, count: usize) -> Vec<i64> {
+            let start = (0..self.next_address)
+                .find(|&start| self.is_block_free(start, count))
+                .unwrap_or_else(|| {
+                    self.next_address += count as i64;
+                    self.next_address - count as i64
+                });
+            (start..start + count as i64).collect()
+        }
+
+        fn store(&mut self, address: i64, value: Value) {
+            self.values_by_address.insert(address, value);
+        }
+
+        fn load(&self, address: i64) -> Value {
+            self.values_by_address.get(&address).cloned().unwrap()
+        }
+    }
+}
+
+use baseline::HashMapHeap;
+
+/// A linked list of `NODES` two-cell nodes, `[next, value]`, the layout
+/// the VM uses for list values.
+fn build_list(heap: &mut impl Cells) -> i64 {
+    let mut head = -1;
+    for value in 0..NODES {
+        let node = heap.allocate(2);
+        heap.store(node[0], Value::new_int(head));
+        heap.store(node[1], Value::new_int(value));
+        head = node[0];
+    }
+    head
+}
+
+/// A complete binary tree of three-cell nodes, `[value, left, right]`.
+fn build_tree(heap: &mut impl Cells, depth: u32) -> i64 {
+    let node = heap.allocate(3);
+    for &cell in &node {
+        heap.store(cell, Value::new_int(-1));
+    }
+    heap.store(node[0], Value::new_int(depth as i64));
+    let (left, right) = match depth {
+        0 => (-1, -1),
+        _ => (build_tree(heap, depth - 1), build_tree(heap, depth - 1)),
+    };
+    heap.store(node[1], Value::new_int(left));
+    heap.store(node[2], Value::new_int(right));
+    node[0]
+}
+
+fn sum_tree(heap: &impl Cells, node: i64) -> i64 {
+    if node < 0 {
+        return 0;
+    }
+    let cell = |offset| heap.load(node + offset).extract_int().unwrap();
+    cell(0) + sum_tree(heap, cell(1)) + sum_tree(heap, cell(2))
+}
+
+fn list_building(c: &mut Criterion) {
+    let mut group = c.benchmark_group("list building");
+    group.bench_function("pages", |b| {
+        b.iter(|| {
+            let mut heap = Heap::new(4 * NODES, 0.25);
+            black_box(build_list(&mut heap))
+        })
+    });
+    // the baseline scans every cell for each node, so keep it short
+    group.sample_size(10);
+    group.bench_function("hashmap", |b| {
+        b.iter(|| black_box(build_list(&mut HashMapHeap::default())))
+    });
+    group.finish();
+}
+
+fn tree_traversal(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tree traversal");
+    let mut heap = Heap::new(100_000, 0.25);
+    let root = build_tree(&mut heap, 12);
+    group.bench_function("pages", |b| b.iter(|| black_box(sum_tree(&heap, root))));
+    let mut heap = HashMapHeap::default();
+    let root = build_tree(&mut heap, 12);
+    group.bench_function("hashmap", |b| b.iter(|| black_box(sum_tree(&heap, root))));
+    group.finish();
+}
+
+criterion_group!(benches, list_building, tree_traversal);
+criterion_main!(benches);
@@ -0,0 +1,34 @@
+use value::Value;
+
+use crate::vm::VM;
+
+/// `Gc {}` collects garbage now and returns the number of cells freed.
+pub fn builtin_gc(vm: &mut VM, args: Vec<Value>) -> Value {
+    if !args.is_empty() {
+        panic!("Gc() takes no arguments");
+    }
+    Value::new_int(vm.collect_garbage() as i64)
+}
+
+/// `ToAddress {n}` turns the integer `n` into the address of cell `n`, for
+/// programs that compute addresses as plain numbers.
+pub fn builtin_to_address(_vm: &mut VM, args: Vec<Value>) -> Value {
+    if args.len() != 1 {
+        panic!("ToAddress() takes exactly one argument");
+    }
+    match args[0] {
+        Value::Int(address) | Value::Address(address) => Value::new_address(address),
+        _ => panic!("Invalid argument for ToAddress()"),
+    }
+}
+
+/// `ToInt {a}` turns the address `a` into the number of its cell.
+pub fn builtin_to_int(_vm: &mut VM, args: Vec<Value>) -> Value {
+    if args.len() != 1 {
+        panic!("ToInt() takes exactly one argument");
+    }
+    match args[0] {
+        Value::Int(address) | Value::Address(address) => Value::new_int(address),
+        _ => panic!("Invalid argument for ToInt()"),
+    }
+}
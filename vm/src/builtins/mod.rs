@@ -1,6 +1,19 @@
 use crate::VM;
 use value::Value;
+pub mod memory;
 pub mod print;
 pub mod string;
 
 pub type BuiltinFunction = fn(&mut VM, Vec<Value>) -> Value;
+
+/// The builtins registered by `new_vm`.
+pub const STANDARD_BUILTINS: &[(&str, BuiltinFunction)] = &[
+    ("Print", print::builtin_print),
+    ("CharAt", string::builtin_char_at),
+    ("Concat", string::builtin_concat),
+    ("Replace", string::builtin_replace),
+    ("SubString", string::builtin_substring),
+    ("Gc", memory::builtin_gc),
+    ("ToAddress", memory::builtin_to_address),
+    ("ToInt", memory::builtin_to_int),
+];
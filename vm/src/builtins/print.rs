@@ -3,9 +3,10 @@ use value::Value;
 use crate::vm::VM;
 
 pub fn builtin_print(vm: &mut VM, args: Vec<Value>) -> Value {
+    let output = vm.output();
     for arg in args {
-        print!("{}", arg.to_string())
+        let _ = write!(output, "{}", arg);
     }
-    println!(); // New line after printing all arguments
+    let _ = writeln!(output); // New line after printing all arguments
     Value::Null // Return null value
 }
@@ -0,0 +1,127 @@
+use std::{fmt, str::FromStr};
+
+use crate::heap::HeapGrowth;
+
+/// The interpreter loop a VM runs programs with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// Executes the bytecode as is, on an operand stack.
+    #[default]
+    Stack,
+    /// Lowers the bytecode to register instructions first, see
+    /// `crate::register`.
+    Register,
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Backend::Stack => f.write_str("stack"),
+            Backend::Register => f.write_str("register"),
+        }
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stack" => Ok(Backend::Stack),
+            "register" => Ok(Backend::Register),
+            _ => Err(format!(
+                "unknown backend '{}', expected 'stack' or 'register'",
+                s
+            )),
+        }
+    }
+}
+
+/// Settings a VM is created with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VmConfig {
+    /// Number of heap cells, shared by the general and the reserved partition.
+    pub heap_size: i64,
+    /// Share of the heap cells reserved for lists built by the VM itself.
+    pub reserved_ratio: f64,
+    pub growth: HeapGrowth,
+    /// Collect garbage when a partition is full, before growing the heap.
+    pub gc: bool,
+    /// Check heap accesses and report leaks at exit, see `Sanitizer`.
+    pub sanitize: bool,
+    pub backend: Backend,
+    /// Subprogram calls that may be active at once, not counting tail
+    /// calls, which reuse the frame of their caller.
+    pub max_call_depth: usize,
+}
+
+impl Default for VmConfig {
+    fn default() -> Self {
+        Self {
+            heap_size: 4000,
+            reserved_ratio: 0.25,
+            growth: HeapGrowth::Fixed,
+            gc: false,
+            sanitize: false,
+            backend: Backend::Stack,
+            max_call_depth: 10_000,
+        }
+    }
+}
+
+impl VmConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.heap_size <= 0 {
+            return Err(format!(
+                "heap size must be positive, got {}",
+                self.heap_size
+            ));
+        }
+        if !(0.0..1.0).contains(&self.reserved_ratio) {
+            return Err(format!(
+                "reserved ratio must be at least 0 and less than 1, got {}",
+                self.reserved_ratio
+            ));
+        }
+        if self.max_call_depth == 0 {
+            return Err("maximum call depth must be positive, got 0".to_string());
+        }
+        if let HeapGrowth::Doubling(max_size) = self.growth {
+            if max_size < self.heap_size {
+                return Err(format!(
+                    "maximum heap size {} is less than the heap size {}",
+                    max_size, self.heap_size
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(VmConfig::default().validate(), Ok(()));
+        let config = VmConfig {
+            reserved_ratio: 1.0,
+            ..VmConfig::default()
+        };
+        assert!(config.validate().is_err());
+        let config = VmConfig {
+            max_call_depth: 0,
+            ..VmConfig::default()
+        };
+        assert!(config.validate().is_err());
+        let config = VmConfig {
+            growth: HeapGrowth::Doubling(100),
+            ..VmConfig::default()
+        };
+        assert_eq!(
+            config.validate(),
+            Err("maximum heap size 100 is less than the heap size 4000".to_string())
+        );
+    }
+}
@@ -1,25 +1,77 @@
-use std::collections::HashMap;
+mod pages;
+
+use pages::{Bitmap, Pages};
+use std::fmt;
 use value::Value;
 
-#[derive(Debug)]
+/// Memory cells addressed by integers, split into a general partition for
+/// the program's own cells and a reserved one for lists the VM builds.
+///
+/// Cells live in `Pages`; an occupied cell holds a value, `Null` right after
+/// allocation. Freed addresses go to the free list of their partition and
+/// are handed out again before new ones.
 pub struct Heap {
-    values_by_address: HashMap<i64, Value>,
+    cells: Pages,
     next_address_general: i64,
     next_address_reserved: i64,
     free_list_general: Vec<i64>,
     free_list_reserved: Vec<i64>,
     total_limit: i64,
+    reserved_ratio: f64,
+    reserved_start: i64,
+    growth: HeapGrowth,
     general_limit: i64,
     reserved_limit: i64,
     general_allocated: i64,
     reserved_allocated: i64,
+    reserved_addresses: Bitmap,
+    /// Cells allocated in one block with the cell right before them.
+    block_continuations: Bitmap,
+    /// No general cell below this address is free, so searches for a block
+    /// of free cells can start here.
+    first_free_general: i64,
+}
+
+/// How the heap reacts when a partition is full.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum HeapGrowth {
+    /// The heap keeps its initial size.
+    #[default]
+    Fixed,
+    /// The heap doubles its size, but never grows past the given size.
+    Doubling(i64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Partition {
+    General,
+    Reserved,
+}
+
+impl std::fmt::Display for Partition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Partition::General => write!(f, "general"),
+            Partition::Reserved => write!(f, "reserved"),
+        }
+    }
+}
+
+/// The state of a partition an allocation did not fit in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PartitionUsage {
+    pub partition: Partition,
+    pub allocated: i64,
+    pub requested: i64,
+    pub limit: i64,
+    pub heap_size: i64,
 }
 
 #[derive(Debug)]
 pub enum HeapError {
     OutOfMemory,
     InvalidAddress(i64),
-    PartitionLimitExceeded,
+    PartitionLimitExceeded(PartitionUsage),
 }
 
 impl Heap {
@@ -28,126 +80,177 @@ impl Heap {
         let general_limit = total_limit - reserved_limit;
 
         Self {
-            values_by_address: HashMap::new(),
+            cells: Pages::default(),
             next_address_general: 0,
             next_address_reserved: reserved_limit, // Start reserved addresses from reserved_limit
             free_list_general: Vec::new(),
             free_list_reserved: Vec::new(),
             total_limit,
+            reserved_ratio,
+            reserved_start: reserved_limit,
+            growth: HeapGrowth::Fixed,
             general_limit,
             reserved_limit,
             general_allocated: 0,
             reserved_allocated: 0,
+            reserved_addresses: Bitmap::default(),
+            block_continuations: Bitmap::default(),
+            first_free_general: 0,
         }
     }
 
-    pub fn allocate_address(&mut self, reserved: bool) -> Result<i64, HeapError> {
-        if reserved && self.reserved_allocated >= self.reserved_limit {
-            return Err(HeapError::PartitionLimitExceeded);
-        } else if !reserved && self.general_allocated >= self.general_limit {
-            return Err(HeapError::PartitionLimitExceeded);
+    pub fn set_growth(&mut self, growth: HeapGrowth) {
+        self.growth = growth;
+    }
+
+    /// Number of cells the heap holds now.
+    pub fn size(&self) -> i64 {
+        self.total_limit
+    }
+
+    /// Doubles the heap, keeping the reserved ratio, if the growth policy
+    /// allows it. Returns whether the heap grew.
+    pub fn grow(&mut self) -> bool {
+        let HeapGrowth::Doubling(max_size) = self.growth else {
+            return false;
+        };
+        let size = self.total_limit.saturating_mul(2).min(max_size);
+        if size <= self.total_limit {
+            return false;
         }
+        self.total_limit = size;
+        self.reserved_limit = (size as f64 * self.reserved_ratio) as i64;
+        self.general_limit = size - self.reserved_limit;
+        true
+    }
 
-        let address = if reserved {
-            if let Some(address) = self.free_list_reserved.pop() {
-                self.reserved_allocated += 1;
-                address
-            } else {
-                let address = self.next_address_reserved;
-                self.next_address_reserved += 1;
-                self.reserved_allocated += 1;
-                address
-            }
+    /// Fails unless `count` more cells fit in the partition.
+    fn check_limit(&self, count: i64, reserved: bool) -> Result<(), HeapError> {
+        let (partition, allocated, limit) = if reserved {
+            (
+                Partition::Reserved,
+                self.reserved_allocated,
+                self.reserved_limit,
+            )
         } else {
-            if let Some(address) = self.free_list_general.pop() {
-                self.general_allocated += 1;
-                address
+            (
+                Partition::General,
+                self.general_allocated,
+                self.general_limit,
+            )
+        };
+        if allocated + count <= limit {
+            return Ok(());
+        }
+        Err(HeapError::PartitionLimitExceeded(PartitionUsage {
+            partition,
+            allocated,
+            requested: count,
+            limit,
+            heap_size: self.total_limit,
+        }))
+    }
+
+    /// Takes an address from the free list, or a new one, skipping
+    /// addresses that became occupied in the meantime.
+    fn next_free_address(&mut self, reserved: bool) -> i64 {
+        loop {
+            let (free_list, next_address) = if reserved {
+                (
+                    &mut self.free_list_reserved,
+                    &mut self.next_address_reserved,
+                )
             } else {
-                let address = self.next_address_general;
-                self.next_address_general += 1;
-                self.general_allocated += 1;
-                address
+                (&mut self.free_list_general, &mut self.next_address_general)
+            };
+            let address = free_list.pop().unwrap_or_else(|| {
+                *next_address += 1;
+                *next_address - 1
+            });
+            if !self.cells.contains(address) {
+                return address;
             }
-        };
-        if self.is_address_free(address) {
-            self.values_by_address.insert(address, Value::Null);
-            Ok(address)
+        }
+    }
+
+    /// The first address from `from` on that starts `count` free cells.
+    fn first_free_block(&self, from: i64, count: usize) -> i64 {
+        let mut start = from;
+        while let Some(occupied) =
+            (start..start + count as i64).rfind(|&address| self.cells.contains(address))
+        {
+            start = occupied + 1;
+        }
+        start
+    }
+
+    /// Marks `address` as allocated in its partition.
+    fn occupy(&mut self, address: i64, reserved: bool) {
+        self.cells.insert(address, Value::Null);
+        if reserved {
+            self.reserved_allocated += 1;
+            self.reserved_addresses.insert(address as usize);
         } else {
-            self.allocate_address(reserved)
+            self.general_allocated += 1;
         }
     }
 
+    pub fn allocate_address(&mut self, reserved: bool) -> Result<i64, HeapError> {
+        self.check_limit(1, reserved)?;
+        let address = self.next_free_address(reserved);
+        self.occupy(address, reserved);
+        Ok(address)
+    }
+
     pub fn lookup_address(&self, address: i64) -> Result<Value, HeapError> {
-        self.values_by_address
-            .get(&address)
+        self.cells
+            .get(address)
             .cloned()
             .ok_or(HeapError::InvalidAddress(address))
     }
 
+    /// Allocates `count` cells with consecutive addresses. General blocks
+    /// reuse the first gap of free cells that fits.
     pub fn allocate_consecutive_addresses(
         &mut self,
         count: usize,
         reserved: bool,
     ) -> Result<Vec<i64>, HeapError> {
-        if reserved && (self.reserved_allocated + count as i64) > self.reserved_limit {
-            return Err(HeapError::PartitionLimitExceeded);
-        } else if !reserved && (self.general_allocated + count as i64) > self.general_limit {
-            return Err(HeapError::PartitionLimitExceeded);
-        }
+        self.check_limit(count as i64, reserved)?;
 
-        for start in 0..self.next_address_general {
-            if self.is_block_free(start, count) {
-                let addresses = (start..start + count as i64).collect::<Vec<_>>();
-                for &address in &addresses {
-                    if reserved {
-                        self.free_list_reserved.retain(|&x| x != address);
-                    } else {
-                        self.free_list_general.retain(|&x| x != address);
-                    }
-                }
-                if reserved {
-                    self.reserved_allocated += count as i64;
-                } else {
-                    self.general_allocated += count as i64;
-                }
-                return Ok(addresses);
-            }
-        }
-
-        let start_address = if reserved {
-            let start_address = self.next_address_reserved;
-            self.next_address_reserved += count as i64;
-            self.reserved_allocated += count as i64;
-            start_address
+        let start = if reserved {
+            let start = self.first_free_block(self.next_address_reserved, count);
+            self.next_address_reserved = start + count as i64;
+            start
         } else {
-            let start_address = self.next_address_general;
-            self.next_address_general += count as i64;
-            self.general_allocated += count as i64;
-            start_address
+            let gap =
+                self.cells
+                    .find_free_run(self.first_free_general, self.next_address_general, count);
+            gap.unwrap_or_else(|| {
+                let start = self.first_free_block(self.next_address_general, count);
+                self.next_address_general = start + count as i64;
+                start
+            })
         };
-
-        Ok((start_address..start_address + count as i64).collect())
-    }
-
-    fn is_address_free(&self, address: i64) -> bool {
-        !self.values_by_address.contains_key(&address)
-    }
-
-    fn is_block_free(&self, start: i64, count: usize) -> bool {
-        for i in 0..count {
-            if self.values_by_address.contains_key(&(start + i as i64))
-                || self.free_list_general.contains(&(start + i as i64))
-                || self.free_list_reserved.contains(&(start + i as i64))
-            {
-                return false;
-            }
+        let addresses: Vec<i64> = (start..start + count as i64).collect();
+        for &address in &addresses {
+            self.occupy(address, reserved);
         }
-        true
+        for &address in addresses.iter().skip(1) {
+            self.block_continuations.insert(address as usize);
+        }
+        if !reserved {
+            self.first_free_general = self
+                .cells
+                .find_free_run(self.first_free_general, self.next_address_general, 1)
+                .unwrap_or(self.next_address_general);
+        }
+        Ok(addresses)
     }
 
     pub fn store(&mut self, address: i64, value: Value) -> Result<(), HeapError> {
-        if address < self.total_limit {
-            self.values_by_address.insert(address, value);
+        if (0..self.total_limit).contains(&address) {
+            self.cells.insert(address, value);
             Ok(())
         } else {
             Err(HeapError::InvalidAddress(address))
@@ -156,34 +259,105 @@ impl Heap {
 
     pub fn store_value(&mut self, value: Value, reserved: bool) -> Result<i64, HeapError> {
         let address = self.allocate_address(reserved)?;
-        self.values_by_address.insert(address, value);
+        self.cells.insert(address, value);
         Ok(address)
     }
 
+    /// Returns the cell at `address` to the free list of its partition.
+    fn release(&mut self, address: i64, reserved: bool) {
+        self.block_continuations.remove(address as usize);
+        self.block_continuations.remove(address as usize + 1);
+        if reserved {
+            self.free_list_reserved.push(address);
+            self.reserved_allocated -= 1;
+            self.reserved_addresses.remove(address as usize);
+        } else {
+            self.free_list_general.push(address);
+            self.general_allocated = (self.general_allocated - 1).max(0);
+            self.first_free_general = self.first_free_general.min(address);
+        }
+    }
+
     pub fn free(&mut self, address: i64, reserved: bool) -> Result<(), HeapError> {
-        if self.values_by_address.contains_key(&address) {
-            self.values_by_address.remove(&address);
-            if reserved {
-                self.free_list_reserved.push(address);
-                self.reserved_allocated -= 1;
-            } else {
-                self.free_list_general.push(address);
-                self.general_allocated -= 1;
+        match self.cells.remove(address) {
+            Some(_) => {
+                self.release(address, reserved);
+                Ok(())
             }
-            Ok(())
-        } else {
-            Err(HeapError::InvalidAddress(address))
+            None => Err(HeapError::InvalidAddress(address)),
         }
     }
 
+    /// The occupied cells in increasing address order.
+    pub fn cells(&self) -> impl Iterator<Item = (i64, &Value)> + '_ {
+        self.cells.iter()
+    }
+
+    /// Whether `address` was allocated in the reserved partition.
+    pub fn is_reserved(&self, address: i64) -> bool {
+        usize::try_from(address).is_ok_and(|a| self.reserved_addresses.contains(a))
+    }
+
     pub fn lookup_values_general(&self, values: Vec<Value>) -> Vec<Value> {
-        let selected = self
-            .values_by_address
+        self.cells
+            .iter()
+            .filter(|(address, value)| *address < self.reserved_start && values.contains(value))
+            .map(|(address, _)| Value::new_address(address))
+            .collect()
+    }
+
+    /// The occupied cells that cannot be reached from `roots`, following the
+    /// addresses the cells hold and the integers that are addresses of
+    /// occupied cells, in address order. Reaching a cell reaches its whole
+    /// block, since the others are found from it by address arithmetic.
+    pub fn unreachable(&self, roots: impl IntoIterator<Item = i64>) -> Vec<i64> {
+        let mut marked = Bitmap::default();
+        let mut pending: Vec<i64> = roots.into_iter().collect();
+        while let Some(address) = pending.pop() {
+            let Some(value) = self.cells.get(address) else {
+                continue;
+            };
+            if !marked.insert(address as usize) {
+                continue;
+            }
+            if self.block_continuations.contains(address as usize) {
+                pending.push(address - 1);
+            }
+            if self.block_continuations.contains(address as usize + 1) {
+                pending.push(address + 1);
+            }
+            if let Value::Address(next) | Value::Int(next) = value {
+                pending.push(*next);
+            }
+        }
+        self.cells
             .iter()
-            .filter(|(&key, v)| values.contains(v) && key < self.reserved_limit)
-            .map(|(&key, _value)| Value::new_int(key))
-            .collect();
+            .map(|(address, _)| address)
+            .filter(|&address| !marked.contains(address as usize))
+            .collect()
+    }
+
+    /// Frees every general cell that cannot be reached from `roots` or from
+    /// a reserved cell and returns how many cells were freed.
+    ///
+    /// Tracing is conservative: an address may be kept as an integer (see
+    /// `ToInt`), so an integer in a reachable cell is followed whenever it
+    /// is the address of an allocated cell.
+    pub fn collect_garbage(&mut self, roots: impl IntoIterator<Item = i64>) -> usize {
+        let reserved = self.reserved_addresses.iter().map(|a| a as i64);
+        let roots: Vec<i64> = roots.into_iter().chain(reserved).collect();
+        let garbage = self.unreachable(roots);
+        // the free list is a stack: the lowest addresses are reused first
+        for &address in garbage.iter().rev() {
+            self.cells.remove(address);
+            self.release(address, false);
+        }
+        garbage.len()
+    }
+}
 
-        selected
+impl fmt::Debug for Heap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.cells)
     }
 }
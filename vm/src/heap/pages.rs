@@ -0,0 +1,194 @@
+use std::fmt;
+
+use value::Value;
+
+const PAGE_BITS: u32 = 10;
+const PAGE_SIZE: usize = 1 << PAGE_BITS;
+
+/// A growable set of addresses, one bit per address.
+#[derive(Clone, Default)]
+pub struct Bitmap {
+    words: Vec<u64>,
+}
+
+impl Bitmap {
+    pub fn contains(&self, index: usize) -> bool {
+        self.words
+            .get(index / 64)
+            .is_some_and(|word| word & (1 << (index % 64)) != 0)
+    }
+
+    /// Adds `index`, returns whether it was not there yet.
+    pub fn insert(&mut self, index: usize) -> bool {
+        let word = index / 64;
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        let was_set = self.words[word] & (1 << (index % 64)) != 0;
+        self.words[word] |= 1 << (index % 64);
+        !was_set
+    }
+
+    /// Removes `index`, returns whether it was there.
+    pub fn remove(&mut self, index: usize) -> bool {
+        let was_set = self.contains(index);
+        if was_set {
+            self.words[index / 64] &= !(1 << (index % 64));
+        }
+        was_set
+    }
+
+    /// The indices in the set, in increasing order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, &word)| {
+            let mut word = word;
+            std::iter::from_fn(move || {
+                if word == 0 {
+                    return None;
+                }
+                let bit = word.trailing_zeros() as usize;
+                word &= word - 1;
+                Some(i * 64 + bit)
+            })
+        })
+    }
+}
+
+struct Page {
+    cells: Vec<Value>,
+    occupied: Bitmap,
+}
+
+/// Heap cells stored by address in fixed-size pages, which are allocated
+/// when a cell in them is first written. A cell is either occupied, holding
+/// a value, or free.
+#[derive(Default)]
+pub struct Pages {
+    pages: Vec<Option<Box<Page>>>,
+}
+
+/// Splits a non-negative address into a page number and an offset.
+fn locate(address: i64) -> Option<(usize, usize)> {
+    let address = usize::try_from(address).ok()?;
+    Some((address >> PAGE_BITS, address & (PAGE_SIZE - 1)))
+}
+
+impl Pages {
+    fn page(&self, page: usize) -> Option<&Page> {
+        self.pages.get(page)?.as_deref()
+    }
+
+    pub fn get(&self, address: i64) -> Option<&Value> {
+        let (page, offset) = locate(address)?;
+        let page = self.page(page)?;
+        page.occupied.contains(offset).then(|| &page.cells[offset])
+    }
+
+    pub fn contains(&self, address: i64) -> bool {
+        self.get(address).is_some()
+    }
+
+    /// Occupies the cell at `address` with `value`; negative addresses are
+    /// ignored, the heap never hands them out.
+    pub fn insert(&mut self, address: i64, value: Value) {
+        let Some((page, offset)) = locate(address) else {
+            return;
+        };
+        if page >= self.pages.len() {
+            self.pages.resize_with(page + 1, || None);
+        }
+        let page = self.pages[page].get_or_insert_with(|| {
+            Box::new(Page {
+                cells: vec![Value::Null; PAGE_SIZE],
+                occupied: Bitmap::default(),
+            })
+        });
+        page.cells[offset] = value;
+        page.occupied.insert(offset);
+    }
+
+    /// Frees the cell at `address`, returning its value if it was occupied.
+    pub fn remove(&mut self, address: i64) -> Option<Value> {
+        let (page, offset) = locate(address)?;
+        let page = self.pages.get_mut(page)?.as_deref_mut()?;
+        if !page.occupied.remove(offset) {
+            return None;
+        }
+        Some(std::mem::replace(&mut page.cells[offset], Value::Null))
+    }
+
+    /// The occupied cells in increasing address order.
+    pub fn iter(&self) -> impl Iterator<Item = (i64, &Value)> + '_ {
+        self.pages.iter().enumerate().flat_map(|(number, page)| {
+            page.iter().flat_map(move |page| {
+                page.occupied.iter().map(move |offset| {
+                    (((number << PAGE_BITS) + offset) as i64, &page.cells[offset])
+                })
+            })
+        })
+    }
+
+    /// The first address in `from..to` starting `count` free cells that end
+    /// before `to`.
+    pub fn find_free_run(&self, from: i64, to: i64, count: usize) -> Option<i64> {
+        let mut start = from.max(0);
+        let mut address = start;
+        while address < to {
+            if address - start == count as i64 {
+                return Some(start);
+            }
+            if self.contains(address) {
+                start = address + 1;
+            }
+            address += 1;
+        }
+        (to - start >= count as i64).then_some(start)
+    }
+}
+
+impl fmt::Debug for Pages {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bitmap() {
+        let mut bitmap = Bitmap::default();
+        assert!(bitmap.insert(3));
+        assert!(!bitmap.insert(3));
+        assert!(bitmap.insert(130));
+        assert!(bitmap.contains(130) && !bitmap.contains(129));
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), [3, 130]);
+        assert!(bitmap.remove(3));
+        assert!(!bitmap.remove(3));
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), [130]);
+    }
+
+    #[test]
+    fn test_pages() {
+        let mut pages = Pages::default();
+        pages.insert(1, Value::new_int(1));
+        pages.insert(PAGE_SIZE as i64 * 3 + 2, Value::new_int(2));
+        assert_eq!(pages.get(1), Some(&Value::new_int(1)));
+        assert_eq!(pages.get(2), None);
+        assert_eq!(pages.get(-1), None);
+        assert_eq!(
+            pages.iter().map(|(address, _)| address).collect::<Vec<_>>(),
+            [1, PAGE_SIZE as i64 * 3 + 2]
+        );
+        assert_eq!(pages.remove(1), Some(Value::new_int(1)));
+        assert_eq!(pages.remove(1), None);
+
+        pages.insert(1, Value::Null);
+        pages.insert(4, Value::Null);
+        assert_eq!(pages.find_free_run(0, 10, 1), Some(0));
+        assert_eq!(pages.find_free_run(0, 10, 2), Some(2));
+        assert_eq!(pages.find_free_run(0, 10, 3), Some(5));
+        assert_eq!(pages.find_free_run(0, 7, 3), None);
+    }
+}
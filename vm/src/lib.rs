@@ -1,19 +1,252 @@
 pub mod builtins;
+pub mod config;
 pub mod heap;
+pub mod register;
+pub mod sanitizer;
 pub mod scope;
+pub mod verifier;
 pub mod vm;
 
-use builtins::{print::builtin_print, string::*};
-use codegen::bytecode::Bytecode;
-use vm::{error::VMError, VM};
+use builtins::STANDARD_BUILTINS;
+use codegen::bytecode::{line_table::LineTable, Bytecode};
+use config::VmConfig;
+use vm::{
+    error::{LocatedVMError, VMError},
+    VM,
+};
 
-pub fn execute_bytecode(bytecode: Vec<Bytecode>) -> Result<(), VMError> {
-    let mut vm = VM::new(bytecode);
-    vm.register_builtin("Print", builtin_print);
-    vm.register_builtin("CharAt", builtin_char_at);
-    vm.register_builtin("Concat", builtin_concat);
-    vm.register_builtin("Replace", builtin_replace);
-    vm.register_builtin("SubString", builtin_substring);
+/// Creates a VM for `bytecode` with the standard builtins registered.
+pub fn new_vm(bytecode: Vec<Bytecode>, config: &VmConfig) -> VM {
+    let mut vm = VM::with_config(bytecode, config);
+    for (name, function) in STANDARD_BUILTINS {
+        vm.register_builtin(name, *function);
+    }
+    vm
+}
 
+/// Verifies `bytecode` and runs it.
+pub fn execute_bytecode(bytecode: Vec<Bytecode>, config: &VmConfig) -> Result<(), VMError> {
+    config.validate().map_err(VMError::InvalidConfig)?;
+    let mut vm = new_vm(bytecode, config);
+    vm.verify().map_err(VMError::InvalidBytecode)?;
     vm.run()
 }
+
+/// Verifies and runs `bytecode`, locating a failure with `line_table`.
+/// Verification errors are located at the first problem found.
+pub fn execute_bytecode_with_line_table(
+    bytecode: Vec<Bytecode>,
+    line_table: &LineTable,
+    config: &VmConfig,
+) -> Result<(), Box<LocatedVMError>> {
+    if let Err(message) = config.validate() {
+        return Err(Box::new(LocatedVMError {
+            error: VMError::InvalidConfig(message),
+            pc: 0,
+            span: None,
+        }));
+    }
+    run_with_line_table(&mut new_vm(bytecode, config), line_table)
+}
+
+/// Verifies and runs the program loaded in `vm`, locating a failure with
+/// `line_table`. The VM is left as the program left it, so its heap can be
+/// inspected afterwards.
+pub fn run_with_line_table(vm: &mut VM, line_table: &LineTable) -> Result<(), Box<LocatedVMError>> {
+    if let Err(errors) = vm.verify() {
+        let pc = errors[0].offset();
+        return Err(Box::new(LocatedVMError {
+            error: VMError::InvalidBytecode(errors),
+            pc,
+            span: line_table.get(pc),
+        }));
+    }
+    vm.run().map_err(|error| {
+        Box::new(LocatedVMError {
+            error,
+            pc: vm.current_pc(),
+            span: line_table.get(vm.current_pc()),
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codegen::{gen_bytecode, gen_bytecode_with_line_table, ir, opt::optimize};
+    use config::Backend;
+    use interpreter::evaluation::{
+        errors::{EvaluationError, RuntimeError},
+        runtime_context::RuntimeContext,
+        Evaluator,
+    };
+    use parser::ast::Algorithm;
+    use std::{
+        cell::RefCell,
+        fs, io,
+        path::{Path, PathBuf},
+        rc::Rc,
+    };
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// What running `bytecode` prints, how it ends and the heap it leaves.
+    fn outcome(bytecode: Vec<Bytecode>) -> (Vec<u8>, Result<(), String>, String) {
+        let output = SharedBuffer::default();
+        let mut vm = new_vm(bytecode, &VmConfig::default());
+        vm.set_output(output.clone());
+        let result = vm.run().map_err(|error| error.to_string());
+        let printed = output.0.take();
+        (printed, result, vm.heap_snapshot().to_json())
+    }
+
+    fn find_examples(dir: &Path, examples: &mut Vec<PathBuf>) {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                find_examples(&path, examples);
+            } else if path.extension().is_some_and(|extension| extension == "adl") {
+                examples.push(path);
+            }
+        }
+    }
+
+    /// Examples, relative to `examples/`, that are known not to compile.
+    const NOT_COMPILING: &[&str] = &["tree/binary_tree/remove/impl.adl"];
+
+    /// Every example with its path relative to `examples/`, each of which
+    /// must parse.
+    fn parsed_examples() -> Vec<(String, Algorithm)> {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../examples");
+        let mut examples = Vec::new();
+        find_examples(&root, &mut examples);
+        examples.sort();
+        assert!(examples.len() > NOT_COMPILING.len());
+        examples
+            .into_iter()
+            .map(|example| {
+                let name = example.strip_prefix(&root).unwrap().display().to_string();
+                let ast = parser::parse_program(example.to_str().unwrap())
+                    .unwrap_or_else(|e| panic!("{}: {}", name, e));
+                (name, ast)
+            })
+            .collect()
+    }
+
+    /// Checks that `example` compiles unless it is in `NOT_COMPILING`.
+    fn expect_compiles<T, E>(example: &str, result: Result<T, E>) -> Option<T> {
+        assert_eq!(
+            result.is_err(),
+            NOT_COMPILING.contains(&example),
+            "{} compiles unexpectedly or fails to",
+            example
+        );
+        result.ok()
+    }
+
+    #[test]
+    fn test_original_notation_example() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../examples/object/person.txt");
+        let ast = parser::parse_program(path.to_str().unwrap()).unwrap();
+        let (printed, result, _) = outcome(gen_bytecode(ast).unwrap());
+        assert_eq!(result, Ok(()));
+        // strings joined with numbers and with the strings `+` reads
+        assert_eq!(
+            String::from_utf8(printed).unwrap(),
+            "Hello, my name is Alice\nI`m 23 years old\nI`m married on Bob\n"
+        );
+    }
+
+    #[test]
+    fn test_assign_to_call() {
+        let ast = parser::parse("ToAddress {3} = 7\nPrint {'3}\n").unwrap();
+        let (printed, result, _) = outcome(gen_bytecode(ast).unwrap());
+        assert_eq!(result, Ok(()));
+        assert_eq!(String::from_utf8(printed).unwrap(), "7\n");
+    }
+
+    #[test]
+    fn test_int_as_address_fails_on_both_backends() {
+        // 'p is the integer 1, which is not an address to store 2 at
+        let source = "p = 5; 'p = 1; ''p = 2\n";
+
+        let bytecode = gen_bytecode(parser::parse(source).unwrap()).unwrap();
+        let mut vm = new_vm(bytecode, &VmConfig::default());
+        let vm_error = match vm.run() {
+            Err(VMError::UnexpectedType(error)) => error.to_string(),
+            other => panic!("expected a type error, got {:?}", other),
+        };
+
+        let Algorithm::Body(lines) = parser::parse(source).unwrap();
+        let mut evaluator = Evaluator::new(lines, RuntimeContext::new());
+        let interpreter_error = match evaluator.eval() {
+            Err(EvaluationError::RuntimeError(_, _, RuntimeError::TypeError(error))) => {
+                error.to_string()
+            }
+            other => panic!("expected a type error, got {:?}", other),
+        };
+
+        assert_eq!(vm_error, "Expect type 'address', but actual : (int: 1)");
+        assert_eq!(interpreter_error, vm_error);
+    }
+
+    #[test]
+    fn test_list_examples_sanitized() {
+        // the examples without sugar build their lists at literal cells,
+        // which the sanitizer rightly reports
+        let examples = parsed_examples().into_iter().filter(|(example, _)| {
+            example.starts_with("list/") && !example.starts_with("list/no_sugar_syntax/")
+        });
+        for (example, ast) in examples {
+            let bytecode = gen_bytecode(ast).unwrap();
+            for backend in [Backend::Stack, Backend::Register] {
+                let config = VmConfig {
+                    sanitize: true,
+                    backend,
+                    ..VmConfig::default()
+                };
+                let mut vm = new_vm(bytecode.clone(), &config);
+                vm.set_output(SharedBuffer::default());
+                assert!(vm.run().is_ok(), "{}", example);
+                assert_eq!(vm.memory_violations(), &[], "{} {:?}", example, backend);
+            }
+        }
+    }
+
+    #[test]
+    fn test_optimized_examples() {
+        for (example, ast) in parsed_examples() {
+            let result = gen_bytecode_with_line_table(ast);
+            let Some((bytecode, line_table)) = expect_compiles(&example, result) else {
+                continue;
+            };
+            let (optimized, _) = optimize(bytecode.clone(), &line_table);
+            assert!(optimized.len() <= bytecode.len());
+            assert_eq!(outcome(optimized), outcome(bytecode), "{}", example);
+        }
+    }
+
+    #[test]
+    fn test_ir_examples() {
+        for (example, ast) in parsed_examples() {
+            let cfg = expect_compiles(&example, ir::build(&ast));
+            let Some(bytecode) = expect_compiles(&example, gen_bytecode(ast)) else {
+                continue;
+            };
+            let (lowered, _) = ir::lower(&cfg.unwrap());
+            assert_eq!(outcome(lowered), outcome(bytecode), "{}", example);
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use codegen::bytecode::Bytecode;
 use value::Value;
-use vm::execute_bytecode;
+use vm::{config::VmConfig, execute_bytecode};
 
 fn main() {
     env_logger::init();
@@ -14,6 +14,6 @@ fn main() {
         Bytecode::LoadVar("y".to_string()),
         Bytecode::Halt,
     ];
-    execute_bytecode(bytecode);
+    execute_bytecode(bytecode, &VmConfig::default());
 }
 // RUST_LOG=trace cargo run
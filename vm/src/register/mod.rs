@@ -0,0 +1,434 @@
+//! A register-based instruction set, lowered from `Bytecode`.
+//!
+//! Every value the stack VM would push gets a register instead, and the
+//! instruction that would pop it reads the register. Constants are not
+//! loaded at all: their consumer reads them from the constant pool. `Dup`,
+//! `Swap`, `Pop` and `Label` only rearrange the stack and lower to nothing.
+//!
+//! Registers are shared by every frame, so no value may stay on the stack
+//! across a jump target, a subprogram entry or a call's continuation. The
+//! code generator never leaves one that is used afterwards.
+
+use codegen::bytecode::Bytecode;
+use value::{error::ValueError, Value};
+
+/// Where an instruction reads a value from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Register(usize),
+    /// An index into `RegisterProgram::constants`.
+    Constant(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    And,
+    Or,
+    Equal,
+    NotEqual,
+    Greater,
+    Less,
+    GreaterEqual,
+    LessEqual,
+}
+
+impl BinaryOp {
+    pub fn function(self) -> fn(&Value, &Value) -> Result<Value, ValueError> {
+        match self {
+            BinaryOp::Add => Value::sum,
+            BinaryOp::Sub => Value::sub,
+            BinaryOp::Mul => Value::mul,
+            BinaryOp::Div => Value::div,
+            BinaryOp::Mod => Value::modulus,
+            BinaryOp::And => Value::and,
+            BinaryOp::Or => Value::or,
+            BinaryOp::Equal => Value::eq,
+            BinaryOp::NotEqual => Value::ne,
+            BinaryOp::Greater => Value::gt,
+            BinaryOp::Less => Value::lt,
+            BinaryOp::GreaterEqual => Value::ge,
+            BinaryOp::LessEqual => Value::le,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Not,
+    Negate,
+}
+
+impl UnaryOp {
+    pub fn function(self) -> fn(&Value) -> Result<Value, ValueError> {
+        match self {
+            UnaryOp::Not => Value::not,
+            UnaryOp::Negate => Value::negate,
+        }
+    }
+}
+
+/// A register instruction. The destination register comes first; jump and
+/// call targets are instruction indices.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    Binary(BinaryOp, usize, Operand, Operand),
+    Unary(UnaryOp, usize, Operand),
+    LoadSlot(usize, usize), // register, slot
+    StoreSlot(usize),
+    BindSlot(usize, Operand), // slot, address
+    Deref(usize, Operand),
+    MulDeref(usize, Operand, Operand), // register, address, count
+    Store(Operand, Operand),           // value, address
+    StoreAddr(usize, Operand),
+    Alloc(usize),
+    AllocMany(Vec<usize>),
+    FreeAddr(Operand),
+    Exchange(Operand, Operand),
+    Jump(usize),
+    JumpIfFalse(Operand, usize),
+    CallBuiltin(usize, String, Vec<Operand>),
+    CallSubProgram(usize, usize), // entry, continuation
+    Return,
+    Halt,
+    PushScope,
+    PopScope,
+}
+
+/// Lowered code with the constants it reads.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegisterProgram {
+    pub code: Vec<Instruction>,
+    pub constants: Vec<Value>,
+    /// Offset of the `Bytecode` instruction each one was lowered from, for
+    /// line tables and the sanitizer.
+    pub origins: Vec<usize>,
+    /// Number of registers the code uses.
+    pub registers: usize,
+}
+
+/// Bytecode the register backend cannot run, with the offset of the
+/// offending instruction.
+#[derive(PartialEq, Eq)]
+pub enum LowerError {
+    ValueAcrossJump(usize),
+    UnresolvedVariable(usize, String),
+}
+
+impl LowerError {
+    pub fn offset(&self) -> usize {
+        match self {
+            LowerError::ValueAcrossJump(offset) | LowerError::UnresolvedVariable(offset, _) => {
+                *offset
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for LowerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "instruction {}: ", self.offset())?;
+        match self {
+            LowerError::ValueAcrossJump(_) => {
+                write!(f, "uses a value pushed before a jump target")
+            }
+            LowerError::UnresolvedVariable(_, name) => {
+                write!(f, "variable '{}' is not resolved to a slot", name)
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for LowerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+/// Lowers `bytecode`, whose variables must be resolved to slots (see
+/// `codegen::bytecode::slots`).
+pub fn lower(bytecode: &[Bytecode]) -> Result<RegisterProgram, LowerError> {
+    let mut lowering = Lowering::default();
+    let entries = entries(bytecode);
+    let mut indices = Vec::with_capacity(bytecode.len() + 1);
+    for (offset, instruction) in bytecode.iter().enumerate() {
+        if entries[offset] {
+            lowering.stack.clear();
+        }
+        indices.push(lowering.code.len());
+        lowering.offset = offset;
+        lowering.lower(instruction)?;
+    }
+    indices.push(lowering.code.len());
+
+    // targets past the end finish the program, as in the stack VM
+    let index = |target: usize| {
+        indices
+            .get(target)
+            .copied()
+            .unwrap_or(indices[bytecode.len()])
+    };
+    for instruction in &mut lowering.code {
+        match instruction {
+            Instruction::Jump(target) | Instruction::JumpIfFalse(_, target) => {
+                *target = index(*target)
+            }
+            Instruction::CallSubProgram(entry, continuation) => {
+                *entry = index(*entry);
+                *continuation = index(*continuation);
+            }
+            _ => {}
+        }
+    }
+    Ok(RegisterProgram {
+        code: lowering.code,
+        constants: lowering.constants,
+        origins: lowering.origins,
+        registers: lowering.registers,
+    })
+}
+
+/// Offsets control may reach other than from the previous instruction.
+fn entries(bytecode: &[Bytecode]) -> Vec<bool> {
+    let mut entries = vec![false; bytecode.len() + 3];
+    entries[0] = true;
+    for (offset, instruction) in bytecode.iter().enumerate() {
+        match instruction {
+            Bytecode::Jump(target) | Bytecode::JumpIfFalse(target) => {
+                if let Some(entry) = entries.get_mut(*target) {
+                    *entry = true;
+                }
+            }
+            Bytecode::CallSubProgram(target, _) => {
+                if let Some(entry) = entries.get_mut(*target) {
+                    *entry = true;
+                }
+                // a return resumes past the `PopScope` following the call
+                entries[offset + 2] = true;
+            }
+            _ => {}
+        }
+    }
+    entries
+}
+
+#[derive(Default)]
+struct Lowering {
+    code: Vec<Instruction>,
+    constants: Vec<Value>,
+    origins: Vec<usize>,
+    registers: usize,
+    /// The operand stack of the stack VM at `offset`.
+    stack: Vec<Operand>,
+    offset: usize,
+}
+
+impl Lowering {
+    fn lower(&mut self, instruction: &Bytecode) -> Result<(), LowerError> {
+        match instruction {
+            Bytecode::Constant(value) => {
+                self.constants.push(value.clone());
+                self.stack.push(Operand::Constant(self.constants.len() - 1));
+            }
+            Bytecode::LoadSlot(slot) => self.produce(|dst| Instruction::LoadSlot(dst, *slot)),
+            Bytecode::StoreSlot(slot) => self.emit(Instruction::StoreSlot(*slot)),
+            Bytecode::BindSlot(slot) => {
+                let address = self.pop()?;
+                self.emit(Instruction::BindSlot(*slot, address));
+            }
+            Bytecode::LoadVar(name) | Bytecode::StoreVar(name) | Bytecode::BindAddr(name) => {
+                return Err(LowerError::UnresolvedVariable(self.offset, name.clone()))
+            }
+            Bytecode::Add => self.binary(BinaryOp::Add)?,
+            Bytecode::Sub => self.binary(BinaryOp::Sub)?,
+            Bytecode::Mul => self.binary(BinaryOp::Mul)?,
+            Bytecode::Div => self.binary(BinaryOp::Div)?,
+            Bytecode::Mod => self.binary(BinaryOp::Mod)?,
+            Bytecode::And => self.binary(BinaryOp::And)?,
+            Bytecode::Or => self.binary(BinaryOp::Or)?,
+            Bytecode::Equal => self.binary(BinaryOp::Equal)?,
+            Bytecode::NotEqual => self.binary(BinaryOp::NotEqual)?,
+            Bytecode::Greater => self.binary(BinaryOp::Greater)?,
+            Bytecode::Less => self.binary(BinaryOp::Less)?,
+            Bytecode::GreaterEqual => self.binary(BinaryOp::GreaterEqual)?,
+            Bytecode::LessEqual => self.binary(BinaryOp::LessEqual)?,
+            Bytecode::Not => self.unary(UnaryOp::Not)?,
+            Bytecode::Negate => self.unary(UnaryOp::Negate)?,
+            Bytecode::Jump(target) => self.emit(Instruction::Jump(*target)),
+            Bytecode::JumpIfFalse(target) => {
+                let condition = self.pop()?;
+                self.emit(Instruction::JumpIfFalse(condition, *target));
+            }
+            Bytecode::Label(_) => {}
+            Bytecode::Pop => {
+                self.pop()?;
+            }
+            Bytecode::CallBuiltin(name, argc) => {
+                let mut args = Vec::with_capacity(*argc);
+                for _ in 0..*argc {
+                    args.push(self.pop()?);
+                }
+                args.reverse();
+                self.produce(|dst| Instruction::CallBuiltin(dst, name.clone(), args));
+            }
+            Bytecode::CallSubProgram(target, _) => {
+                self.emit(Instruction::CallSubProgram(*target, self.offset + 2))
+            }
+            Bytecode::Return => self.emit(Instruction::Return),
+            Bytecode::Halt => self.emit(Instruction::Halt),
+            Bytecode::Deref => {
+                let address = self.pop()?;
+                self.produce(|dst| Instruction::Deref(dst, address));
+            }
+            Bytecode::MulDeref => {
+                let count = self.pop()?;
+                let address = self.pop()?;
+                self.produce(|dst| Instruction::MulDeref(dst, address, count));
+            }
+            Bytecode::Store => {
+                let address = self.pop()?;
+                let value = self.pop()?;
+                self.emit(Instruction::Store(value, address));
+            }
+            Bytecode::Alloc => self.produce(Instruction::Alloc),
+            Bytecode::AllocMany(count) => {
+                let registers: Vec<usize> = (0..*count)
+                    .map(|_| {
+                        let register = self.free_register();
+                        self.stack.push(Operand::Register(register));
+                        register
+                    })
+                    .collect();
+                self.emit(Instruction::AllocMany(registers));
+            }
+            Bytecode::Dup => {
+                let top = *self.stack.last().ok_or(self.underflow())?;
+                self.stack.push(top);
+                self.stack.push(top);
+            }
+            Bytecode::StoreAddr => {
+                let value = self.pop()?;
+                self.produce(|dst| Instruction::StoreAddr(dst, value));
+            }
+            Bytecode::PushScope => self.emit(Instruction::PushScope),
+            Bytecode::PopScope => self.emit(Instruction::PopScope),
+            Bytecode::FreeAddr => {
+                let address = self.pop()?;
+                self.emit(Instruction::FreeAddr(address));
+            }
+            Bytecode::Swap => {
+                let len = self.stack.len();
+                if len < 2 {
+                    return Err(self.underflow());
+                }
+                self.stack.swap(len - 1, len - 2);
+            }
+            Bytecode::Exchange => {
+                let rhs = self.pop()?;
+                let lhs = self.pop()?;
+                self.emit(Instruction::Exchange(lhs, rhs));
+            }
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, instruction: Instruction) {
+        self.code.push(instruction);
+        self.origins.push(self.offset);
+    }
+
+    /// Emits an instruction writing a fresh register, which it pushes.
+    fn produce(&mut self, instruction: impl FnOnce(usize) -> Instruction) {
+        let dst = self.free_register();
+        self.emit(instruction(dst));
+        self.stack.push(Operand::Register(dst));
+    }
+
+    fn binary(&mut self, op: BinaryOp) -> Result<(), LowerError> {
+        let rhs = self.pop()?;
+        let lhs = self.pop()?;
+        self.produce(|dst| Instruction::Binary(op, dst, lhs, rhs));
+        Ok(())
+    }
+
+    fn unary(&mut self, op: UnaryOp) -> Result<(), LowerError> {
+        let operand = self.pop()?;
+        self.produce(|dst| Instruction::Unary(op, dst, operand));
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<Operand, LowerError> {
+        self.stack.pop().ok_or(self.underflow())
+    }
+
+    fn underflow(&self) -> LowerError {
+        LowerError::ValueAcrossJump(self.offset)
+    }
+
+    /// The lowest register no value on the stack is held in. Operands are
+    /// read before the destination is written, so an instruction may reuse
+    /// the register of one it popped.
+    fn free_register(&mut self) -> usize {
+        let register = (0..)
+            .find(|register| !self.stack.contains(&Operand::Register(*register)))
+            .expect("a register is free");
+        self.registers = self.registers.max(register + 1);
+        register
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lower() {
+        let bytecode = vec![
+            Bytecode::LoadSlot(0),
+            Bytecode::Dup,
+            Bytecode::Deref,
+            Bytecode::Constant(Value::new_int(1)),
+            Bytecode::Add,
+            Bytecode::Swap,
+            Bytecode::Store,
+            Bytecode::Pop,
+            Bytecode::JumpIfFalse(0),
+            Bytecode::Halt,
+        ];
+        assert_eq!(lower(&bytecode), Err(LowerError::ValueAcrossJump(8)));
+
+        let bytecode = vec![
+            Bytecode::LoadSlot(0),
+            Bytecode::Dup,
+            Bytecode::Deref,
+            Bytecode::Constant(Value::new_int(1)),
+            Bytecode::Add,
+            Bytecode::Swap,
+            Bytecode::Store,
+            Bytecode::Label("loop".to_string()),
+            Bytecode::Constant(Value::new_bool(true)),
+            Bytecode::JumpIfFalse(7),
+            Bytecode::Jump(20),
+        ];
+        let program = lower(&bytecode).unwrap();
+        let (r0, r1) = (Operand::Register(0), Operand::Register(1));
+        assert_eq!(
+            program.code,
+            [
+                Instruction::LoadSlot(0, 0),
+                Instruction::Deref(1, r0),
+                // the sum reuses the register of the value it adds to
+                Instruction::Binary(BinaryOp::Add, 1, r1, Operand::Constant(0)),
+                Instruction::Store(r1, r0),
+                Instruction::JumpIfFalse(Operand::Constant(1), 4),
+                Instruction::Jump(6),
+            ]
+        );
+        assert_eq!(program.origins, [0, 2, 4, 6, 9, 10]);
+        assert_eq!(program.registers, 2);
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::{BTreeMap, HashMap};
+
+/// Cells handed out together by one allocating instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Block {
+    pub start: i64,
+    pub len: usize,
+    /// Offset of the instruction that allocated the block.
+    pub pc: usize,
+}
+
+impl Block {
+    fn contains(&self, address: i64) -> bool {
+        (self.start..self.start + self.len as i64).contains(&address)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Access {
+    Read,
+    Write,
+    Free,
+}
+
+impl std::fmt::Display for Access {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Access::Read => write!(f, "read of"),
+            Access::Write => write!(f, "write to"),
+            Access::Free => write!(f, "free of"),
+        }
+    }
+}
+
+/// A memory mistake found by the sanitizer, with the offset of the
+/// instruction that made it, or, for a leak, of the one that allocated the
+/// leaked cells.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MemoryViolation {
+    UseAfterFree(usize, Access, i64, usize), // offset, access, address, offset of the free
+    DoubleFree(usize, i64, usize),           // offset, address, offset of the first free
+    BlockEscape(usize, Access, i64, Block),  // offset, access, address, block it was computed from
+    Unallocated(usize, Access, i64),
+    Leak(usize, usize), // offset of the allocation, unreachable cells
+}
+
+impl MemoryViolation {
+    pub fn offset(&self) -> usize {
+        match self {
+            MemoryViolation::UseAfterFree(offset, _, _, _)
+            | MemoryViolation::DoubleFree(offset, _, _)
+            | MemoryViolation::BlockEscape(offset, _, _, _)
+            | MemoryViolation::Unallocated(offset, _, _)
+            | MemoryViolation::Leak(offset, _) => *offset,
+        }
+    }
+}
+
+impl std::fmt::Display for MemoryViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "instruction {}: ", self.offset())?;
+        match self {
+            MemoryViolation::UseAfterFree(_, access, address, freed_at) => write!(
+                f,
+                "{} cell {}, which instruction {} freed",
+                access, address, freed_at
+            ),
+            MemoryViolation::DoubleFree(_, address, freed_at) => write!(
+                f,
+                "cell {} is freed again, instruction {} already freed it",
+                address, freed_at
+            ),
+            MemoryViolation::BlockEscape(_, access, address, block) => write!(
+                f,
+                "{} cell {}, computed from an address in the {} cell(s) at {} \
+                 allocated by instruction {}, lands outside of them",
+                access, address, block.len, block.start, block.pc
+            ),
+            MemoryViolation::Unallocated(_, access, address) => {
+                write!(f, "{} cell {}, which was never allocated", access, address)
+            }
+            MemoryViolation::Leak(_, cells) => write!(
+                f,
+                "{} cell(s) allocated here are unreachable from any variable",
+                cells
+            ),
+        }
+    }
+}
+
+impl std::fmt::Debug for MemoryViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+/// An address computed by `+` or `-` from an address in `block`, but
+/// outside of it.
+struct Escape {
+    address: i64,
+    block: Block,
+    offset: usize,
+}
+
+/// Follows the blocks a program allocates and frees and records the
+/// violations of their bounds and lifetimes. It only observes: the VM runs
+/// the program as it would without it.
+#[derive(Default)]
+pub struct Sanitizer {
+    /// The live block each allocated cell belongs to.
+    owners: HashMap<i64, Block>,
+    /// Freed cells, with the offset of the instruction that freed them.
+    freed: HashMap<i64, usize>,
+    escape: Option<Escape>,
+    violations: Vec<MemoryViolation>,
+}
+
+impl Sanitizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn violations(&self) -> &[MemoryViolation] {
+        &self.violations
+    }
+
+    pub fn allocate(&mut self, start: i64, len: usize, offset: usize) {
+        let block = Block {
+            start,
+            len,
+            pc: offset,
+        };
+        for address in start..start + len as i64 {
+            self.owners.insert(address, block);
+            self.freed.remove(&address);
+        }
+    }
+
+    /// Notes the address `result` computed by `+` or `-` from `address`.
+    /// The instruction right after it is checked against the block
+    /// `address` belongs to, as in `'(p + 2)`.
+    pub fn arithmetic(&mut self, address: i64, result: i64, offset: usize) {
+        self.escape = self
+            .owners
+            .get(&address)
+            .copied()
+            .filter(|block| !block.contains(result))
+            .map(|block| Escape {
+                address: result,
+                block,
+                offset,
+            });
+    }
+
+    pub fn access(&mut self, address: i64, access: Access, offset: usize) {
+        // an instruction like `exchange` accesses two cells, only one of
+        // which may be the computed address
+        let escaped = self
+            .escape
+            .as_ref()
+            .is_some_and(|escape| escape.address == address && escape.offset + 1 == offset);
+        if let Some(escape) = self.escape.take().filter(|_| escaped) {
+            self.violations.push(MemoryViolation::BlockEscape(
+                offset,
+                access,
+                address,
+                escape.block,
+            ));
+        } else if let Some(&freed_at) = self.freed.get(&address) {
+            let violation = if access == Access::Free {
+                MemoryViolation::DoubleFree(offset, address, freed_at)
+            } else {
+                MemoryViolation::UseAfterFree(offset, access, address, freed_at)
+            };
+            self.violations.push(violation);
+        } else if !self.owners.contains_key(&address) {
+            self.violations
+                .push(MemoryViolation::Unallocated(offset, access, address));
+        }
+        // the write creates the cell, later accesses to it are fine
+        if access == Access::Write && !self.owners.contains_key(&address) {
+            self.allocate(address, 1, offset);
+        }
+    }
+
+    pub fn free(&mut self, address: i64, offset: usize) {
+        self.access(address, Access::Free, offset);
+        if self.owners.remove(&address).is_some() {
+            self.freed.insert(address, offset);
+        }
+    }
+
+    /// Reports the `unreachable` cells, grouped by the instruction that
+    /// allocated them.
+    pub fn check_leaks(&mut self, unreachable: impl IntoIterator<Item = i64>) {
+        let mut leaks: BTreeMap<usize, usize> = BTreeMap::new();
+        for address in unreachable {
+            if let Some(block) = self.owners.get(&address) {
+                *leaks.entry(block.pc).or_default() += 1;
+            }
+        }
+        self.violations.extend(
+            leaks
+                .into_iter()
+                .map(|(offset, cells)| MemoryViolation::Leak(offset, cells)),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitizer() {
+        let mut sanitizer = Sanitizer::new();
+        sanitizer.allocate(0, 2, 1);
+        sanitizer.access(1, Access::Read, 2);
+        sanitizer.arithmetic(0, 2, 3);
+        sanitizer.access(2, Access::Write, 4);
+        sanitizer.free(1, 5);
+        sanitizer.access(1, Access::Read, 6);
+        sanitizer.free(1, 7);
+        sanitizer.access(7, Access::Read, 8);
+        sanitizer.access(2, Access::Read, 9);
+        sanitizer.check_leaks([0, 2]);
+
+        let block = Block {
+            start: 0,
+            len: 2,
+            pc: 1,
+        };
+        assert_eq!(
+            sanitizer.violations(),
+            [
+                MemoryViolation::BlockEscape(4, Access::Write, 2, block),
+                MemoryViolation::UseAfterFree(6, Access::Read, 1, 5),
+                MemoryViolation::DoubleFree(7, 1, 5),
+                MemoryViolation::Unallocated(8, Access::Read, 7),
+                MemoryViolation::Leak(1, 1),
+                MemoryViolation::Leak(4, 1),
+            ]
+        );
+    }
+}
@@ -1,8 +1,12 @@
-use std::collections::HashMap;
+use std::rc::Rc;
 
+/// A frame: the address bound to each variable slot, see
+/// `codegen::bytecode::slots`.
 #[derive(Debug, Clone)]
 pub struct Scope {
-    variable_addresses: HashMap<String, i64>,
+    /// Variable names indexed by slot, shared by every scope of a VM.
+    names: Rc<[String]>,
+    addresses: Vec<Option<i64>>,
 }
 
 #[derive(Debug)]
@@ -11,21 +15,47 @@ pub enum ScopeError {
 }
 
 impl Scope {
-    pub fn new() -> Self {
+    pub fn new(names: Rc<[String]>) -> Self {
         Self {
-            variable_addresses: HashMap::new(),
+            addresses: vec![None; names.len()],
+            names,
         }
     }
 
+    pub fn get_slot(&self, slot: usize) -> Option<i64> {
+        self.addresses.get(slot).copied().flatten()
+    }
+
+    pub fn set_slot(&mut self, slot: usize, address: i64) {
+        // bytecode read from a file may use slots no name resolved to
+        if slot >= self.addresses.len() {
+            self.addresses.resize(slot + 1, None);
+        }
+        self.addresses[slot] = Some(address);
+    }
+
     pub fn get_var(&self, name: &str) -> Result<i64, ScopeError> {
-        self.variable_addresses
-            .get(name)
-            .cloned()
+        self.names
+            .iter()
+            .position(|n| n == name)
+            .and_then(|slot| self.get_slot(slot))
             .ok_or(ScopeError::VariableNotFound(name.to_string()))
     }
 
-    pub fn set_var(&mut self, name: &str, address: i64) -> Result<(), ScopeError> {
-        self.variable_addresses.insert(name.to_string(), address);
-        Ok(())
+    /// Names bound in the scope with their addresses, sorted by name.
+    pub fn variables(&self) -> Vec<(&str, i64)> {
+        let mut variables: Vec<(&str, i64)> = self
+            .names
+            .iter()
+            .zip(&self.addresses)
+            .filter_map(|(name, address)| Some((name.as_str(), (*address)?)))
+            .collect();
+        variables.sort();
+        variables
+    }
+
+    /// Addresses bound in the scope, in slot order.
+    pub fn addresses(&self) -> impl Iterator<Item = i64> + '_ {
+        self.addresses.iter().flatten().copied()
     }
 }
@@ -0,0 +1,310 @@
+use codegen::bytecode::Bytecode;
+
+/// A problem found in bytecode before it is executed, with the offset of
+/// the offending instruction.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+pub enum VerifyError {
+    JumpOutOfRange(usize, usize),
+    CallOutOfRange(usize, usize),
+    StackUnderflow(usize, usize, usize), // offset, values needed, values available
+    StackImbalance(usize, usize, usize), // offset, smaller and larger depth reaching it
+    UndefinedBuiltin(usize, String),
+    ReturnOutsideSubProgram(usize),
+}
+
+impl VerifyError {
+    pub fn offset(&self) -> usize {
+        match self {
+            VerifyError::JumpOutOfRange(offset, _)
+            | VerifyError::CallOutOfRange(offset, _)
+            | VerifyError::StackUnderflow(offset, _, _)
+            | VerifyError::StackImbalance(offset, _, _)
+            | VerifyError::UndefinedBuiltin(offset, _)
+            | VerifyError::ReturnOutsideSubProgram(offset) => *offset,
+        }
+    }
+}
+
+impl std::fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "instruction {}: ", self.offset())?;
+        match self {
+            VerifyError::JumpOutOfRange(_, target) => {
+                write!(f, "jump target {} is out of range", target)
+            }
+            VerifyError::CallOutOfRange(_, target) => {
+                write!(f, "subprogram address {} is out of range", target)
+            }
+            VerifyError::StackUnderflow(_, needed, available) => write!(
+                f,
+                "needs {} value(s) on the stack, but only {} may be there",
+                needed, available
+            ),
+            VerifyError::StackImbalance(_, smaller, larger) => write!(
+                f,
+                "reached with {} value(s) on the stack on one path and {} on another",
+                smaller, larger
+            ),
+            VerifyError::UndefinedBuiltin(_, name) => write!(f, "function '{}' not found", name),
+            VerifyError::ReturnOutsideSubProgram(_) => {
+                write!(f, "return is reachable outside of a subprogram")
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+/// Values an instruction pops and pushes.
+fn stack_effect(instruction: &Bytecode) -> (usize, usize) {
+    match instruction {
+        Bytecode::Constant(_) | Bytecode::LoadVar(_) | Bytecode::LoadSlot(_) | Bytecode::Alloc => {
+            (0, 1)
+        }
+        Bytecode::AllocMany(count) => (0, *count),
+        Bytecode::Add
+        | Bytecode::Sub
+        | Bytecode::Mul
+        | Bytecode::Div
+        | Bytecode::Mod
+        | Bytecode::And
+        | Bytecode::Or
+        | Bytecode::Equal
+        | Bytecode::NotEqual
+        | Bytecode::Greater
+        | Bytecode::Less
+        | Bytecode::GreaterEqual
+        | Bytecode::LessEqual
+        | Bytecode::MulDeref => (2, 1),
+        Bytecode::Not | Bytecode::Negate | Bytecode::Deref | Bytecode::StoreAddr => (1, 1),
+        Bytecode::Pop
+        | Bytecode::JumpIfFalse(_)
+        | Bytecode::BindAddr(_)
+        | Bytecode::BindSlot(_)
+        | Bytecode::FreeAddr => (1, 0),
+        Bytecode::Store | Bytecode::Exchange => (2, 0),
+        Bytecode::Swap => (2, 2),
+        // `dup` leaves the value and two copies of it
+        Bytecode::Dup => (1, 3),
+        Bytecode::CallBuiltin(_, argc) => (*argc, 1),
+        Bytecode::StoreVar(_)
+        | Bytecode::StoreSlot(_)
+        | Bytecode::Label(_)
+        | Bytecode::Jump(_)
+        | Bytecode::CallSubProgram(_, _)
+        | Bytecode::Return
+        | Bytecode::Halt
+        | Bytecode::PushScope
+        | Bytecode::PopScope => (0, 0),
+    }
+}
+
+/// Checks `bytecode` without running it and reports every problem found.
+///
+/// The stack depth is tracked along every control-flow path: an instruction
+/// is reported if some path reaches it with fewer values than it pops, or
+/// if two paths reach it with different depths. A subprogram body is
+/// checked as a frame of its own, entered with an empty stack, that must
+/// not pop values it did not push.
+pub fn verify(
+    bytecode: &[Bytecode],
+    is_builtin: impl Fn(&str) -> bool,
+) -> Result<(), Vec<VerifyError>> {
+    let mut errors = Vec::new();
+    let len = bytecode.len();
+
+    for (offset, instruction) in bytecode.iter().enumerate() {
+        match instruction {
+            Bytecode::Jump(target) | Bytecode::JumpIfFalse(target) if *target > len => {
+                errors.push(VerifyError::JumpOutOfRange(offset, *target));
+            }
+            Bytecode::CallSubProgram(target, _) if *target >= len => {
+                errors.push(VerifyError::CallOutOfRange(offset, *target));
+            }
+            Bytecode::CallBuiltin(name, _) if !is_builtin(name) => {
+                errors.push(VerifyError::UndefinedBuiltin(offset, name.clone()));
+            }
+            _ => {}
+        }
+    }
+
+    // smallest stack depth (relative to the enclosing frame) seen at each
+    // instruction, and whether it is reachable without entering a subprogram
+    let mut depths: Vec<Option<usize>> = vec![None; len];
+    let mut top_level = vec![false; len];
+    // paths still to follow; an unbalanced path is explored for underflows
+    // without reporting the imbalance again wherever it joins
+    let mut pending = vec![(0, 0, true, true)];
+    while let Some((offset, depth, is_top_level, mut balanced)) = pending.pop() {
+        if offset >= len {
+            continue;
+        }
+        if let Some(seen) = depths[offset] {
+            if seen != depth && balanced {
+                balanced = false;
+                errors.push(VerifyError::StackImbalance(
+                    offset,
+                    seen.min(depth),
+                    seen.max(depth),
+                ));
+            }
+            // a smaller depth may still underflow further on
+            if seen <= depth && (top_level[offset] || !is_top_level) {
+                continue;
+            }
+        }
+        let depth = depths[offset].map_or(depth, |seen| seen.min(depth));
+        depths[offset] = Some(depth);
+        top_level[offset] |= is_top_level;
+
+        let instruction = &bytecode[offset];
+        let (pops, pushes) = stack_effect(instruction);
+        if depth < pops {
+            errors.push(VerifyError::StackUnderflow(offset, pops, depth));
+        }
+        let next_depth = depth.saturating_sub(pops) + pushes;
+
+        match instruction {
+            Bytecode::Halt => {}
+            Bytecode::Return => {
+                if is_top_level {
+                    errors.push(VerifyError::ReturnOutsideSubProgram(offset));
+                }
+            }
+            Bytecode::Jump(target) => pending.push((*target, next_depth, is_top_level, balanced)),
+            Bytecode::JumpIfFalse(target) => {
+                pending.push((*target, next_depth, is_top_level, balanced));
+                pending.push((offset + 1, next_depth, is_top_level, balanced));
+            }
+            Bytecode::CallSubProgram(target, _) => {
+                pending.push((*target, 0, false, true));
+                // the VM resumes one instruction after the one following the call
+                pending.push((offset + 2, next_depth, is_top_level, balanced));
+            }
+            _ => pending.push((offset + 1, next_depth, is_top_level, balanced)),
+        }
+    }
+
+    if errors.is_empty() {
+        return Ok(());
+    }
+    errors.sort_by(|a, b| a.offset().cmp(&b.offset()).then(a.cmp(b)));
+    errors.dedup();
+    Err(errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use value::Value;
+
+    fn verify_with_print(bytecode: &[Bytecode]) -> Result<(), Vec<VerifyError>> {
+        verify(bytecode, |name| name == "Print")
+    }
+
+    #[test]
+    fn test_valid_program() {
+        // L {1, 1, 'i < 3 => i} ... Print {'i}, then a call of a subprogram
+        let bytecode = vec![
+            Bytecode::Constant(Value::new_int(0)),
+            Bytecode::BindAddr("i".to_string()),
+            Bytecode::LoadVar("i".to_string()),
+            Bytecode::Deref,
+            Bytecode::Constant(Value::new_int(3)),
+            Bytecode::Less,
+            Bytecode::JumpIfFalse(11),
+            Bytecode::LoadVar("i".to_string()),
+            Bytecode::CallBuiltin("Print".to_string(), 1),
+            Bytecode::Pop,
+            Bytecode::Jump(2),
+            Bytecode::PushScope,
+            Bytecode::CallSubProgram(16, 0),
+            Bytecode::PopScope,
+            Bytecode::Label("after_call".to_string()),
+            Bytecode::Halt,
+            Bytecode::Alloc,
+            Bytecode::Pop,
+            Bytecode::Return,
+        ];
+        assert_eq!(verify_with_print(&bytecode), Ok(()));
+    }
+
+    #[test]
+    fn test_targets_and_builtins() {
+        let bytecode = vec![
+            Bytecode::Jump(3),
+            Bytecode::JumpIfFalse(7),
+            Bytecode::CallSubProgram(4, 0),
+            Bytecode::CallBuiltin("Missing".to_string(), 0),
+        ];
+        assert_eq!(
+            verify_with_print(&bytecode),
+            Err(vec![
+                VerifyError::JumpOutOfRange(1, 7),
+                VerifyError::CallOutOfRange(2, 4),
+                VerifyError::UndefinedBuiltin(3, "Missing".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_stack_underflow_on_some_path() {
+        let bytecode = vec![
+            Bytecode::Constant(Value::new_bool(true)),
+            Bytecode::JumpIfFalse(3),
+            Bytecode::Constant(Value::new_int(1)),
+            // only one value is there when the jump is taken
+            Bytecode::Constant(Value::new_int(2)),
+            Bytecode::Add,
+            Bytecode::Store,
+        ];
+        assert_eq!(
+            verify_with_print(&bytecode),
+            Err(vec![
+                VerifyError::StackImbalance(3, 0, 1),
+                VerifyError::StackUnderflow(4, 2, 1),
+                VerifyError::StackUnderflow(5, 2, 1),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_stack_imbalance() {
+        // P {true} 1 | @end: the true branch leaves its value behind
+        let bytecode = vec![
+            Bytecode::Constant(Value::new_bool(true)),
+            Bytecode::JumpIfFalse(4),
+            Bytecode::Constant(Value::new_int(1)),
+            Bytecode::Jump(4),
+            Bytecode::Halt,
+        ];
+        assert_eq!(
+            verify_with_print(&bytecode),
+            Err(vec![VerifyError::StackImbalance(4, 0, 1)])
+        );
+    }
+
+    #[test]
+    fn test_subprogram_frames() {
+        let bytecode = vec![
+            Bytecode::Constant(Value::new_int(1)),
+            Bytecode::CallSubProgram(4, 0),
+            Bytecode::PopScope,
+            Bytecode::Return,
+            // the caller's value is not part of the subprogram's frame
+            Bytecode::Pop,
+            Bytecode::Return,
+        ];
+        assert_eq!(
+            verify_with_print(&bytecode),
+            Err(vec![
+                VerifyError::ReturnOutsideSubProgram(3),
+                VerifyError::StackUnderflow(4, 1, 0),
+            ])
+        );
+    }
+}
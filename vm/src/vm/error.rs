@@ -1,6 +1,7 @@
+use common::{location::Location, util::call_chain};
 use value::error::ValueError;
 
-use crate::{heap::HeapError, scope::ScopeError};
+use crate::{heap::HeapError, register::LowerError, scope::ScopeError, verifier::VerifyError};
 
 #[derive(Debug)]
 pub enum VMError {
@@ -9,14 +10,24 @@ pub enum VMError {
     InvalidOperation,
     UndefinedFunction(String),
     UnexpectedType(ValueError),
+    DivisionByZero,
+    Overflow(String),           // operation
+    StackOverflow(Vec<String>), // subprograms called, outermost first
     HeapEror(HeapError),
     ScopeError(ScopeError),
+    InvalidBytecode(Vec<VerifyError>),
+    Lower(LowerError),
+    InvalidConfig(String),
     Custom(String),
 }
 
 impl From<ValueError> for VMError {
     fn from(err: ValueError) -> Self {
-        VMError::UnexpectedType(err)
+        match err {
+            ValueError::DivisionByZero => VMError::DivisionByZero,
+            ValueError::Overflow { operation } => VMError::Overflow(operation),
+            err => VMError::UnexpectedType(err),
+        }
     }
 }
 
@@ -26,8 +37,77 @@ impl From<HeapError> for VMError {
     }
 }
 
+impl From<LowerError> for VMError {
+    fn from(err: LowerError) -> Self {
+        VMError::Lower(err)
+    }
+}
+
 impl From<ScopeError> for VMError {
     fn from(err: ScopeError) -> Self {
         VMError::ScopeError(err)
     }
 }
+
+impl std::fmt::Display for VMError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VMError::StackUnderflow => write!(f, "Stack underflow"),
+            VMError::InvalidAddress => write!(f, "Invalid address"),
+            VMError::InvalidOperation => write!(f, "Invalid operation"),
+            VMError::UndefinedFunction(name) => write!(f, "Function '{}' not found", name),
+            VMError::UnexpectedType(error) => write!(f, "Type error: {}", error),
+            VMError::DivisionByZero => write!(f, "Division by zero error"),
+            VMError::Overflow(operation) => write!(f, "Integer overflow error in '{}'", operation),
+            VMError::StackOverflow(calls) => write!(
+                f,
+                "Stack overflow: more than {} nested subprogram calls ({})",
+                calls.len() - 1,
+                call_chain(calls)
+            ),
+            VMError::HeapEror(HeapError::OutOfMemory) => write!(f, "Out of memory"),
+            VMError::HeapEror(HeapError::InvalidAddress(address)) => {
+                write!(f, "Invalid address: {}", address)
+            }
+            VMError::HeapEror(HeapError::PartitionLimitExceeded(usage)) => write!(
+                f,
+                "Heap partition limit exceeded: {} more cell(s) requested, but the {} \
+                 partition already uses {} of its {} cells (heap size {})",
+                usage.requested, usage.partition, usage.allocated, usage.limit, usage.heap_size
+            ),
+            VMError::ScopeError(ScopeError::VariableNotFound(name)) => {
+                write!(f, "Variable '{}' not found", name)
+            }
+            VMError::InvalidBytecode(errors) => {
+                write!(f, "Invalid bytecode:")?;
+                for error in errors {
+                    write!(f, "\n  {}", error)?;
+                }
+                Ok(())
+            }
+            VMError::Lower(error) => {
+                write!(f, "Cannot run on the register backend: {}", error)
+            }
+            VMError::InvalidConfig(message) => write!(f, "Invalid configuration: {}", message),
+            VMError::Custom(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+/// A `VMError` together with the instruction that raised it and, when the
+/// bytecode carries a line table, the span of the originating statement.
+#[derive(Debug)]
+pub struct LocatedVMError {
+    pub error: VMError,
+    pub pc: usize,
+    pub span: Option<(Location, Location)>,
+}
+
+impl std::fmt::Display for LocatedVMError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.span {
+            Some((start, _)) => write!(f, "{} (instruction {}, at {})", self.error, self.pc, start),
+            None => write!(f, "{} (instruction {})", self.error, self.pc),
+        }
+    }
+}
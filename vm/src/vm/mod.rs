@@ -1,33 +1,83 @@
 pub mod error;
+mod register;
 
-use codegen::bytecode::Bytecode;
+use codegen::bytecode::{slots::resolve_slots, Bytecode};
+use common::snapshot::{Binding, HeapSnapshot, SnapshotCell};
 use error::VMError;
 use log::trace;
 use std::collections::HashMap;
+use std::io::{self, Write};
+use std::rc::Rc;
 use value::{error::ValueError, Value};
 
-use crate::{builtins::BuiltinFunction, heap::Heap, scope::Scope};
+use crate::{
+    builtins::BuiltinFunction,
+    config::{Backend, VmConfig},
+    heap::{Heap, HeapError, Partition},
+    register::lower,
+    sanitizer::{Access, MemoryViolation, Sanitizer},
+    scope::{Scope, ScopeError},
+    verifier::{verify, VerifyError},
+};
 
 pub struct VM {
-    bytecode: Vec<Bytecode>,
+    /// Shared with `step`, which borrows the instruction it executes.
+    bytecode: Rc<[Bytecode]>,
+    /// Variable names indexed by slot.
+    names: Rc<[String]>,
     pc: usize,
+    current_pc: usize,
     stack: Vec<Value>,
+    /// Values of the register backend, see `crate::register`.
+    registers: Vec<Value>,
+    backend: Backend,
     scopes: Vec<Scope>,
     heap: Heap,
     builtins: HashMap<String, BuiltinFunction>,
     call_stack: Vec<usize>,
+    /// Entry of the subprogram each return address of `call_stack`
+    /// belongs to, for the chain reported on a stack overflow.
+    call_entries: Vec<usize>,
+    max_call_depth: usize,
+    halted: bool,
+    output: Box<dyn Write>,
+    gc_enabled: bool,
+    /// Heads of the lists built by `allocate_list`, for heap snapshots.
+    list_heads: Vec<i64>,
+    sanitizer: Option<Sanitizer>,
 }
 
 impl VM {
     pub fn new(bytecode: Vec<Bytecode>) -> Self {
+        Self::with_config(bytecode, &VmConfig::default())
+    }
+
+    /// Creates a VM with the heap and collector set up by `config`, which
+    /// is expected to be valid (see `VmConfig::validate`). Variables are
+    /// resolved to slots, see `resolve_slots`.
+    pub fn with_config(mut bytecode: Vec<Bytecode>, config: &VmConfig) -> Self {
+        let names: Rc<[String]> = resolve_slots(&mut bytecode).into();
+        let mut heap = Heap::new(config.heap_size, config.reserved_ratio);
+        heap.set_growth(config.growth);
         Self {
-            bytecode,
+            bytecode: bytecode.into(),
+            scopes: vec![Scope::new(Rc::clone(&names))],
+            names,
             pc: 0,
+            current_pc: 0,
             stack: Vec::new(),
-            scopes: vec![Scope::new()],
-            heap: Heap::new(4000, 0.25),
+            registers: Vec::new(),
+            backend: config.backend,
+            heap,
             builtins: HashMap::new(),
             call_stack: Vec::new(),
+            call_entries: Vec::new(),
+            max_call_depth: config.max_call_depth,
+            halted: false,
+            output: Box::new(io::stdout()),
+            gc_enabled: config.gc,
+            list_heads: Vec::new(),
+            sanitizer: config.sanitize.then(Sanitizer::new),
         }
     }
 
@@ -35,61 +85,265 @@ impl VM {
         self.builtins.insert(name.to_string(), func);
     }
 
-    pub fn run(&mut self) -> Result<(), VMError> {
-        while self.pc < self.bytecode.len() {
-            let instruction = self.bytecode[self.pc].clone();
-            self.pc += 1;
-
-            trace!("--- PC: {:?} ---", self.pc);
-            trace!("Instruction: {:?}", instruction);
-
-            match instruction {
-                Bytecode::Constant(value) => self.stack.push(value),
-                Bytecode::LoadVar(name) => self.get_var(&name)?,
-                Bytecode::StoreVar(name) => self.set_var(&name)?,
-                Bytecode::Add => self.binary_op(Value::sum)?,
-                Bytecode::Sub => self.binary_op(Value::sub)?,
-                Bytecode::Mul => self.binary_op(Value::mul)?,
-                Bytecode::Div => self.binary_op(Value::div)?,
-                Bytecode::Mod => self.binary_op(Value::modulus)?,
-                Bytecode::And => self.binary_op(Value::and)?,
-                Bytecode::Or => self.binary_op(Value::or)?,
-                Bytecode::Equal => self.binary_op(Value::eq)?,
-                Bytecode::NotEqual => self.binary_op(Value::ne)?,
-                Bytecode::Greater => self.binary_op(Value::gt)?,
-                Bytecode::Less => self.binary_op(Value::lt)?,
-                Bytecode::Not => self.unary_op(Value::not)?,
-                Bytecode::Negate => self.unary_op(Value::negate)?,
-                Bytecode::Jump(addr) => self.pc = addr,
-                Bytecode::JumpIfFalse(addr) => self.jump_if_false(addr)?,
-                Bytecode::Label(_) => {}
-                Bytecode::CallBuiltin(name, argc) => self.call_builtin(&name, argc)?,
-                Bytecode::CallSubProgram(label, argc) => {
-                    self.call_subprogram(label, argc);
+    /// Redirects what the program prints, stdout by default.
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.output = Box::new(output);
+    }
+
+    pub fn output(&mut self) -> &mut dyn Write {
+        &mut self.output
+    }
+
+    /// Lets allocations that find the general partition full collect
+    /// garbage and retry instead of failing.
+    pub fn set_gc_enabled(&mut self, enabled: bool) {
+        self.gc_enabled = enabled;
+    }
+
+    /// Selects the interpreter loop `run` executes the program with.
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend;
+    }
+
+    /// Limits the subprogram calls that may be active at once, see
+    /// `VmConfig::max_call_depth`.
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+
+    /// Checks every heap access against the blocks the program allocated
+    /// and freed, see `Sanitizer`.
+    pub fn set_sanitizer_enabled(&mut self, enabled: bool) {
+        self.sanitizer = enabled.then(Sanitizer::new);
+    }
+
+    /// What the sanitizer found so far, nothing when it is disabled.
+    pub fn memory_violations(&self) -> &[MemoryViolation] {
+        self.sanitizer
+            .as_ref()
+            .map_or(&[], |sanitizer| sanitizer.violations())
+    }
+
+    /// Runs `check` on the sanitizer, if enabled, with the offset of the
+    /// current instruction.
+    fn sanitize(&mut self, check: impl FnOnce(&mut Sanitizer, usize)) {
+        if let Some(sanitizer) = &mut self.sanitizer {
+            check(sanitizer, self.current_pc);
+        }
+    }
+
+    /// Frees the general cells unreachable from the variables of every
+    /// scope and the addresses on the operand stack or in registers, see
+    /// `Heap::collect_garbage`. Returns how many cells were freed.
+    pub fn collect_garbage(&mut self) -> usize {
+        let variables = self.scopes.iter().flat_map(Scope::addresses);
+        let stack = self
+            .stack
+            .iter()
+            .chain(&self.registers)
+            .filter_map(|value| match value {
+                // integers may be addresses, see `Heap::collect_garbage`
+                Value::Address(address) | Value::Int(address) => Some(*address),
+                _ => None,
+            });
+        let roots: Vec<i64> = variables.chain(stack).collect();
+        let freed = self.heap.collect_garbage(roots);
+        trace!("Garbage collection freed {} cells", freed);
+        freed
+    }
+
+    /// Runs `allocate` on the heap. When a partition is full, garbage is
+    /// collected if the collector is enabled and the heap grown if its
+    /// growth policy allows it, retrying `allocate` after each step.
+    fn allocate<T>(
+        &mut self,
+        allocate: impl Fn(&mut Heap) -> Result<T, HeapError>,
+    ) -> Result<T, VMError> {
+        let mut collected = !self.gc_enabled;
+        loop {
+            match allocate(&mut self.heap) {
+                Err(HeapError::PartitionLimitExceeded(usage)) => {
+                    // garbage is only collected in the general partition
+                    if !collected && usage.partition == Partition::General {
+                        collected = true;
+                        self.collect_garbage();
+                    } else if !self.heap.grow() {
+                        return Err(HeapError::PartitionLimitExceeded(usage).into());
+                    }
                 }
-                Bytecode::Return => self.handle_return()?,
-                Bytecode::Halt => break,
-                Bytecode::Pop => {
-                    self.stack.pop().ok_or(VMError::StackUnderflow)?;
+                result => return Ok(result?),
+            }
+        }
+    }
+
+    /// Checks the loaded bytecode against the registered builtins.
+    pub fn verify(&self) -> Result<(), Vec<VerifyError>> {
+        verify(&self.bytecode, |name| self.builtins.contains_key(name))
+    }
+
+    /// Index of the instruction executed last, the faulting one after `run`
+    /// returned an error.
+    pub fn current_pc(&self) -> usize {
+        self.current_pc
+    }
+
+    /// Index of the instruction `step` executes next.
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn bytecode(&self) -> &[Bytecode] {
+        &self.bytecode
+    }
+
+    /// Variable names indexed by the slot `LoadSlot` and friends refer to.
+    pub fn variable_names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn stack(&self) -> &[Value] {
+        &self.stack
+    }
+
+    /// Scopes from the outermost to the current one.
+    pub fn scopes(&self) -> &[Scope] {
+        &self.scopes
+    }
+
+    /// Return addresses of the active subprogram calls, innermost last.
+    pub fn call_stack(&self) -> &[usize] {
+        &self.call_stack
+    }
+
+    pub fn heap(&self) -> &Heap {
+        &self.heap
+    }
+
+    /// The heap cells, the variables of every scope bound to them and the
+    /// lists the VM built.
+    pub fn heap_snapshot(&self) -> HeapSnapshot {
+        let cells = self
+            .heap
+            .cells()
+            .map(|(address, value)| SnapshotCell {
+                address,
+                value: value.to_string(),
+                pointer: match value {
+                    Value::Address(pointer) => Some(*pointer),
+                    _ => None,
+                },
+                reserved: self.heap.is_reserved(address),
+            })
+            .collect();
+        let bindings = self
+            .scopes
+            .iter()
+            .enumerate()
+            .flat_map(|(depth, scope)| {
+                scope
+                    .variables()
+                    .into_iter()
+                    .map(move |(name, address)| Binding {
+                        name: name.to_string(),
+                        scope: depth,
+                        address,
+                    })
+            })
+            .collect();
+        HeapSnapshot::new(cells, bindings, &self.list_heads)
+    }
+
+    /// Runs the program to the end with the selected backend. The
+    /// register backend lowers the bytecode first, see `register::lower`.
+    pub fn run(&mut self) -> Result<(), VMError> {
+        match self.backend {
+            Backend::Stack => {
+                while !self.is_finished() {
+                    self.step()?;
                 }
-                Bytecode::Deref => self.deref()?,
-                Bytecode::MulDeref => self.mul_deref()?,
-                Bytecode::Store => self.store()?,
-                Bytecode::Alloc => self.alloc(false)?,
-                Bytecode::AllocMany(count) => self.alloc_many(count, false)?,
-                Bytecode::Dup => self.dup()?,
-                Bytecode::StoreAddr => self.store_addr(false)?,
-                Bytecode::BindAddr(name) => self.bind_addr(name)?,
-                Bytecode::PushScope => self.push_scope(),
-                Bytecode::PopScope => self.pop_scope()?,
-                Bytecode::FreeAddr => self.free_addr()?,
-                Bytecode::Swap => self.swap()?,
             }
+            Backend::Register => {
+                let program = lower(&self.bytecode)?;
+                self.run_registers(&program)?;
+            }
+        }
+        if self.sanitizer.is_some() {
+            let variables: Vec<i64> = self.scopes.iter().flat_map(Scope::addresses).collect();
+            let unreachable = self.heap.unreachable(variables);
+            self.sanitize(|sanitizer, _| sanitizer.check_leaks(unreachable));
+        }
+        Ok(())
+    }
 
-            trace!("Stack: {:?}", self.stack);
-            trace!("Values by address: {:?}", self.heap);
-            trace!("Current scope: {:?}", self.current_scope());
+    /// Tells whether the program halted or ran past its last instruction.
+    pub fn is_finished(&self) -> bool {
+        self.halted || self.pc >= self.bytecode.len()
+    }
+
+    /// Executes the instruction at `pc`.
+    pub fn step(&mut self) -> Result<(), VMError> {
+        self.current_pc = self.pc;
+        let bytecode = Rc::clone(&self.bytecode);
+        let instruction = &bytecode[self.pc];
+        self.pc += 1;
+
+        trace!("--- PC: {:?} ---", self.pc);
+        trace!("Instruction: {:?}", instruction);
+
+        match instruction {
+            Bytecode::Constant(value) => self.stack.push(value.clone()),
+            Bytecode::LoadSlot(slot) => self.get_var(*slot)?,
+            Bytecode::StoreSlot(slot) => self.set_var(*slot)?,
+            Bytecode::BindSlot(slot) => self.bind_addr(*slot)?,
+            // resolved when the VM is created, kept for completeness
+            Bytecode::LoadVar(name) => self.get_var(self.slot_of(name)?)?,
+            Bytecode::StoreVar(name) => self.set_var(self.slot_of(name)?)?,
+            Bytecode::BindAddr(name) => self.bind_addr(self.slot_of(name)?)?,
+            Bytecode::Add => self.address_arithmetic(Value::sum)?,
+            Bytecode::Sub => self.address_arithmetic(Value::sub)?,
+            Bytecode::Mul => self.binary_op(Value::mul)?,
+            Bytecode::Div => self.binary_op(Value::div)?,
+            Bytecode::Mod => self.binary_op(Value::modulus)?,
+            Bytecode::And => self.binary_op(Value::and)?,
+            Bytecode::Or => self.binary_op(Value::or)?,
+            Bytecode::Equal => self.binary_op(Value::eq)?,
+            Bytecode::NotEqual => self.binary_op(Value::ne)?,
+            Bytecode::Greater => self.binary_op(Value::gt)?,
+            Bytecode::Less => self.binary_op(Value::lt)?,
+            Bytecode::GreaterEqual => self.binary_op(Value::ge)?,
+            Bytecode::LessEqual => self.binary_op(Value::le)?,
+            Bytecode::Not => self.unary_op(Value::not)?,
+            Bytecode::Negate => self.unary_op(Value::negate)?,
+            Bytecode::Jump(addr) => self.pc = *addr,
+            Bytecode::JumpIfFalse(addr) => self.jump_if_false(*addr)?,
+            Bytecode::Label(_) => {}
+            Bytecode::CallBuiltin(name, argc) => self.call_builtin(name, *argc)?,
+            Bytecode::CallSubProgram(label, _) => {
+                self.enter_subprogram(*label, self.pc + 1)?;
+                self.pc = *label;
+            }
+            Bytecode::Return => self.handle_return()?,
+            Bytecode::Halt => self.halted = true,
+            Bytecode::Pop => {
+                self.stack.pop().ok_or(VMError::StackUnderflow)?;
+            }
+            Bytecode::Deref => self.deref()?,
+            Bytecode::MulDeref => self.mul_deref()?,
+            Bytecode::Store => self.store()?,
+            Bytecode::Alloc => self.alloc(false)?,
+            Bytecode::AllocMany(count) => self.alloc_many(*count, false)?,
+            Bytecode::Dup => self.dup()?,
+            Bytecode::StoreAddr => self.store_addr(false)?,
+            Bytecode::PushScope => self.push_scope(),
+            Bytecode::PopScope => self.pop_scope()?,
+            Bytecode::FreeAddr => self.free_addr()?,
+            Bytecode::Swap => self.swap()?,
+            Bytecode::Exchange => self.exchange()?,
         }
+
+        trace!("Stack: {:?}", self.stack);
+        trace!("Values by address: {:?}", self.heap);
+        trace!("Current scope: {:?}", self.current_scope());
         Ok(())
     }
 
@@ -102,12 +356,39 @@ impl VM {
         Ok(())
     }
 
+    fn exchange(&mut self) -> Result<(), VMError> {
+        let rhs = self.stack.pop().ok_or(VMError::StackUnderflow)?;
+        let lhs = self.stack.pop().ok_or(VMError::StackUnderflow)?;
+        self.exchange_cells(&lhs, &rhs)
+    }
+
+    fn exchange_cells(&mut self, lhs: &Value, rhs: &Value) -> Result<(), VMError> {
+        let (lhs, rhs) = (lhs.extract_address()?, rhs.extract_address()?);
+        self.sanitize(|sanitizer, pc| {
+            sanitizer.access(lhs, Access::Write, pc);
+            sanitizer.access(rhs, Access::Write, pc);
+        });
+        // unset cells hold null, as in `deref`
+        let lhs_value = self.heap.lookup_address(lhs).unwrap_or(Value::Null);
+        let rhs_value = self.heap.lookup_address(rhs).unwrap_or(Value::Null);
+        self.heap.store(lhs, rhs_value)?;
+        self.heap.store(rhs, lhs_value)?;
+        Ok(())
+    }
+
+    fn slot_of(&self, name: &str) -> Result<usize, VMError> {
+        self.names
+            .iter()
+            .position(|n| n == name)
+            .ok_or_else(|| ScopeError::VariableNotFound(name.to_string()).into())
+    }
+
     fn current_scope(&mut self) -> &mut Scope {
         self.scopes.last_mut().expect("No scope available")
     }
 
     fn push_scope(&mut self) {
-        self.scopes.push(Scope::new());
+        self.scopes.push(Scope::new(Rc::clone(&self.names)));
     }
 
     fn pop_scope(&mut self) -> Result<(), VMError> {
@@ -119,84 +400,116 @@ impl VM {
 
     fn store(&mut self) -> Result<(), VMError> {
         // println!("store");
-        let addr = self.stack.pop().ok_or(VMError::StackUnderflow)?;
-        match addr {
-            Value::Int(address) => {
-                let value = self.stack.pop().ok_or(VMError::StackUnderflow)?;
-                self.heap.store(address, value)?;
-                Ok(())
-            }
-            _ => Err(VMError::InvalidAddress),
-        }
+        let address = self.stack.pop().ok_or(VMError::StackUnderflow)?;
+        let value = self.stack.pop().ok_or(VMError::StackUnderflow)?;
+        self.store_value(value, &address)
+    }
+
+    fn store_value(&mut self, value: Value, address: &Value) -> Result<(), VMError> {
+        let address = address.extract_address()?;
+        self.sanitize(|sanitizer, pc| sanitizer.access(address, Access::Write, pc));
+        self.heap.store(address, value)?;
+        Ok(())
     }
 
     fn store_addr(&mut self, reserved: bool) -> Result<(), VMError> {
+        if self.stack.is_empty() {
+            return Err(VMError::StackUnderflow);
+        }
+        // allocated while the value is still on the stack, where a
+        // collection sees it
+        let address = self.allocate_cell(reserved)?;
         let value = self.stack.pop().ok_or(VMError::StackUnderflow)?;
-        self.stack
-            .push(Value::new_int(self.heap.store_value(value, reserved)?));
+        self.heap.store(address, value)?;
+        self.stack.push(Value::new_address(address));
         Ok(())
     }
 
-    fn bind_addr(&mut self, name: String) -> Result<(), VMError> {
-        // println!("bind_addr");
+    fn bind_addr(&mut self, slot: usize) -> Result<(), VMError> {
         let address = self.stack.pop().ok_or(VMError::StackUnderflow)?;
-        if let Value::Int(addr) = address {
-            self.current_scope().set_var(&name, addr)?;
-            Ok(())
-        } else {
-            Err(VMError::InvalidAddress)
-        }
+        self.bind_slot(slot, &address)
     }
 
-    fn get_var(&mut self, name: &str) -> Result<(), VMError> {
-        if let Ok(address) = self.current_scope().get_var(name) {
-            self.stack.push(Value::Int(address));
-        } else {
-            let address = self.heap.allocate_address(false)?;
-            self.current_scope().set_var(name, address)?;
-            self.stack.push(Value::Int(address));
-        }
+    fn bind_slot(&mut self, slot: usize, address: &Value) -> Result<(), VMError> {
+        let address = address.extract_address()?;
+        self.current_scope().set_slot(slot, address);
         Ok(())
     }
 
-    fn set_var(&mut self, name: &str) -> Result<(), VMError> {
-        let address = self.heap.allocate_address(false)?;
-        self.current_scope().set_var(name, address)?;
+    fn get_var(&mut self, slot: usize) -> Result<(), VMError> {
+        let address = self.load_slot(slot)?;
+        self.stack.push(Value::Address(address));
+        Ok(())
+    }
+
+    /// The address bound to `slot`, bound to a new cell if there is none.
+    fn load_slot(&mut self, slot: usize) -> Result<i64, VMError> {
+        match self.current_scope().get_slot(slot) {
+            Some(address) => Ok(address),
+            None => {
+                let address = self.allocate_cell(false)?;
+                self.current_scope().set_slot(slot, address);
+                Ok(address)
+            }
+        }
+    }
+
+    fn set_var(&mut self, slot: usize) -> Result<(), VMError> {
+        let address = self.allocate_cell(false)?;
+        self.current_scope().set_slot(slot, address);
         Ok(())
     }
 
     fn alloc(&mut self, reserved: bool) -> Result<(), VMError> {
-        let new_address = self.heap.allocate_address(reserved)?;
-        self.stack.push(Value::new_int(new_address));
+        let new_address = self.allocate_cell(reserved)?;
+        self.stack.push(Value::new_address(new_address));
         Ok(())
     }
 
+    fn allocate_cell(&mut self, reserved: bool) -> Result<i64, VMError> {
+        let address = self.allocate(|heap| heap.allocate_address(reserved))?;
+        self.sanitize(|sanitizer, pc| sanitizer.allocate(address, 1, pc));
+        Ok(address)
+    }
+
     fn alloc_many(&mut self, count: usize, reserved: bool) -> Result<(), VMError> {
-        let addresses = self.heap.allocate_consecutive_addresses(count, reserved)?;
+        let addresses = self.allocate_cells(count, reserved)?;
         for address in addresses.iter().copied() {
-            self.stack.push(Value::new_int(address));
+            self.stack.push(Value::new_address(address));
         }
         Ok(())
     }
 
+    fn allocate_cells(&mut self, count: usize, reserved: bool) -> Result<Vec<i64>, VMError> {
+        let addresses =
+            self.allocate(|heap| heap.allocate_consecutive_addresses(count, reserved))?;
+        if let Some(&start) = addresses.first() {
+            self.sanitize(|sanitizer, pc| sanitizer.allocate(start, count, pc));
+        }
+        Ok(addresses)
+    }
+
     fn deref(&mut self) -> Result<(), VMError> {
         // print!("deref");
-        let value = self.stack.pop().ok_or(VMError::StackUnderflow)?;
-        match value {
-            Value::Int(address) => {
-                if let Ok(stored_value) = self.heap.lookup_address(address) {
-                    self.stack.push(stored_value.clone());
-                } else {
-                    self.stack.push(Value::Null);
-                }
-                Ok(())
-            }
-            _ => Err(VMError::InvalidAddress),
-        }
+        let address = self.stack.pop().ok_or(VMError::StackUnderflow)?;
+        let value = self.load(&address)?;
+        self.stack.push(value);
+        Ok(())
+    }
+
+    /// The value stored at `address`, null for an unset cell.
+    fn load(&mut self, address: &Value) -> Result<Value, VMError> {
+        let address = address.extract_address()?;
+        self.sanitize(|sanitizer, pc| sanitizer.access(address, Access::Read, pc));
+        Ok(self.heap.lookup_address(address).unwrap_or(Value::Null))
     }
 
     fn allocate_list(&mut self, elements: Vec<Value>, reserved: bool) -> Result<i64, VMError> {
-        let mut addresses = self.heap.allocate_consecutive_addresses(2, reserved)?;
+        // a reserved allocation grows the heap but never collects garbage,
+        // which would free the cells of the list built so far
+        let mut addresses =
+            self.allocate(|heap| heap.allocate_consecutive_addresses(2, reserved))?;
+        self.sanitize(|sanitizer, pc| sanitizer.allocate(addresses[0], 2, pc));
         let head = addresses[0];
         let mut i = 0;
         for elem in elements.clone() {
@@ -207,45 +520,53 @@ impl VM {
             // println!("3");
 
             if i != elements.len() {
-                let next: Vec<i64> = self.heap.allocate_consecutive_addresses(2, reserved)?;
-                self.heap.store(addresses[0], Value::new_int(next[0]))?;
+                let next: Vec<i64> =
+                    self.allocate(|heap| heap.allocate_consecutive_addresses(2, reserved))?;
+                self.sanitize(|sanitizer, pc| sanitizer.allocate(next[0], 2, pc));
+                self.heap.store(addresses[0], Value::new_address(next[0]))?;
                 addresses = next;
             }
         }
+        self.list_heads.push(head);
         Ok(head)
     }
 
     fn mul_deref(&mut self) -> Result<(), VMError> {
-        let n: i64 = self
-            .stack
-            .pop()
-            .ok_or(VMError::StackUnderflow)?
-            .extract_int()?;
+        let n = self.stack.pop().ok_or(VMError::StackUnderflow)?;
         let address = self.stack.pop().ok_or(VMError::StackUnderflow)?;
+        let value = self.load_many(address, &n)?;
+        self.stack.push(value);
+        Ok(())
+    }
+
+    /// Dereferences `address` `n` times, or lists the cells pointing to it
+    /// `-n` levels up for a negative `n`.
+    fn load_many(&mut self, address: Value, n: &Value) -> Result<Value, VMError> {
+        let n = n.extract_int()?;
         if n == 0 {
-            self.stack.push(address.clone());
+            Ok(address)
         } else if n < 0 {
             // println!("minus dereference");
             let values = vec![address];
             let fathers = self.mul_minus_deref_vec(values, n)?;
             // println!("Fathers: {:?}", fathers);
             if fathers.is_empty() {
-                self.stack.push(Value::Null);
+                Ok(Value::Null)
             } else {
                 let head = self.allocate_list(fathers, true)?;
                 // print!("push_head: {:?}", head);
-                self.stack.push(Value::Int(head));
+                Ok(Value::Address(head))
             }
         } else {
-            let mut address_p = address.extract_int()?;
+            let mut address_p = address.extract_address()?;
             for _ in 1..n {
-                address_p = self.heap.lookup_address(address_p)?.extract_int()?;
+                self.sanitize(|sanitizer, pc| sanitizer.access(address_p, Access::Read, pc));
+                address_p = self.heap.lookup_address(address_p)?.extract_address()?;
             }
+            self.sanitize(|sanitizer, pc| sanitizer.access(address_p, Access::Read, pc));
 
-            self.stack
-                .push(self.heap.lookup_address(address_p)?.clone());
+            Ok(self.heap.lookup_address(address_p)?)
         }
-        Ok(())
     }
 
     fn mul_minus_deref_vec(&self, values: Vec<Value>, n: i64) -> Result<Vec<Value>, VMError> {
@@ -269,7 +590,7 @@ impl VM {
 
     fn jump_if_false(&mut self, addr: usize) -> Result<(), VMError> {
         let condition = self.stack.pop().ok_or(VMError::StackUnderflow)?;
-        if !self.is_truthy(condition)? {
+        if !self.is_truthy(&condition)? {
             self.pc = addr;
         }
         Ok(())
@@ -281,34 +602,86 @@ impl VM {
             args.push(self.stack.pop().ok_or(VMError::StackUnderflow)?);
         }
         args.reverse();
-        if let Some(func) = self.builtins.get(name) {
-            let result = func(self, args);
-            self.stack.push(result);
-            Ok(())
-        } else {
-            Err(VMError::UndefinedFunction(name.to_string()))
+        let result = self.invoke_builtin(name, args)?;
+        self.stack.push(result);
+        Ok(())
+    }
+
+    fn invoke_builtin(&mut self, name: &str, args: Vec<Value>) -> Result<Value, VMError> {
+        match self.builtins.get(name) {
+            Some(func) => Ok(func(self, args)),
+            None => Err(VMError::UndefinedFunction(name.to_string())),
         }
     }
 
-    fn call_subprogram(&mut self, label: usize, argc: usize) {
-        self.call_stack.push(self.pc + 1);
-        self.pc = label;
+    /// Records the call at `current_pc` to the subprogram entered at
+    /// `entry`, its scope already pushed. A call in tail position inside
+    /// another subprogram reuses the caller's frame: the caller's scope
+    /// is dropped and the callee returns where the caller would have.
+    fn enter_subprogram(&mut self, entry: usize, return_address: usize) -> Result<(), VMError> {
+        if !self.call_stack.is_empty() && in_tail_position(&self.bytecode, self.current_pc) {
+            let caller = self.scopes.len() - 2;
+            self.scopes.remove(caller);
+            *self
+                .call_entries
+                .last_mut()
+                .expect("a frame per return address") = entry;
+            return Ok(());
+        }
+        if self.call_stack.len() >= self.max_call_depth {
+            let calls = self
+                .call_entries
+                .iter()
+                .chain([&entry])
+                .map(|&entry| subprogram_name(&self.bytecode, entry))
+                .collect();
+            return Err(VMError::StackOverflow(calls));
+        }
+        self.call_stack.push(return_address);
+        self.call_entries.push(entry);
+        Ok(())
     }
 
-    fn handle_return(&mut self) -> Result<(), VMError> {
+    /// Leaves the subprogram called last, returning where it was called.
+    fn leave_subprogram(&mut self) -> Result<usize, VMError> {
         self.pop_scope()?;
-        self.pc = self.call_stack.pop().ok_or(VMError::StackUnderflow)?;
+        self.call_entries.pop();
+        self.call_stack.pop().ok_or(VMError::StackUnderflow)
+    }
+
+    fn handle_return(&mut self) -> Result<(), VMError> {
+        self.pc = self.leave_subprogram()?;
         Ok(())
     }
 
     fn free_addr(&mut self) -> Result<(), VMError> {
         let address = self.stack.pop().ok_or(VMError::StackUnderflow)?;
-        if let Value::Int(addr) = address {
-            self.heap.free(addr, false)?; // TODO
-            Ok(())
-        } else {
-            Err(VMError::InvalidAddress)
+        self.free_cell(&address)
+    }
+
+    fn free_cell(&mut self, address: &Value) -> Result<(), VMError> {
+        let address = address.extract_address()?;
+        self.sanitize(|sanitizer, pc| sanitizer.free(address, pc));
+        self.heap.free(address, false)?; // TODO
+        self.list_heads.retain(|&head| head != address);
+        Ok(())
+    }
+
+    /// `binary_op` for `+` and `-`, which may compute an address from
+    /// another one.
+    fn address_arithmetic<F>(&mut self, op: F) -> Result<(), VMError>
+    where
+        F: Fn(&Value, &Value) -> Result<Value, ValueError>,
+    {
+        let operand = match self.stack.as_slice() {
+            [.., lhs, rhs] => address_operand(lhs, rhs),
+            _ => None,
+        };
+        self.binary_op(op)?;
+        if let (Some(operand), Some(&Value::Address(result))) = (operand, self.stack.last()) {
+            self.sanitize(|sanitizer, pc| sanitizer.arithmetic(operand, result, pc));
         }
+        Ok(())
     }
 
     fn binary_op<F>(&mut self, op: F) -> Result<(), VMError>
@@ -332,22 +705,66 @@ impl VM {
         Ok(())
     }
 
-    fn is_truthy(&self, value: Value) -> Result<bool, VMError> {
+    fn is_truthy(&self, value: &Value) -> Result<bool, VMError> {
         match value {
-            Value::Bool(b) => Ok(b),
+            Value::Bool(b) => Ok(*b),
             _ => Err(VMError::InvalidOperation),
         }
     }
 }
 
+/// Tells whether the subprogram call at `offset` is the last thing its
+/// caller does: a return resumes past the `PopScope` following the call,
+/// and only labels and jumps lead from there to a `Return`.
+pub(crate) fn in_tail_position(bytecode: &[Bytecode], offset: usize) -> bool {
+    let mut offset = offset + 2;
+    // a jump cycle visits more instructions than there are
+    for _ in 0..bytecode.len() {
+        match bytecode.get(offset) {
+            Some(Bytecode::Label(_)) => offset += 1,
+            Some(Bytecode::Jump(target)) => offset = *target,
+            Some(Bytecode::Return) => return true,
+            _ => return false,
+        }
+    }
+    false
+}
+
+/// The label of the subprogram entered at `entry`: its header only sends
+/// null to the parameters after the label. Optimized bytecode has no labels.
+fn subprogram_name(bytecode: &[Bytecode], entry: usize) -> String {
+    bytecode[..entry.min(bytecode.len())]
+        .iter()
+        .rev()
+        .find_map(|instruction| match instruction {
+            Bytecode::Label(name) => Some(Some(name.clone())),
+            Bytecode::Constant(_)
+            | Bytecode::LoadSlot(_)
+            | Bytecode::LoadVar(_)
+            | Bytecode::Store => None,
+            _ => Some(None),
+        })
+        .flatten()
+        .unwrap_or_else(|| format!("<subprogram at {}>", entry))
+}
+
+/// The address `+` or `-` may compute another one from.
+fn address_operand(lhs: &Value, rhs: &Value) -> Option<i64> {
+    match (lhs, rhs) {
+        (Value::Address(address), _) | (_, Value::Address(address)) => Some(*address),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{heap::HeapGrowth, sanitizer::Block};
 
     #[test]
     fn test_vm_execution() {
         let bytecode = vec![
-            Bytecode::Constant(Value::new_int(5)),
+            Bytecode::Constant(Value::new_address(5)),
             Bytecode::BindAddr("x".to_string()),
             Bytecode::LoadVar("x".to_string()),
             Bytecode::Constant(Value::new_int(3)),
@@ -359,7 +776,7 @@ mod tests {
 
         let mut vm = VM::new(bytecode);
         assert!(vm.run().is_ok());
-        assert_eq!(vm.stack.pop().unwrap(), Value::new_int(8));
+        assert_eq!(vm.stack.pop().unwrap(), Value::new_address(8));
     }
 
     #[test]
@@ -368,7 +785,7 @@ mod tests {
 
         let mut vm = VM::new(bytecode);
         assert!(vm.run().is_ok());
-        assert_eq!(vm.stack.pop().unwrap(), Value::new_int(0));
+        assert_eq!(vm.stack.pop().unwrap(), Value::new_address(0));
     }
 
     #[test]
@@ -383,7 +800,7 @@ mod tests {
 
         let mut vm = VM::new(bytecode);
         assert!(vm.run().is_ok());
-        assert_eq!(vm.stack.pop().unwrap(), Value::new_int(0));
+        assert_eq!(vm.stack.pop().unwrap(), Value::new_address(0));
     }
 
     #[test]
@@ -391,7 +808,7 @@ mod tests {
         let bytecode = vec![
             Bytecode::Constant(Value::new_int(42)),
             Bytecode::StoreAddr,
-            Bytecode::Constant(Value::new_int(100)),
+            Bytecode::Constant(Value::new_address(100)),
             Bytecode::Deref,
             Bytecode::Halt,
         ];
@@ -400,4 +817,371 @@ mod tests {
         assert!(vm.run().is_ok());
         assert_eq!(vm.stack.pop().unwrap(), Value::Null);
     }
+
+    #[test]
+    fn test_int_is_not_an_address() {
+        let bytecode = vec![
+            Bytecode::Constant(Value::new_int(42)),
+            Bytecode::StoreAddr,
+            Bytecode::Constant(Value::new_int(0)),
+            Bytecode::Deref,
+            Bytecode::Halt,
+        ];
+        let mut vm = crate::new_vm(bytecode, &VmConfig::default());
+        assert!(matches!(vm.run(), Err(VMError::UnexpectedType(_))));
+
+        let bytecode = vec![
+            Bytecode::Constant(Value::new_int(42)),
+            Bytecode::StoreAddr,
+            Bytecode::Constant(Value::new_int(1)),
+            Bytecode::Sub,
+            Bytecode::CallBuiltin("ToInt".to_string(), 1),
+            Bytecode::CallBuiltin("ToAddress".to_string(), 1),
+            Bytecode::Constant(Value::new_int(1)),
+            Bytecode::Add,
+            Bytecode::Deref,
+            Bytecode::Halt,
+        ];
+        let mut vm = crate::new_vm(bytecode, &VmConfig::default());
+        assert!(vm.run().is_ok());
+        assert_eq!(vm.stack.pop().unwrap(), Value::new_int(42));
+    }
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let bytecode = vec![
+            Bytecode::Constant(Value::new_int(3)),
+            Bytecode::Constant(Value::new_float(0.5)),
+            Bytecode::Add,
+            Bytecode::Halt,
+        ];
+        let mut vm = VM::new(bytecode);
+        assert!(vm.run().is_ok());
+        assert_eq!(vm.stack.pop().unwrap(), Value::new_float(3.5));
+
+        let bytecode = vec![
+            Bytecode::Constant(Value::new_int(1)),
+            Bytecode::Constant(Value::new_int(0)),
+            Bytecode::Div,
+            Bytecode::Halt,
+        ];
+        let mut vm = VM::new(bytecode);
+        assert!(matches!(vm.run(), Err(VMError::DivisionByZero)));
+
+        let bytecode = vec![
+            Bytecode::Constant(Value::new_int(i64::MAX)),
+            Bytecode::Constant(Value::new_int(1)),
+            Bytecode::Add,
+            Bytecode::Halt,
+        ];
+        let mut vm = VM::new(bytecode);
+        assert!(matches!(vm.run(), Err(VMError::Overflow(_))));
+    }
+
+    #[test]
+    fn test_gc_builtin() {
+        let bytecode = vec![
+            Bytecode::Constant(Value::new_string("kept".to_string())),
+            Bytecode::StoreAddr,
+            Bytecode::StoreAddr,
+            Bytecode::BindAddr("p".to_string()),
+            Bytecode::Constant(Value::new_string("lost".to_string())),
+            Bytecode::StoreAddr,
+            Bytecode::Pop,
+            Bytecode::CallBuiltin("Gc".to_string(), 0),
+            Bytecode::Halt,
+        ];
+
+        let mut vm = crate::new_vm(bytecode, &VmConfig::default());
+        assert!(vm.run().is_ok());
+        assert_eq!(vm.stack.pop().unwrap(), Value::new_int(1));
+        // `p` is bound to cell 1, which points to cell 0
+        assert_eq!(vm.heap.lookup_address(1).unwrap(), Value::new_address(0));
+        assert!(vm.heap.lookup_address(0).is_ok());
+        assert!(vm.heap.lookup_address(2).is_err());
+    }
+
+    #[test]
+    fn test_gc_keeps_addresses_held_as_ints() {
+        let bytecode = vec![
+            Bytecode::Constant(Value::new_string("kept".to_string())),
+            Bytecode::StoreAddr,
+            Bytecode::CallBuiltin("ToInt".to_string(), 1),
+            Bytecode::LoadVar("n".to_string()),
+            Bytecode::Store,
+            Bytecode::CallBuiltin("Gc".to_string(), 0),
+            Bytecode::Halt,
+        ];
+
+        let mut vm = crate::new_vm(bytecode, &VmConfig::default());
+        assert!(vm.run().is_ok());
+        assert_eq!(vm.stack.pop().unwrap(), Value::new_int(0));
+        // `n` is bound to cell 1, which holds the number of cell 0
+        assert_eq!(vm.heap.lookup_address(1).unwrap(), Value::new_int(0));
+        assert_eq!(
+            vm.heap.lookup_address(0).unwrap(),
+            Value::new_string("kept".to_string())
+        );
+    }
+
+    #[test]
+    fn test_gc_keeps_whole_blocks() {
+        let bytecode = vec![
+            Bytecode::AllocMany(2),
+            Bytecode::Constant(Value::new_string("kept".to_string())),
+            Bytecode::Swap,
+            Bytecode::Store,
+            Bytecode::LoadVar("l".to_string()),
+            Bytecode::Store,
+            Bytecode::CallBuiltin("Gc".to_string(), 0),
+            Bytecode::Halt,
+        ];
+
+        let mut vm = crate::new_vm(bytecode, &VmConfig::default());
+        assert!(vm.run().is_ok());
+        assert_eq!(vm.stack.pop().unwrap(), Value::new_int(0));
+        // `l` holds only the address of cell 0, cell 1 is reached through it
+        assert_eq!(
+            vm.heap.lookup_address(1).unwrap(),
+            Value::new_string("kept".to_string())
+        );
+    }
+
+    #[test]
+    fn test_gc_on_allocation_pressure() {
+        // one more cell than the general partition holds
+        let mut bytecode: Vec<Bytecode> = (0..3001)
+            .flat_map(|_| [Bytecode::Alloc, Bytecode::Pop])
+            .collect();
+        bytecode.push(Bytecode::Halt);
+
+        let mut vm = VM::new(bytecode.clone());
+        assert!(matches!(
+            vm.run(),
+            Err(VMError::HeapEror(HeapError::PartitionLimitExceeded(_)))
+        ));
+
+        let mut vm = VM::new(bytecode);
+        vm.set_gc_enabled(true);
+        assert!(vm.run().is_ok());
+    }
+
+    #[test]
+    fn test_heap_growth() {
+        let bytecode: Vec<Bytecode> = (0..20).map(|_| Bytecode::Alloc).collect();
+        let config = VmConfig {
+            heap_size: 10,
+            growth: HeapGrowth::Doubling(40),
+            ..VmConfig::default()
+        };
+        let mut vm = VM::with_config(bytecode.clone(), &config);
+        assert!(vm.run().is_ok());
+        assert_eq!(vm.heap().size(), 40);
+
+        let config = VmConfig {
+            growth: HeapGrowth::Doubling(20),
+            ..config
+        };
+        let mut vm = VM::with_config(bytecode, &config);
+        match vm.run() {
+            Err(VMError::HeapEror(HeapError::PartitionLimitExceeded(usage))) => {
+                assert_eq!(usage.partition, Partition::General);
+                assert_eq!((usage.allocated, usage.limit), (15, 15));
+                assert_eq!(usage.heap_size, 20);
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_heap_snapshot() {
+        let bytecode = vec![
+            Bytecode::Constant(Value::new_int(5)),
+            Bytecode::StoreAddr,
+            Bytecode::StoreAddr,
+            Bytecode::BindAddr("p".to_string()),
+            // the cells pointing to cell 0, as a list
+            Bytecode::Constant(Value::new_address(0)),
+            Bytecode::Constant(Value::new_int(-1)),
+            Bytecode::MulDeref,
+            Bytecode::Halt,
+        ];
+
+        let mut vm = VM::new(bytecode);
+        assert!(vm.run().is_ok());
+        let snapshot = vm.heap_snapshot();
+        let addresses: Vec<i64> = snapshot.cells.iter().map(|cell| cell.address).collect();
+        assert_eq!(addresses, [0, 1, 1000, 1001]);
+        assert!(snapshot.cells[2].reserved && !snapshot.cells[1].reserved);
+        assert_eq!(snapshot.bindings[0].name, "p");
+        assert_eq!(snapshot.bindings[0].address, 1);
+        assert_eq!(snapshot.lists, [vec![1000]]);
+        let edges: Vec<(i64, i64)> = snapshot.edges.iter().map(|e| (e.from, e.to)).collect();
+        assert_eq!(edges, [(1, 0), (1001, 1)]);
+    }
+
+    #[test]
+    fn test_sanitizer() {
+        let bytecode = vec![
+            Bytecode::AllocMany(2),
+            Bytecode::Pop,
+            Bytecode::FreeAddr,
+            Bytecode::Constant(Value::new_address(0)),
+            Bytecode::Deref,
+            Bytecode::Pop,
+            Bytecode::Constant(Value::new_address(1)),
+            Bytecode::Constant(Value::new_int(1)),
+            Bytecode::Add,
+            Bytecode::Deref,
+            Bytecode::Halt,
+        ];
+
+        let mut vm = VM::new(bytecode.clone());
+        assert!(vm.run().is_ok());
+        assert!(vm.memory_violations().is_empty());
+
+        let mut vm = VM::new(bytecode);
+        vm.set_sanitizer_enabled(true);
+        assert!(vm.run().is_ok());
+        let block = Block {
+            start: 0,
+            len: 2,
+            pc: 0,
+        };
+        assert_eq!(
+            vm.memory_violations(),
+            [
+                MemoryViolation::UseAfterFree(4, Access::Read, 0, 2),
+                MemoryViolation::BlockEscape(9, Access::Read, 2, block),
+                MemoryViolation::Leak(0, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_exchange() {
+        let bytecode = vec![
+            Bytecode::Constant(Value::new_int(1)),
+            Bytecode::StoreAddr,
+            Bytecode::Constant(Value::new_int(2)),
+            Bytecode::StoreAddr,
+            Bytecode::Exchange,
+            Bytecode::Constant(Value::new_address(0)),
+            Bytecode::Deref,
+            Bytecode::Constant(Value::new_address(1)),
+            Bytecode::Deref,
+            Bytecode::Halt,
+        ];
+
+        let mut vm = VM::new(bytecode);
+        assert!(vm.run().is_ok());
+        assert_eq!(vm.stack.pop().unwrap(), Value::new_int(1));
+        assert_eq!(vm.stack.pop().unwrap(), Value::new_int(2));
+    }
+
+    #[test]
+    fn test_register_backend() {
+        let var = |name: &str| Bytecode::LoadVar(name.to_string());
+        let bytecode = vec![
+            Bytecode::Constant(Value::new_int(0)),
+            var("s"),
+            Bytecode::Store,
+            Bytecode::Constant(Value::new_int(5)),
+            var("i"),
+            Bytecode::Store,
+            Bytecode::Label("loop".to_string()),
+            var("i"),
+            Bytecode::Deref,
+            Bytecode::Constant(Value::new_int(0)),
+            Bytecode::Greater,
+            Bytecode::JumpIfFalse(27),
+            var("s"),
+            Bytecode::Deref,
+            var("i"),
+            Bytecode::Deref,
+            Bytecode::Add,
+            var("s"),
+            Bytecode::Store,
+            var("i"),
+            Bytecode::Dup,
+            Bytecode::Deref,
+            Bytecode::Constant(Value::new_int(1)),
+            Bytecode::Sub,
+            Bytecode::Swap,
+            Bytecode::Store,
+            Bytecode::Jump(6),
+            Bytecode::Halt,
+        ];
+
+        for backend in [Backend::Stack, Backend::Register] {
+            let mut vm = VM::new(bytecode.clone());
+            vm.set_backend(backend);
+            vm.set_sanitizer_enabled(true);
+            assert!(vm.run().is_ok());
+            assert!(vm.is_finished());
+            let sum = vm.scopes()[0].get_var("s").unwrap();
+            assert_eq!(vm.heap().lookup_address(sum).unwrap(), Value::new_int(15));
+            assert!(vm.memory_violations().is_empty());
+        }
+    }
+
+    /// Counts `n` up to `limit`, recursing after the increment and then
+    /// running `after` before returning.
+    fn counting_program(limit: i64, after: &str) -> Vec<Bytecode> {
+        let source_text = format!(
+            "0 => n\nSP count {{n}}\n!\n\ncount ... null => n\n\
+             P {{'n < {}}} | @done\n'n + 1 => n\nSP count {{n}}\n{}\ndone ...\nreturn\n",
+            limit, after
+        );
+        codegen::gen_bytecode(parser::parse(&source_text).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_tail_calls() {
+        for backend in [Backend::Stack, Backend::Register] {
+            let mut vm = VM::new(counting_program(50, ""));
+            vm.set_backend(backend);
+            vm.set_max_call_depth(10);
+            assert!(vm.run().is_ok());
+            assert!(vm.call_stack().is_empty());
+            assert_eq!(vm.scopes().len(), 1);
+            let n = vm.scopes()[0].get_var("n").unwrap();
+            assert_eq!(vm.heap().lookup_address(n).unwrap(), Value::new_int(50));
+        }
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        for backend in [Backend::Stack, Backend::Register] {
+            let mut vm = VM::new(counting_program(50, "Print {'n}"));
+            vm.set_backend(backend);
+            vm.set_output(io::sink());
+            vm.set_max_call_depth(10);
+            match vm.run() {
+                Err(VMError::StackOverflow(calls)) => assert_eq!(calls, vec!["count"; 11]),
+                result => panic!("expected a stack overflow, got {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_step() {
+        let bytecode = vec![
+            Bytecode::Constant(Value::new_address(7)),
+            Bytecode::BindAddr("x".to_string()),
+            Bytecode::Halt,
+            Bytecode::Alloc,
+        ];
+
+        let mut vm = VM::new(bytecode);
+        vm.step().unwrap();
+        assert_eq!(vm.pc(), 1);
+        assert_eq!(vm.stack(), &[Value::new_address(7)]);
+        vm.step().unwrap();
+        assert_eq!(vm.scopes()[0].variables(), vec![("x", 7)]);
+        assert!(!vm.is_finished());
+        vm.step().unwrap();
+        assert!(vm.is_finished());
+        assert!(vm.stack().is_empty());
+    }
 }
@@ -0,0 +1,121 @@
+use codegen::bytecode::Bytecode;
+use value::Value;
+
+use super::{address_operand, error::VMError, VM};
+use crate::register::{BinaryOp, Instruction, Operand, RegisterProgram};
+
+impl VM {
+    /// Runs `program` to the end. Instructions share the heap, scopes and
+    /// builtins with the stack backend, and `current_pc` tracks the
+    /// bytecode offset each was lowered from.
+    pub(super) fn run_registers(&mut self, program: &RegisterProgram) -> Result<(), VMError> {
+        self.registers = vec![Value::Null; program.registers];
+        let mut index = 0;
+        while let Some(instruction) = program.code.get(index) {
+            self.current_pc = program.origins[index];
+            index += 1;
+            match instruction {
+                Instruction::Binary(op, dst, lhs, rhs) => {
+                    let lhs = read(&self.registers, program, *lhs);
+                    let rhs = read(&self.registers, program, *rhs);
+                    let result = op.function()(lhs, rhs)?;
+                    if let (BinaryOp::Add | BinaryOp::Sub, Some(operand), Value::Address(address)) =
+                        (op, address_operand(lhs, rhs), &result)
+                    {
+                        let address = *address;
+                        self.sanitize(|sanitizer, pc| sanitizer.arithmetic(operand, address, pc));
+                    }
+                    self.registers[*dst] = result;
+                }
+                Instruction::Unary(op, dst, operand) => {
+                    let operand = read(&self.registers, program, *operand);
+                    self.registers[*dst] = op.function()(operand)?;
+                }
+                Instruction::LoadSlot(dst, slot) => {
+                    self.registers[*dst] = Value::Address(self.load_slot(*slot)?);
+                }
+                Instruction::StoreSlot(slot) => self.set_var(*slot)?,
+                Instruction::BindSlot(slot, address) => {
+                    let address = self.operand(program, *address);
+                    self.bind_slot(*slot, &address)?;
+                }
+                Instruction::Deref(dst, address) => {
+                    let address = self.operand(program, *address);
+                    self.registers[*dst] = self.load(&address)?;
+                }
+                Instruction::MulDeref(dst, address, n) => {
+                    let address = self.operand(program, *address);
+                    let n = self.operand(program, *n);
+                    self.registers[*dst] = self.load_many(address, &n)?;
+                }
+                Instruction::Store(value, address) => {
+                    let value = self.operand(program, *value);
+                    let address = self.operand(program, *address);
+                    self.store_value(value, &address)?;
+                }
+                Instruction::StoreAddr(dst, value) => {
+                    let address = self.allocate_cell(false)?;
+                    let value = self.operand(program, *value);
+                    self.heap.store(address, value)?;
+                    self.registers[*dst] = Value::new_address(address);
+                }
+                Instruction::Alloc(dst) => {
+                    self.registers[*dst] = Value::new_address(self.allocate_cell(false)?);
+                }
+                Instruction::AllocMany(dsts) => {
+                    let addresses = self.allocate_cells(dsts.len(), false)?;
+                    for (dst, address) in dsts.iter().zip(addresses) {
+                        self.registers[*dst] = Value::new_address(address);
+                    }
+                }
+                Instruction::FreeAddr(address) => {
+                    let address = self.operand(program, *address);
+                    self.free_cell(&address)?;
+                }
+                Instruction::Exchange(lhs, rhs) => {
+                    let lhs = self.operand(program, *lhs);
+                    let rhs = self.operand(program, *rhs);
+                    self.exchange_cells(&lhs, &rhs)?;
+                }
+                Instruction::Jump(target) => index = *target,
+                Instruction::JumpIfFalse(condition, target) => {
+                    if !self.is_truthy(read(&self.registers, program, *condition))? {
+                        index = *target;
+                    }
+                }
+                Instruction::CallBuiltin(dst, name, args) => {
+                    let args = args.iter().map(|arg| self.operand(program, *arg)).collect();
+                    self.registers[*dst] = self.invoke_builtin(name, args)?;
+                }
+                Instruction::CallSubProgram(entry, continuation) => {
+                    // frames name the subprogram by its bytecode entry
+                    let Bytecode::CallSubProgram(label, _) = self.bytecode[self.current_pc] else {
+                        unreachable!("a call is lowered from a call");
+                    };
+                    self.enter_subprogram(label, *continuation)?;
+                    index = *entry;
+                }
+                Instruction::Return => index = self.leave_subprogram()?,
+                Instruction::Halt => {
+                    self.halted = true;
+                    break;
+                }
+                Instruction::PushScope => self.push_scope(),
+                Instruction::PopScope => self.pop_scope()?,
+            }
+        }
+        self.pc = self.bytecode.len();
+        Ok(())
+    }
+
+    fn operand(&self, program: &RegisterProgram, operand: Operand) -> Value {
+        read(&self.registers, program, operand).clone()
+    }
+}
+
+fn read<'a>(registers: &'a [Value], program: &'a RegisterProgram, operand: Operand) -> &'a Value {
+    match operand {
+        Operand::Register(register) => &registers[register],
+        Operand::Constant(constant) => &program.constants[constant],
+    }
+}
@@ -0,0 +1,21 @@
+const path = require("path");
+const { LanguageClient } = require("vscode-languageclient/node");
+
+let client;
+
+function activate(context) {
+  const command = context.asAbsolutePath(path.join("bin", "adl-lsp"));
+  client = new LanguageClient(
+    "adl",
+    "Address Language",
+    { command },
+    { documentSelector: [{ language: "adl" }] }
+  );
+  client.start();
+}
+
+function deactivate() {
+  return client && client.stop();
+}
+
+module.exports = { activate, deactivate };